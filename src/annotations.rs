@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// ===========================
+// 使用者對摘要的手動註記
+// ===========================
+
+// 註記與 LLM 摘要的合併方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnotationMode {
+    // 以註記取代 LLM 摘要
+    Replace,
+    // 註記放在 LLM 摘要之前
+    Prepend,
+    // 註記放在 LLM 摘要之後
+    #[default]
+    Append,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    pub annotation: String,
+    pub mode: AnnotationMode,
+}
+
+impl Annotation {
+    // 依合併方式將註記套用到 LLM 摘要（沒有摘要時只回傳註記）
+    pub fn apply(&self, summary: Option<&str>) -> String {
+        let note = self.annotation.trim();
+        match (self.mode, summary.map(str::trim).filter(|s| !s.is_empty())) {
+            (AnnotationMode::Replace, _) | (_, None) => note.to_string(),
+            (AnnotationMode::Prepend, Some(summary)) => format!("{}\n\n{}", note, summary),
+            (AnnotationMode::Append, Some(summary)) => format!("{}\n\n{}", summary, note),
+        }
+    }
+}
+
+// 將所有註記合併進摘要，回傳新的檔案路徑 -> 摘要對照表
+pub fn merge_annotations(
+    summaries: &BTreeMap<String, String>,
+    annotations: &BTreeMap<String, Annotation>,
+) -> BTreeMap<String, String> {
+    let mut merged = summaries.clone();
+    for (path, annotation) in annotations {
+        let summary = annotation.apply(summaries.get(path).map(String::as_str));
+        merged.insert(path.clone(), summary);
+    }
+    merged
+}
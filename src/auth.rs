@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use subtle::ConstantTimeEq;
+use warp::reject::Reject;
+use warp::{Filter, Rejection};
+
+use crate::error::QprError;
+
+// ===========================
+// API token 驗證
+// ===========================
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl Reject for Unauthorized {}
+
+// 驗證 `Authorization: Bearer <token>`，未設定 token 時一律放行
+pub fn require_auth(token: Option<Arc<str>>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let token = token.clone();
+            async move {
+                let Some(token) = token else {
+                    return Ok(());
+                };
+                let provided = header
+                    .as_deref()
+                    .and_then(|h| h.strip_prefix("Bearer "))
+                    .unwrap_or("");
+                // 以固定時間比較，避免從回應時間推測 token
+                if bool::from(provided.as_bytes().ct_eq(token.as_bytes())) {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(Unauthorized))
+                }
+            }
+        })
+        .untuple_one()
+}
+
+// 將驗證失敗轉為 401 `{"error": "unauthorized"}`，其餘拒絕維持 warp 的預設處理
+pub async fn handle_rejection(err: Rejection) -> Result<warp::reply::Response, Rejection> {
+    if err.find::<Unauthorized>().is_some() {
+        return Ok(QprError::Unauthorized.into_response());
+    }
+    Err(err)
+}
@@ -0,0 +1,112 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::QprError;
+use crate::quality::SummaryQuality;
+use crate::{relative_path, Directory, FileInfo};
+
+// ===========================
+// 與上次報告比較（--baseline）
+// ===========================
+
+// 檔案相對於基準報告的狀態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeStatus {
+    New,
+    Modified,
+    Deleted,
+    Unchanged,
+}
+
+// 相對於專案根目錄的檔案路徑 -> 基準報告中的內容雜湊（報告未記錄雜湊時為 None）
+pub type BaselineHashes = HashMap<String, Option<String>>;
+
+// 讀取 /api/export/json 匯出的報告，以相對路徑為鍵，專案搬移或重新 clone 後仍可比對
+pub fn load_baseline(path: &Path) -> Result<BaselineHashes, QprError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| QprError::ConfigError(format!("無法讀取基準報告 {}：{}", path.display(), e)))?;
+    let report: Directory = serde_json::from_str(&content)
+        .map_err(|e| QprError::ConfigError(format!("基準報告 {} 不是有效的 JSON 匯出檔：{}", path.display(), e)))?;
+    report.validate().map_err(|e| {
+        QprError::ConfigError(format!(
+            "基準報告 {} 的目錄樹已損毀：{}；請刪除此檔案或重新以 /api/export/json 匯出",
+            path.display(),
+            e
+        ))
+    })?;
+
+    let mut hashes = BaselineHashes::new();
+    report.collect_baseline_hashes(Path::new(&report.path), &mut hashes);
+    let missing = hashes.values().filter(|hash| hash.is_none()).count();
+    if missing > 0 {
+        tracing::warn!("基準報告中有 {} 個檔案沒有內容雜湊，這些檔案將一律標記為已修改", missing);
+    }
+    Ok(hashes)
+}
+
+impl Directory {
+    fn collect_baseline_hashes(&self, root: &Path, hashes: &mut BaselineHashes) {
+        for file in &self.files {
+            if file.change_status == Some(ChangeStatus::Deleted) {
+                continue;
+            }
+            let file_path = Path::new(&self.path).join(&file.name);
+            hashes.insert(relative_path(root, &file_path), file.content_hash.clone());
+        }
+        for subdir in &self.subdirs {
+            subdir.collect_baseline_hashes(root, hashes);
+        }
+    }
+
+    // 依基準報告標記每個檔案的狀態；基準中有、但磁碟上已不存在的檔案以 Deleted 項目加入所在目錄
+    pub fn apply_baseline(&mut self, root: &Path, baseline: &BaselineHashes) {
+        let mut seen = HashSet::new();
+        self.mark_change_status(root, baseline, &mut seen);
+
+        let mut deleted: Vec<&String> = baseline.keys().filter(|path| !seen.contains(*path)).collect();
+        deleted.sort();
+        for relative in deleted {
+            let file_path = root.join(relative);
+            if file_path.exists() {
+                // 仍存在但不在本次選定的資料夾中
+                continue;
+            }
+            let (Some(dir), Some(name)) = (self.parent_dir_mut(&file_path), file_path.file_name()) else {
+                continue;
+            };
+            dir.files.push(FileInfo {
+                name: name.to_string_lossy().to_string(),
+                summary: None,
+                content_hash: None,
+                size_bytes: 0,
+                change_status: Some(ChangeStatus::Deleted),
+                encoding_lossy: false,
+                language: None,
+                quality: SummaryQuality::Ok,
+                modified_at: None,
+            });
+            dir.files.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+    }
+
+    fn mark_change_status(&mut self, root: &Path, baseline: &BaselineHashes, seen: &mut HashSet<String>) {
+        // 先移除上次標記的已刪除項目
+        self.files.retain(|file| file.change_status != Some(ChangeStatus::Deleted));
+        for file in &mut self.files {
+            let relative = relative_path(root, &Path::new(&self.path).join(&file.name));
+            file.change_status = Some(match baseline.get(&relative) {
+                None => ChangeStatus::New,
+                Some(Some(hash)) if file.content_hash.as_ref() == Some(hash) => ChangeStatus::Unchanged,
+                Some(_) => ChangeStatus::Modified,
+            });
+            seen.insert(relative);
+        }
+        for subdir in &mut self.subdirs {
+            subdir.mark_change_status(root, baseline, seen);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::annotations::Annotation;
+use crate::error::QprError;
+
+// ===========================
+// 摘要快取檔案
+// ===========================
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SummaryCache {
+    // 檔案路徑 -> 摘要
+    pub summaries: BTreeMap<String, String>,
+    // 檔案路徑 -> 生成摘要時的內容雜湊（舊版快取沒有此欄位）
+    #[serde(default)]
+    pub hashes: BTreeMap<String, String>,
+    // 檔案路徑 -> 使用者的手動註記（POST /api/annotate）
+    #[serde(default)]
+    pub annotations: BTreeMap<String, Annotation>,
+    // 檔案路徑 -> 超過 summary_max_length 而被截斷的摘要原文
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub full_summaries: BTreeMap<String, String>,
+}
+
+// 與快取比對後的檔案狀態
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheStatus {
+    // 內容未變動，可直接使用快取的摘要
+    Unchanged(String),
+    // 有快取的摘要，但內容雜湊不同
+    Changed,
+    // 快取中沒有此檔案
+    New,
+}
+
+// 以十六進位表示的 SHA-256 內容雜湊
+pub fn content_hash(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+impl SummaryCache {
+    // 讀取快取檔案，不存在或格式錯誤時回傳空快取
+    pub fn load(path: &Path) -> Self {
+        let Ok(content) = fs::read_to_string(path) else {
+            return SummaryCache::default();
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            tracing::warn!("快取檔案 {} 格式錯誤，將忽略：{}", path.display(), e);
+            SummaryCache::default()
+        })
+    }
+
+    // 比對快取中的雜湊；content_hash 為 None（未啟用雜湊）時只要有摘要就視為未變動
+    pub fn lookup(&self, path: &str, content_hash: Option<&str>) -> CacheStatus {
+        let Some(summary) = self.summaries.get(path) else {
+            return CacheStatus::New;
+        };
+        match content_hash {
+            Some(hash) if self.hashes.get(path).map(String::as_str) != Some(hash) => CacheStatus::Changed,
+            _ => CacheStatus::Unchanged(summary.clone()),
+        }
+    }
+
+    // 移除單一檔案的摘要與雜湊（註記保留），回傳是否有移除
+    pub fn remove(&mut self, path: &str) -> bool {
+        self.hashes.remove(path);
+        self.full_summaries.remove(path);
+        self.summaries.remove(path).is_some()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), QprError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
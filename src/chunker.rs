@@ -0,0 +1,64 @@
+// ===========================
+// 依函式／類別邊界切割檔案
+// ===========================
+
+// 從行數上限往回尋找函式邊界的最大行數
+const BOUNDARY_SEARCH_LINES: usize = 50;
+
+pub struct SmartChunker;
+
+impl SmartChunker {
+    // 以整行為單位，將內容切成每段最多 max_tokens 個 token 的片段（單行超過上限時該行自成一段）；
+    // 支援的語言會在上限前 50 行內找最近的函式或類別開頭切割，找不到或其他語言則切在超過上限的那一行
+    pub fn chunk(content: &str, extension: &str, max_tokens: usize, count_tokens: impl Fn(&str) -> usize) -> Vec<String> {
+        let extension = extension.to_lowercase();
+        let lines: Vec<&str> = content.lines().collect();
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < lines.len() {
+            // 第一個放不下的行（每行另計一個換行的 token）
+            let mut limit = start;
+            let mut tokens = 0;
+            while limit < lines.len() {
+                tokens += count_tokens(lines[limit]) + 1;
+                if tokens > max_tokens && limit > start {
+                    break;
+                }
+                limit += 1;
+            }
+            if limit >= lines.len() {
+                chunks.push(lines[start..].join("\n"));
+                break;
+            }
+            // 邊界行作為下一段的第一行，因此最遠可以是 limit
+            let earliest = limit.saturating_sub(BOUNDARY_SEARCH_LINES).max(start + 1);
+            let end = (earliest..=limit)
+                .rev()
+                .find(|&i| is_boundary(&extension, lines[i]))
+                .unwrap_or(limit);
+            chunks.push(lines[start..end].join("\n"));
+            start = end;
+        }
+        chunks
+    }
+}
+
+// 是否為頂層函式、impl 或類別的開頭
+fn is_boundary(extension: &str, line: &str) -> bool {
+    match extension {
+        "rs" => {
+            let rest = ["pub ", "async ", "unsafe "]
+                .iter()
+                .find_map(|prefix| line.strip_prefix(prefix))
+                .unwrap_or(line);
+            rest.starts_with("fn ") || line.starts_with("impl ")
+        }
+        "py" => line.starts_with("def ") || line.starts_with("class "),
+        "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => {
+            let rest = line.strip_prefix("export ").unwrap_or(line);
+            let rest = rest.strip_prefix("async ").unwrap_or(rest);
+            rest.starts_with("function ") || line.starts_with("class ")
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,277 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use std::net::IpAddr;
+
+use crate::config::{SortOrder, SummaryLanguage};
+use crate::export::ExportFormat;
+use crate::llm::BackendKind;
+use crate::CONFIG_FILE;
+
+// ===========================
+// 命令列參數
+// ===========================
+
+#[derive(Parser, Debug, Clone)]
+#[command(name = "qpr", about = "Quick Project Report：以 LLM 分析專案資料夾並生成程式碼摘要")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// 設定檔路徑
+    #[arg(long, default_value = CONFIG_FILE)]
+    pub config: String,
+
+    /// 要分析的專案目錄，可重複指定以在同一個伺服器中分析多個專案
+    #[arg(long)]
+    pub path: Vec<String>,
+
+    /// 要分析的 Git 儲存庫網址，會以 `git clone --depth=1` 複製到暫存目錄
+    #[arg(long, conflicts_with = "path")]
+    pub git_url: Option<String>,
+
+    /// 複製私有儲存庫用的存取 token（未指定時讀取 GIT_TOKEN 環境變數）
+    #[arg(long, requires = "git_url")]
+    pub git_token: Option<String>,
+
+    /// 網頁伺服器埠號
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// 網頁伺服器綁定的 IP 位址（預設 127.0.0.1），在 Docker 或 VM 中可使用 0.0.0.0
+    #[arg(long, value_name = "IP")]
+    pub bind_addr: Option<IpAddr>,
+
+    /// HTTPS 憑證檔（PEM），需與 --tls-key 一起指定
+    #[arg(long, value_name = "PATH")]
+    pub tls_cert: Option<String>,
+
+    /// HTTPS 私鑰檔（PEM），需與 --tls-cert 一起指定
+    #[arg(long, value_name = "PATH")]
+    pub tls_key: Option<String>,
+
+    /// OpenAI、Claude 或 Mistral 的 API 金鑰，優先於 OPENAI_API_KEY／ANTHROPIC_API_KEY／MISTRAL_API_KEY、設定檔與 ~/.config/qpr/credentials
+    #[arg(long, value_name = "KEY")]
+    pub api_key: Option<String>,
+
+    /// 網頁伺服器的 API token，設定後除了首頁以外的路由都需要 `Authorization: Bearer <token>`
+    #[arg(long, value_name = "SECRET")]
+    pub api_token: Option<String>,
+
+    /// 同時進行中的 LLM 摘要請求數量上限
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub concurrency: Option<u32>,
+
+    /// 個別後端的並行請求數上限，例如 `openai=8,llama=2`，優先於 --concurrency
+    #[arg(long, value_name = "BACKEND=N", value_delimiter = ',', value_parser = parse_backend_concurrency)]
+    pub concurrency_per_backend: Vec<(BackendKind, u32)>,
+
+    /// 已讀取內容、等待 API 額度的檔案數上限，超過時暫停讀取檔案（預設與 --concurrency 相同）
+    #[arg(long)]
+    pub max_queue_depth: Option<usize>,
+
+    /// LLM 後端
+    #[arg(long, value_enum)]
+    pub backend: Option<BackendKind>,
+
+    /// LLM API 網址
+    #[arg(long)]
+    pub llm_url: Option<String>,
+
+    /// 模型名稱
+    #[arg(long)]
+    pub model: Option<String>,
+
+    /// OpenAI 後端分析資料夾時使用的模型（例如較便宜的 gpt-4o-mini），預設與 --model 相同
+    #[arg(long)]
+    pub folder_analysis_model: Option<String>,
+
+    /// Claude 後端使用的模型，預設 claude-3-haiku-20240307
+    #[arg(long)]
+    pub claude_model: Option<String>,
+
+    /// Mistral 後端使用的模型，預設 mistral-small-latest
+    #[arg(long)]
+    pub mistral_model: Option<String>,
+
+    /// Azure OpenAI 資源網址（例如 https://<resource>.openai.azure.com），需與 --azure-deployment 一起指定
+    #[arg(long)]
+    pub azure_endpoint: Option<String>,
+
+    /// Azure OpenAI 部署名稱
+    #[arg(long)]
+    pub azure_deployment: Option<String>,
+
+    /// Azure OpenAI API 版本，預設 2024-06-01
+    #[arg(long)]
+    pub azure_api_version: Option<String>,
+
+    /// API 暫時性錯誤（429、500、503）的最大重試次數
+    #[arg(long)]
+    pub max_retries: Option<u8>,
+
+    /// 摘要快取檔案
+    #[arg(long)]
+    pub cache_file: Option<String>,
+
+    /// 摘要輸出語言（zh-TW、zh-CN、en、ja、ko），預設 zh-TW
+    #[arg(long)]
+    pub language: Option<SummaryLanguage>,
+
+    /// 專案說明檔（相對於目前工作目錄），內容加在每個檔案摘要與資料夾分析提示語的開頭
+    #[arg(long, value_name = "FILE")]
+    pub extra_context: Option<String>,
+
+    /// 專案總覽輸入的 token 上限
+    #[arg(long)]
+    pub rollup_max_tokens: Option<usize>,
+
+    /// 單一 LLM 請求的提示語 token 上限，超過時將檔案分段摘要
+    #[arg(long)]
+    pub max_tokens_per_request: Option<usize>,
+
+    /// 模型的 context window（tokens），每段程式碼最多使用其中的 60%，預設 llama 為 4096，其他後端為 16384
+    #[arg(long)]
+    pub model_context_window: Option<usize>,
+
+    /// 每 1K tokens 的價格（美元），用於 --dry-run 的費用估算
+    #[arg(long)]
+    pub price_per_1k_tokens: Option<f64>,
+
+    /// 超過此大小（bytes）的檔案不生成摘要，預設 102400（100 KB）
+    #[arg(long, value_name = "BYTES")]
+    pub max_file_size: Option<u64>,
+
+    /// 儲存的檔案摘要字元數上限，超過時在句子邊界截斷，預設 500
+    #[arg(long, value_name = "CHARS")]
+    pub summary_max_length: Option<usize>,
+
+    /// 累計使用的 token 數達到 N 後停止送出摘要請求，其餘檔案標示為 [BUDGET EXCEEDED]
+    #[arg(long, value_name = "N")]
+    pub token_budget: Option<u64>,
+
+    /// 掃描目錄的最大深度（根目錄為 0，預設 20），超過此深度的子目錄不會被收集
+    #[arg(long, value_name = "N")]
+    pub max_depth: Option<usize>,
+
+    /// 目錄樹的排列順序：alphabetical（名稱，預設）、size（大小由大到小）或 mtime（最近修改的在前）
+    #[arg(long, value_enum, value_name = "ORDER")]
+    pub sort_by: Option<SortOrder>,
+
+    /// 不分析的副檔名，以逗號分隔（例如 `md,resx`），與 [extensions] 的 exclude 合併
+    #[arg(long, value_name = "EXT", value_delimiter = ',')]
+    pub ignore_extensions: Vec<String>,
+
+    /// 不以 LLM 過濾資料夾，直接摘要所有非隱藏資料夾並立即開始（適合無法互動輸入的 CI）
+    #[arg(long)]
+    pub skip_folders_analysis: bool,
+
+    /// 搭配 --skip-folders-analysis：只摘要這些資料夾（相對於專案目錄，以逗號分隔）
+    #[arg(long, value_name = "DIRS", value_delimiter = ',')]
+    pub include_dirs: Vec<String>,
+
+    /// 搭配 --skip-folders-analysis：不摘要這些資料夾及其子目錄（以逗號分隔）
+    #[arg(long, value_name = "DIRS", value_delimiter = ',')]
+    pub exclude_dirs: Vec<String>,
+
+    /// 摘要完成後監看選定的資料夾，檔案新增、修改或刪除時更新摘要（僅網頁伺服器模式）
+    #[arg(long)]
+    pub watch: bool,
+
+    /// 略過測試檔案（例如 *_test.go、test_*.py、*.spec.ts、開頭為 #[cfg(test)] 的 Rust 檔案）
+    #[arg(long, conflicts_with = "include_tests")]
+    pub exclude_tests: bool,
+
+    /// 摘要測試檔案，覆寫設定檔中的 exclude_tests = true
+    #[arg(long)]
+    pub include_tests: bool,
+
+    /// 摘要完成後在終端機以 tree 格式輸出目錄樹與各檔案的摘要
+    #[arg(long)]
+    pub print_tree: bool,
+
+    /// 每個檔案摘要完成後，依原始檔案的相對路徑寫入 <DIR>/<path>.qpr_summary（目錄不存在時自動建立）
+    #[arg(long, value_name = "DIR", conflicts_with = "sidecar")]
+    pub output_dir: Option<String>,
+
+    /// 將 .qpr_summary 寫在原始檔案旁邊，等同 --output-dir <專案目錄>
+    #[arg(long)]
+    pub sidecar: bool,
+
+    /// 只掃描目錄並估算 API 請求數與費用，不呼叫任何 LLM API
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// 啟動時不檢查 LLM 後端是否可連線（離線或測試時使用）
+    #[arg(long)]
+    pub skip_health_check: bool,
+
+    /// 不顯示摘要進度條，改為每完成一個檔案輸出一行日誌（標準錯誤輸出不是終端機時自動停用）
+    #[arg(long)]
+    pub no_progress_bar: bool,
+
+    /// 上次以 /api/export/json 匯出的報告，用於標記新增、修改與刪除的檔案
+    #[arg(long, value_name = "REPORT_JSON")]
+    pub baseline: Option<String>,
+
+    /// 摘要完成後將報告匯出為 Markdown 檔案並結束，不啟動網頁伺服器
+    #[arg(long, value_name = "FILE", conflicts_with = "export_format")]
+    pub export_markdown: Option<String>,
+
+    /// 摘要完成後輸出指定格式的報告、印出檔案路徑並結束，不啟動網頁伺服器
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub export_format: Option<ExportFormat>,
+
+    /// --export-format 的輸出檔案，預設為目前目錄下的 qpr_report.json／.html／.md／.csv
+    #[arg(long, value_name = "FILE", requires = "export_format")]
+    pub export_output: Option<String>,
+
+    /// 不啟動網頁伺服器：在命令列完成分析與摘要（搭配 --export-format 時輸出報告）後結束；
+    /// 標準輸出不是終端機（例如 CI）時預設啟用
+    #[arg(long, conflicts_with = "server")]
+    pub no_server: bool,
+
+    /// 標準輸出不是終端機時仍啟動網頁伺服器
+    #[arg(long)]
+    pub server: bool,
+
+    /// 保存每個檔案最近一次摘要的 LLM 原始回應，並開放 GET /api/raw-llm-response/<path> 檢視
+    #[arg(long)]
+    pub debug_llm: bool,
+
+    /// 輸出除錯層級的日誌（等同 RUST_LOG=debug）
+    #[arg(long)]
+    pub verbose: bool,
+
+    /// 另外將結構化的 JSON 日誌寫入此檔案，標準輸出仍維持易讀格式
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<String>,
+
+    /// 額外載入的環境變數檔，優先於 .env.local、.env 與專案目錄的 .env
+    #[arg(long, value_name = "PATH")]
+    pub env_file: Option<String>,
+}
+
+// 解析 `--concurrency-per-backend` 的 `BACKEND=N`
+fn parse_backend_concurrency(value: &str) -> Result<(BackendKind, u32), String> {
+    let (backend, concurrency) = value
+        .split_once('=')
+        .ok_or_else(|| format!("格式應為 BACKEND=N：{}", value))?;
+    let backend = BackendKind::from_str(backend.trim(), true)?;
+    let concurrency: u32 = concurrency
+        .trim()
+        .parse()
+        .map_err(|_| format!("並行數必須是正整數：{}", concurrency))?;
+    if concurrency == 0 {
+        return Err("並行數必須大於 0".to_string());
+    }
+    Ok((backend, concurrency))
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// 產生預設的設定檔
+    Init {
+        /// 覆寫已存在的設定檔
+        #[arg(long)]
+        force: bool,
+    },
+}
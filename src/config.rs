@@ -0,0 +1,974 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::path::{Path, PathBuf};
+
+use crate::cli::Cli;
+use crate::error::QprError;
+use crate::extensions::Language;
+use crate::llm::BackendKind;
+use crate::{
+    DEFAULT_AZURE_API_VERSION, DEFAULT_BIND_ADDR, DEFAULT_CACHE_FILE, DEFAULT_CLAUDE_MODEL, DEFAULT_CONNECT_TIMEOUT_MS,
+    DEFAULT_CONTEXT_WINDOW, DEFAULT_LLAMA_CONTEXT_WINDOW, DEFAULT_LLAMA_REQUEST_TIMEOUT_MS, DEFAULT_MAX_DEPTH, DEFAULT_PER_FILE_TOTAL_TIMEOUT_MS, DEFAULT_REQUEST_TIMEOUT_MS, DEFAULT_CLAUDE_URL, DEFAULT_LLAMA_URL, DEFAULT_MISTRAL_MODEL, DEFAULT_MISTRAL_URL, DEFAULT_MODEL, DEFAULT_OPENAI_URL,
+    DEFAULT_SUMMARY_MAX_LENGTH, ENV_FILE, ENV_LOCAL_FILE, FILE_SUMMARY_PROMPT, FOLDER_ANALYSIS_PROMPT, LLAMA_FILE_ROLLUP_PROMPT, LLAMA_FILE_SUMMARY_PROMPT,
+    FILE_ROLLUP_PROMPT, KNOWN_MISTRAL_MODELS, KNOWN_OPENAI_MODELS, LLAMA_FOLDER_ANALYSIS_PROMPT, PROJECT_PATH, SERVER_PORT,
+};
+
+// ===========================
+// 執行期設定（qpr.toml）
+// ===========================
+
+// 預設的模型價格表（每 1K 輸入 tokens，美元）
+const DEFAULT_PRICING: &[(&str, f64)] = &[
+    ("gpt-3.5-turbo", 0.0005),
+    ("gpt-4", 0.03),
+    ("gpt-4-turbo", 0.01),
+    ("gpt-4o", 0.0025),
+    ("gpt-4o-mini", 0.00015),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QprConfig {
+    pub project_path: String,
+    // [[projects]]：同時分析的多個專案，設定後取代 project_path
+    pub projects: Vec<ProjectConfig>,
+    pub server_port: u16,
+    // 網頁伺服器綁定的位址，在 Docker 或 VM 中可設為 0.0.0.0
+    pub bind_addr: IpAddr,
+    pub concurrency: u32,
+    // 已讀取內容、等待 API 額度的檔案數上限，未設定時與 concurrency 相同
+    pub max_queue_depth: Option<usize>,
+    pub backend: BackendKind,
+    // 未設定時依後端使用預設網址
+    pub llm_url: Option<String>,
+    pub model: String,
+    // OpenAI 後端分析資料夾時使用的模型，未設定時與 model 相同
+    pub folder_analysis_model: Option<String>,
+    // claude 後端使用的模型，未設定時為 claude-3-haiku-20240307
+    pub claude_model: Option<String>,
+    // mistral 後端使用的模型，未設定時為 mistral-small-latest
+    pub mistral_model: Option<String>,
+    // Azure OpenAI 資源網址與部署名稱，設定後 OpenAI 後端改用 Azure 的網址與驗證方式
+    pub azure_endpoint: Option<String>,
+    pub azure_deployment: Option<String>,
+    pub azure_api_version: Option<String>,
+    // mock 後端第 N 次呼叫（從 1 起算）回傳 HTTP 503，用於驗證重試
+    pub mock_fail_on_call: Option<usize>,
+    // [mock_responses]：mock 後端摘要這些檔案（完整路徑或相對路徑）時回傳指定的內容
+    pub mock_responses: HashMap<String, String>,
+    // mock 後端每次呼叫等待的毫秒數，模擬 API 的延遲
+    pub mock_delay_ms: u64,
+    pub max_retries: u8,
+    pub cache_file: String,
+    pub language: SummaryLanguage,
+    // 未設定時依後端使用內建提示語
+    pub prompt_folder_analysis: Option<String>,
+    pub prompt_file_summary: Option<String>,
+    // 專案說明（或 --extra-context 檔案的內容），加在檔案摘要與資料夾分析提示語的開頭，讓模型理解專案的專有名詞
+    pub project_context: Option<String>,
+    // 專案總覽輸入的 token 上限，超過時只取每份摘要的第一句
+    pub rollup_max_tokens: usize,
+    // 單一 LLM 請求的提示語 token 上限，超過時將檔案分段摘要
+    pub max_tokens_per_request: usize,
+    // 模型的 context window（tokens），未設定時 llama 後端為 4096，其他後端為 16384
+    pub model_context_window: Option<usize>,
+    // 每 1K tokens 的價格（美元），未設定時依 pricing 表查詢目前的模型
+    pub price_per_1k_tokens: Option<f64>,
+    // 模型名稱 -> 每 1K tokens 的價格（美元），用於 --dry-run 的費用估算
+    pub pricing: HashMap<String, f64>,
+    pub extensions: ExtensionsConfig,
+    // [prompts]：依檔案語言加在檔案摘要提示語前的說明
+    pub prompts: PromptsConfig,
+    // 各後端自己的並行請求數上限，未設定的後端使用 concurrency
+    pub concurrency_per_backend: BackendConcurrencyConfig,
+    // 掃描時計算檔案的 SHA-256，用於判斷快取的摘要是否仍有效
+    pub hash_files: bool,
+    // 超過此大小（bytes）的檔案不送出摘要請求
+    pub max_file_size: u64,
+    // 儲存的檔案摘要字元數上限，超過時在句子邊界截斷
+    pub summary_max_length: usize,
+    // 累計使用的 token 數達到此值後不再送出摘要請求，未設定時不限制
+    pub token_budget: Option<u64>,
+    // 建立連線的逾時（毫秒）
+    pub connect_timeout_ms: u64,
+    // 單一 LLM 請求的逾時（毫秒），未設定時 llama 後端為 300000，其他後端為 120000
+    pub request_timeout_ms: Option<u64>,
+    // 單一檔案所有分段、重試與合併請求的總逾時（毫秒），逾時的檔案以 [TIMEOUT] 標記
+    pub per_file_total_timeout_ms: u64,
+    // 掃描目錄的最大深度（根目錄為 0），超過的子目錄不會被掃描
+    pub max_depth: usize,
+    // 目錄樹中同一層的子目錄與檔案的排列順序
+    pub sort_by: SortOrder,
+    // 略過測試檔案（依檔名，Rust 檔案另檢查開頭的 #[cfg(test)]）
+    pub exclude_tests: bool,
+    // 不以 LLM 過濾資料夾，選定所有非隱藏資料夾後直接開始摘要
+    pub skip_folders_analysis: bool,
+    // skip_folders_analysis 時只選定這些資料夾（相對於專案目錄），未設定時為整個專案
+    pub include_dirs: Vec<String>,
+    // skip_folders_analysis 時不摘要的資料夾，包含其子目錄
+    pub exclude_dirs: Vec<String>,
+    // 摘要完成後監看選定的資料夾，檔案變動時重新生成摘要
+    pub watch: bool,
+    // 摘要完成後在終端機輸出附上摘要的目錄樹
+    pub print_tree: bool,
+    // 每個檔案摘要完成後寫入 <相對路徑>.qpr_summary 的目錄，未設定時只保留在記憶體與快取中
+    pub output_dir: Option<String>,
+    // 將 .qpr_summary 寫在原始檔案旁邊，等同 output_dir 設為專案目錄
+    pub sidecar: bool,
+    // OpenAI、Claude 或 Mistral 的 API 金鑰，優先順序低於 --api-key 與 OPENAI_API_KEY／ANTHROPIC_API_KEY／MISTRAL_API_KEY 環境變數
+    pub api_key: Option<String>,
+    // 命令列 --api-key 的值，不寫入設定檔
+    #[serde(skip)]
+    pub cli_api_key: Option<String>,
+    // 命令列 --debug-llm：保存 LLM 原始回應並開放 /api/raw-llm-response，不能由設定檔啟用
+    #[serde(skip)]
+    pub debug_llm: bool,
+    // 設定後除了首頁以外的路由都需要 `Authorization: Bearer <token>`
+    pub api_token: Option<String>,
+}
+
+// [[projects]] 中的一個專案
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectConfig {
+    pub path: String,
+}
+
+// 目錄樹中同一層的子目錄與檔案的排列順序（--sort-by）；大小或修改時間相同時依名稱排序
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+pub enum SortOrder {
+    // 依名稱（逐位元組比較、區分大小寫）
+    #[default]
+    #[serde(rename = "alphabetical")]
+    Alphabetical,
+    // 依大小由大到小，子目錄以其下所有檔案的大小總和比較
+    #[serde(rename = "size")]
+    #[value(name = "size")]
+    BySize,
+    // 依修改時間由新到舊，子目錄以其下最近修改的檔案比較
+    #[serde(rename = "mtime")]
+    #[value(name = "mtime")]
+    ByModTime,
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SortOrder::Alphabetical => "alphabetical",
+            SortOrder::BySize => "size",
+            SortOrder::ByModTime => "mtime",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// 摘要輸出語言，設定檔與命令列使用語言代碼（例如 zh-TW）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SummaryLanguage {
+    #[default]
+    ZhTw,
+    ZhCn,
+    En,
+    Ja,
+    Ko,
+}
+
+impl SummaryLanguage {
+    // 放入提示語 {language} 佔位符的語言名稱
+    pub fn prompt_name(&self) -> &'static str {
+        match self {
+            SummaryLanguage::ZhTw => "繁體中文",
+            SummaryLanguage::ZhCn => "简体中文",
+            SummaryLanguage::En => "English",
+            SummaryLanguage::Ja => "日本語",
+            SummaryLanguage::Ko => "한국어",
+        }
+    }
+}
+
+impl fmt::Display for SummaryLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            SummaryLanguage::ZhTw => "zh-TW",
+            SummaryLanguage::ZhCn => "zh-CN",
+            SummaryLanguage::En => "en",
+            SummaryLanguage::Ja => "ja",
+            SummaryLanguage::Ko => "ko",
+        };
+        write!(f, "{}", code)
+    }
+}
+
+impl FromStr for SummaryLanguage {
+    type Err = String;
+
+    // 接受語言代碼（不分大小寫，`_` 與 `-` 皆可），也接受舊設定檔中的語言名稱
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('_', "-").as_str() {
+            "zh-tw" | "zh-hant" | "繁體中文" => Ok(SummaryLanguage::ZhTw),
+            "zh-cn" | "zh-hans" | "简体中文" | "簡體中文" => Ok(SummaryLanguage::ZhCn),
+            "en" | "en-us" | "english" => Ok(SummaryLanguage::En),
+            "ja" | "ja-jp" | "日本語" => Ok(SummaryLanguage::Ja),
+            "ko" | "ko-kr" | "한국어" => Ok(SummaryLanguage::Ko),
+            _ => Err(format!("不支援的語言：{}（可用：zh-TW、zh-CN、en、ja、ko）", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for SummaryLanguage {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<SummaryLanguage> for String {
+    fn from(language: SummaryLanguage) -> Self {
+        language.to_string()
+    }
+}
+
+// [extensions]：調整哪些檔案視為程式碼檔案
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtensionsConfig {
+    // 加入內建清單的副檔名
+    pub additional: Vec<String>,
+    // 從清單中移除的副檔名
+    pub exclude: Vec<String>,
+    // 依檔名比對的 glob 規則，可用於沒有副檔名的檔案
+    pub include_filenames: Vec<String>,
+}
+
+// [prompts]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptsConfig {
+    // [prompts.by_language]：語言名稱（rust、python、typescript 等，未內建的語言為副檔名）-> 提示語前綴
+    pub by_language: HashMap<String, String>,
+}
+
+// [concurrency_per_backend]：例如 OpenAI 可以多個請求並行，本機的 llama.cpp 只適合 1～2 個
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackendConcurrencyConfig {
+    pub openai_concurrency: Option<u32>,
+    pub llama_concurrency: Option<u32>,
+    pub claude_concurrency: Option<u32>,
+    pub mistral_concurrency: Option<u32>,
+}
+
+impl BackendConcurrencyConfig {
+    fn get(&self, backend: BackendKind) -> Option<u32> {
+        match backend {
+            BackendKind::OpenAi => self.openai_concurrency,
+            BackendKind::Llama => self.llama_concurrency,
+            BackendKind::Claude => self.claude_concurrency,
+            BackendKind::Mistral => self.mistral_concurrency,
+            BackendKind::Mock => None,
+        }
+    }
+
+    fn set(&mut self, backend: BackendKind, concurrency: u32) {
+        match backend {
+            BackendKind::OpenAi => self.openai_concurrency = Some(concurrency),
+            BackendKind::Llama => self.llama_concurrency = Some(concurrency),
+            BackendKind::Claude => self.claude_concurrency = Some(concurrency),
+            BackendKind::Mistral => self.mistral_concurrency = Some(concurrency),
+            BackendKind::Mock => {}
+        }
+    }
+}
+
+impl Default for QprConfig {
+    fn default() -> Self {
+        QprConfig {
+            project_path: PROJECT_PATH.to_string(),
+            projects: Vec::new(),
+            server_port: SERVER_PORT,
+            bind_addr: DEFAULT_BIND_ADDR,
+            concurrency: 4,
+            max_queue_depth: None,
+            backend: BackendKind::OpenAi,
+            llm_url: None,
+            model: DEFAULT_MODEL.to_string(),
+            folder_analysis_model: None,
+            claude_model: None,
+            mistral_model: None,
+            azure_endpoint: None,
+            azure_deployment: None,
+            azure_api_version: None,
+            mock_fail_on_call: None,
+            mock_responses: HashMap::new(),
+            mock_delay_ms: 0,
+            max_retries: 3,
+            cache_file: DEFAULT_CACHE_FILE.to_string(),
+            language: SummaryLanguage::default(),
+            prompt_folder_analysis: None,
+            prompt_file_summary: None,
+            project_context: None,
+            rollup_max_tokens: 3000,
+            max_tokens_per_request: 3000,
+            model_context_window: None,
+            price_per_1k_tokens: None,
+            pricing: DEFAULT_PRICING
+                .iter()
+                .map(|(model, price)| (model.to_string(), *price))
+                .collect(),
+            extensions: ExtensionsConfig::default(),
+            prompts: PromptsConfig::default(),
+            concurrency_per_backend: BackendConcurrencyConfig::default(),
+            hash_files: true,
+            max_file_size: 100 * 1024,
+            summary_max_length: DEFAULT_SUMMARY_MAX_LENGTH,
+            token_budget: None,
+            connect_timeout_ms: DEFAULT_CONNECT_TIMEOUT_MS,
+            request_timeout_ms: None,
+            per_file_total_timeout_ms: DEFAULT_PER_FILE_TOTAL_TIMEOUT_MS,
+            max_depth: DEFAULT_MAX_DEPTH,
+            sort_by: SortOrder::default(),
+            exclude_tests: false,
+            skip_folders_analysis: false,
+            include_dirs: Vec::new(),
+            exclude_dirs: Vec::new(),
+            watch: false,
+            print_tree: false,
+            output_dir: None,
+            sidecar: false,
+            api_key: None,
+            cli_api_key: None,
+            debug_llm: false,
+            api_token: None,
+        }
+    }
+}
+
+impl QprConfig {
+    // 讀取設定檔，檔案不存在時使用預設值
+    pub fn load(path: &Path) -> Result<Self, QprError> {
+        match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content)
+                .map_err(|e| QprError::ConfigError(format!("無法解析設定檔 {}：{}", path.display(), e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(QprConfig::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // 以命令列參數覆寫設定檔的值
+    pub fn apply_cli(&mut self, cli: &Cli) {
+        if !cli.path.is_empty() {
+            self.projects = cli
+                .path
+                .iter()
+                .map(|path| ProjectConfig { path: path.clone() })
+                .collect();
+        }
+        if let Some(port) = cli.port {
+            self.server_port = port;
+        }
+        if let Some(bind_addr) = cli.bind_addr {
+            self.bind_addr = bind_addr;
+        }
+        if let Some(concurrency) = cli.concurrency {
+            self.concurrency = concurrency;
+        }
+        for (backend, concurrency) in &cli.concurrency_per_backend {
+            self.concurrency_per_backend.set(*backend, *concurrency);
+        }
+        if let Some(max_queue_depth) = cli.max_queue_depth {
+            self.max_queue_depth = Some(max_queue_depth);
+        }
+        if let Some(backend) = cli.backend {
+            self.backend = backend;
+        }
+        if let Some(llm_url) = &cli.llm_url {
+            self.llm_url = Some(llm_url.clone());
+        }
+        if let Some(model) = &cli.model {
+            self.model = model.clone();
+        }
+        if let Some(folder_analysis_model) = &cli.folder_analysis_model {
+            self.folder_analysis_model = Some(folder_analysis_model.clone());
+        }
+        if let Some(claude_model) = &cli.claude_model {
+            self.claude_model = Some(claude_model.clone());
+        }
+        if let Some(mistral_model) = &cli.mistral_model {
+            self.mistral_model = Some(mistral_model.clone());
+        }
+        if let Some(azure_endpoint) = &cli.azure_endpoint {
+            self.azure_endpoint = Some(azure_endpoint.clone());
+        }
+        if let Some(azure_deployment) = &cli.azure_deployment {
+            self.azure_deployment = Some(azure_deployment.clone());
+        }
+        if let Some(azure_api_version) = &cli.azure_api_version {
+            self.azure_api_version = Some(azure_api_version.clone());
+        }
+        if let Some(max_retries) = cli.max_retries {
+            self.max_retries = max_retries;
+        }
+        if let Some(cache_file) = &cli.cache_file {
+            self.cache_file = cache_file.clone();
+        }
+        if let Some(language) = cli.language {
+            self.language = language;
+        }
+        if let Some(rollup_max_tokens) = cli.rollup_max_tokens {
+            self.rollup_max_tokens = rollup_max_tokens;
+        }
+        if let Some(max_tokens_per_request) = cli.max_tokens_per_request {
+            self.max_tokens_per_request = max_tokens_per_request;
+        }
+        if let Some(model_context_window) = cli.model_context_window {
+            self.model_context_window = Some(model_context_window);
+        }
+        if let Some(price) = cli.price_per_1k_tokens {
+            self.price_per_1k_tokens = Some(price);
+        }
+        if let Some(max_file_size) = cli.max_file_size {
+            self.max_file_size = max_file_size;
+        }
+        if let Some(summary_max_length) = cli.summary_max_length {
+            self.summary_max_length = summary_max_length;
+        }
+        if let Some(token_budget) = cli.token_budget {
+            self.token_budget = Some(token_budget);
+        }
+        if let Some(max_depth) = cli.max_depth {
+            self.max_depth = max_depth;
+        }
+        if let Some(sort_by) = cli.sort_by {
+            self.sort_by = sort_by;
+        }
+        self.extensions.exclude.extend(cli.ignore_extensions.iter().cloned());
+        if cli.exclude_tests {
+            self.exclude_tests = true;
+        }
+        if cli.include_tests {
+            self.exclude_tests = false;
+        }
+        if cli.skip_folders_analysis {
+            self.skip_folders_analysis = true;
+        }
+        if !cli.include_dirs.is_empty() {
+            self.include_dirs = cli.include_dirs.clone();
+        }
+        if !cli.exclude_dirs.is_empty() {
+            self.exclude_dirs = cli.exclude_dirs.clone();
+        }
+        if cli.watch {
+            self.watch = true;
+        }
+        if cli.print_tree {
+            self.print_tree = true;
+        }
+        if let Some(output_dir) = &cli.output_dir {
+            self.output_dir = Some(output_dir.clone());
+            self.sidecar = false;
+        }
+        if cli.sidecar {
+            self.sidecar = true;
+        }
+        if let Some(api_key) = &cli.api_key {
+            self.cli_api_key = Some(api_key.clone());
+        }
+        if let Some(api_token) = &cli.api_token {
+            self.api_token = Some(api_token.clone());
+        }
+        if cli.debug_llm {
+            self.debug_llm = true;
+        }
+    }
+
+    // 檢查設定值是否合理
+    pub fn validate(&self) -> Result<(), QprError> {
+        let error = |message: &str| Err(QprError::ConfigError(message.to_string()));
+        if self.concurrency == 0 {
+            return error("concurrency 必須大於 0");
+        }
+        let per_backend = &self.concurrency_per_backend;
+        if [
+            per_backend.openai_concurrency,
+            per_backend.llama_concurrency,
+            per_backend.claude_concurrency,
+            per_backend.mistral_concurrency,
+        ]
+        .contains(&Some(0))
+        {
+            return error("concurrency_per_backend 的並行數必須大於 0");
+        }
+        if self.rollup_max_tokens == 0 {
+            return error("rollup_max_tokens 必須大於 0");
+        }
+        if self.max_tokens_per_request == 0 {
+            return error("max_tokens_per_request 必須大於 0");
+        }
+        if self.model_context_window == Some(0) {
+            return error("model_context_window 必須大於 0");
+        }
+        if self.summary_max_length == 0 {
+            return error("summary_max_length 必須大於 0");
+        }
+        if self.token_budget == Some(0) {
+            return error("token_budget 必須大於 0");
+        }
+        let manual_dirs = !self.include_dirs.is_empty() || !self.exclude_dirs.is_empty();
+        if manual_dirs && !self.skip_folders_analysis {
+            return error("include_dirs 與 exclude_dirs 需搭配 skip_folders_analysis（--skip-folders-analysis）使用");
+        }
+        if self.connect_timeout_ms == 0 || self.request_timeout_ms == Some(0) || self.per_file_total_timeout_ms == 0 {
+            return error("connect_timeout_ms、request_timeout_ms 與 per_file_total_timeout_ms 必須大於 0");
+        }
+        if self.price_per_1k_tokens.is_some_and(|p| p < 0.0) {
+            return error("price_per_1k_tokens 不可為負數");
+        }
+        match (&self.azure_endpoint, &self.azure_deployment) {
+            (Some(_), None) => return error("使用 Azure OpenAI 時需同時指定 azure_deployment"),
+            (None, Some(_)) => return error("使用 Azure OpenAI 時需同時指定 azure_endpoint"),
+            (None, None) if self.azure_api_version.is_some() => {
+                return error("azure_api_version 需搭配 azure_endpoint 與 azure_deployment 使用")
+            }
+            (Some(_), Some(_)) if self.backend != BackendKind::OpenAi => {
+                return error("Azure OpenAI 僅適用於 openai 後端")
+            }
+            _ => {}
+        }
+        if self.api_token.as_deref().is_some_and(|t| t.trim().is_empty()) {
+            return error("api_token 不可為空字串");
+        }
+        if self.projects.iter().any(|project| project.path.trim().is_empty()) {
+            return error("[[projects]] 的 path 不可為空字串");
+        }
+        Ok(())
+    }
+
+    // 要分析的所有專案目錄；未設定 [[projects]] 時只有 project_path
+    pub fn project_paths(&self) -> Vec<String> {
+        if self.projects.is_empty() {
+            return vec![self.project_path.clone()];
+        }
+        self.projects.iter().map(|project| project.path.clone()).collect()
+    }
+
+    // 摘要側檔 (.qpr_summary) 的輸出目錄；sidecar 優先於 output_dir
+    pub fn summary_output_dir(&self) -> Option<PathBuf> {
+        if self.sidecar {
+            return Some(PathBuf::from(&self.project_path));
+        }
+        self.output_dir.as_ref().map(PathBuf::from)
+    }
+
+    // 目前後端的並行請求數上限
+    pub fn backend_concurrency(&self) -> u32 {
+        self.concurrency_per_backend
+            .get(self.backend)
+            .unwrap_or(self.concurrency)
+    }
+
+    pub fn llm_url(&self) -> String {
+        if let Some(url) = self.azure_url() {
+            return url;
+        }
+        self.llm_url.clone().unwrap_or_else(|| match self.backend {
+            BackendKind::OpenAi => DEFAULT_OPENAI_URL.to_string(),
+            BackendKind::Llama => DEFAULT_LLAMA_URL.to_string(),
+            BackendKind::Claude => DEFAULT_CLAUDE_URL.to_string(),
+            BackendKind::Mistral => DEFAULT_MISTRAL_URL.to_string(),
+            // mock 後端不連線
+            BackendKind::Mock => String::new(),
+        })
+    }
+
+    // 目前後端實際使用的模型名稱
+    pub fn model_name(&self) -> String {
+        match self.backend {
+            BackendKind::Claude => self
+                .claude_model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_CLAUDE_MODEL.to_string()),
+            BackendKind::Mistral => self
+                .mistral_model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MISTRAL_MODEL.to_string()),
+            BackendKind::OpenAi | BackendKind::Llama | BackendKind::Mock => self.model.clone(),
+        }
+    }
+
+    // 分析資料夾時使用的模型：只有 OpenAI 後端會套用 folder_analysis_model
+    pub fn folder_analysis_model_name(&self) -> String {
+        match (self.backend, &self.folder_analysis_model) {
+            (BackendKind::OpenAi, Some(model)) => model.clone(),
+            _ => self.model_name(),
+        }
+    }
+
+    // OpenAI 與 Mistral 後端的模型不在已知清單時發出警告（Azure 以部署名稱決定模型，不檢查）
+    pub fn warn_unknown_models(&self) {
+        if self.backend == BackendKind::Mistral {
+            let model = self.model_name();
+            if !KNOWN_MISTRAL_MODELS.contains(&model.as_str()) {
+                tracing::warn!("未知的 Mistral 模型：{}（已知：{}）", model, KNOWN_MISTRAL_MODELS.join("、"));
+            }
+            return;
+        }
+        if self.backend != BackendKind::OpenAi || self.azure_url().is_some() {
+            return;
+        }
+        for model in std::iter::once(&self.model).chain(&self.folder_analysis_model) {
+            if !KNOWN_OPENAI_MODELS.contains(&model.as_str()) {
+                tracing::warn!("未知的 OpenAI 模型：{}（已知：{}）", model, KNOWN_OPENAI_MODELS.join("、"));
+            }
+        }
+    }
+
+    // Azure OpenAI 的 chat completions 網址，未設定 Azure 時回傳 None
+    pub fn azure_url(&self) -> Option<String> {
+        let endpoint = self.azure_endpoint.as_deref()?;
+        let deployment = self.azure_deployment.as_deref()?;
+        Some(format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            endpoint.trim_end_matches('/'),
+            deployment,
+            self.azure_api_version.as_deref().unwrap_or(DEFAULT_AZURE_API_VERSION)
+        ))
+    }
+
+    pub fn file_summary_prompt(&self) -> String {
+        self.prompt_file_summary.clone().unwrap_or_else(|| match self.backend {
+            BackendKind::OpenAi | BackendKind::Claude | BackendKind::Mistral | BackendKind::Mock => FILE_SUMMARY_PROMPT.to_string(),
+            BackendKind::Llama => LLAMA_FILE_SUMMARY_PROMPT.to_string(),
+        })
+    }
+
+    // 檔案摘要的提示語範本：[prompts.by_language] 有此語言的前綴時加在範本前；
+    // prompt_override 為目錄的 .qpr_prompt，沒有前綴時原樣回傳
+    pub fn file_summary_template(&self, language: &Language, prompt_override: Option<String>) -> Option<String> {
+        match self.prompts.by_language.get(language.name()) {
+            Some(prefix) => Some(format!(
+                "{}\n{}",
+                prefix,
+                prompt_override.unwrap_or_else(|| self.file_summary_prompt())
+            )),
+            None => prompt_override,
+        }
+    }
+
+    pub fn folder_analysis_prompt(&self) -> String {
+        self.prompt_folder_analysis.clone().unwrap_or_else(|| match self.backend {
+            BackendKind::OpenAi | BackendKind::Claude | BackendKind::Mistral | BackendKind::Mock => FOLDER_ANALYSIS_PROMPT.to_string(),
+            BackendKind::Llama => LLAMA_FOLDER_ANALYSIS_PROMPT.to_string(),
+        })
+    }
+
+    // 目前模型每 1K tokens 的價格，查不到時回傳 None
+    pub fn price_per_1k_tokens(&self) -> Option<f64> {
+        self.price_per_1k_tokens
+            .or_else(|| self.pricing.get(&self.model_name()).copied())
+    }
+
+    // 模型的 context window（tokens）
+    pub fn model_context_window(&self) -> usize {
+        self.model_context_window.unwrap_or(match self.backend {
+            BackendKind::Llama => DEFAULT_LLAMA_CONTEXT_WINDOW,
+            BackendKind::OpenAi | BackendKind::Claude | BackendKind::Mistral | BackendKind::Mock => DEFAULT_CONTEXT_WINDOW,
+        })
+    }
+
+    // 每段程式碼可用的 token 數：context window 的 60%，其餘留給提示語與模型輸出
+    pub fn chunk_tokens(&self) -> usize {
+        self.model_context_window() * 6 / 10
+    }
+
+    // 單一 LLM 請求的逾時（毫秒）
+    pub fn request_timeout_ms(&self) -> u64 {
+        self.request_timeout_ms.unwrap_or(match self.backend {
+            BackendKind::Llama => DEFAULT_LLAMA_REQUEST_TIMEOUT_MS,
+            BackendKind::OpenAi | BackendKind::Claude | BackendKind::Mistral | BackendKind::Mock => DEFAULT_REQUEST_TIMEOUT_MS,
+        })
+    }
+
+    // 檔案分段摘要後合併用的提示語
+    pub fn file_rollup_prompt(&self) -> String {
+        match self.backend {
+            BackendKind::OpenAi | BackendKind::Claude | BackendKind::Mistral | BackendKind::Mock => FILE_ROLLUP_PROMPT.to_string(),
+            BackendKind::Llama => LLAMA_FILE_ROLLUP_PROMPT.to_string(),
+        }
+    }
+}
+
+// 使用者層級的金鑰檔：~/.config/qpr/credentials
+fn credentials_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".config").join("qpr").join("credentials"))
+}
+
+// 依序從 --api-key、環境變數、qpr.toml 的 api_key、~/.config/qpr/credentials 取得 API 金鑰；
+// 環境變數依後端為 OPENAI_API_KEY、ANTHROPIC_API_KEY 或 MISTRAL_API_KEY
+pub fn resolve_api_key(config: &QprConfig) -> Result<String, QprError> {
+    let env_var = match config.backend {
+        BackendKind::Claude => "ANTHROPIC_API_KEY",
+        BackendKind::Mistral => "MISTRAL_API_KEY",
+        BackendKind::OpenAi | BackendKind::Llama | BackendKind::Mock => "OPENAI_API_KEY",
+    };
+    let non_empty = |key: &str| {
+        let key = key.trim();
+        (!key.is_empty()).then(|| key.to_string())
+    };
+
+    if let Some(key) = config.cli_api_key.as_deref().and_then(non_empty) {
+        return Ok(key);
+    }
+    if let Some(key) = env::var(env_var).ok().as_deref().and_then(non_empty) {
+        return Ok(key);
+    }
+    if let Some(key) = config.api_key.as_deref().and_then(non_empty) {
+        return Ok(key);
+    }
+    let credentials = credentials_path();
+    if let Some(path) = &credentials {
+        // 取第一個非空白、非 # 註解的行
+        if let Ok(content) = fs::read_to_string(path) {
+            let key = content
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty() && !line.starts_with('#'));
+            if let Some(key) = key {
+                return Ok(key.to_string());
+            }
+        }
+    }
+
+    // 只列出檢查過的位置，不輸出任何金鑰內容
+    let credentials = credentials
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "~/.config/qpr/credentials（找不到家目錄）".to_string());
+    Err(QprError::ConfigError(format!(
+        "找不到 {} 後端的 API 金鑰，已檢查：--api-key 參數、{} 環境變數（含 .env）、設定檔的 api_key 欄位、{}",
+        config.backend, env_var, credentials
+    )))
+}
+
+// 讀取 --extra-context 指定的專案說明檔（相對於目前工作目錄），內容為空時回傳 None
+pub fn read_project_context(path: &Path) -> Result<Option<String>, QprError> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        QprError::ConfigError(format!("無法讀取 --extra-context {}：{}", path.display(), e))
+    })?;
+    let content = content.trim();
+    Ok((!content.is_empty()).then(|| content.to_string()))
+}
+
+// 載入環境變數檔，優先順序由高到低為 --env-file、.env.local、目前目錄（或其上層）的 .env、專案目錄的 .env；
+// dotenv 不會覆寫已存在的變數，因此由優先順序高的檔案開始載入，實際的環境變數永遠優先
+pub fn load_env_chain(project_path: &Path, env_file: Option<&Path>) -> Result<(), QprError> {
+    if let Some(env_file) = env_file {
+        dotenv::from_path(env_file).map_err(|e| {
+            QprError::ConfigError(format!("無法讀取 --env-file {}：{}", env_file.display(), e))
+        })?;
+        tracing::debug!("已載入環境變數檔：{}", env_file.display());
+    }
+
+    let load = |path: &Path| {
+        if !path.is_file() {
+            tracing::debug!("找不到環境變數檔：{}", path.display());
+            return;
+        }
+        match dotenv::from_path(path) {
+            Ok(()) => tracing::debug!("已載入環境變數檔：{}", path.display()),
+            Err(e) => tracing::warn!("無法讀取環境變數檔 {}：{}", path.display(), e),
+        }
+    };
+    load(Path::new(ENV_LOCAL_FILE));
+    // 與先前相同，.env 會往上層目錄尋找
+    match dotenv::dotenv() {
+        Ok(path) => tracing::debug!("已載入環境變數檔：{}", path.display()),
+        Err(e) if e.not_found() => tracing::debug!("找不到環境變數檔：{}", ENV_FILE),
+        Err(e) => tracing::warn!("無法讀取環境變數檔 {}：{}", ENV_FILE, e),
+    }
+    load(&project_path.join(ENV_FILE));
+    Ok(())
+}
+
+// 產生所有欄位皆為註解的預設設定檔內容（供 `qpr init` 使用）
+pub fn default_config_template() -> String {
+    let defaults = QprConfig::default();
+    // 以單行字串輸出，確保整個值都在同一行註解內
+    let quote = |s: &str| {
+        format!(
+            "\"{}\"",
+            s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+        )
+    };
+
+    let entries = [
+        ("要分析的專案目錄", format!("project_path = {}", quote(&defaults.project_path))),
+        ("網頁伺服器埠號", format!("server_port = {}", defaults.server_port)),
+        (
+            "網頁伺服器綁定的位址，設為 0.0.0.0 時請一併設定 api_token",
+            format!("bind_addr = \"{}\"", defaults.bind_addr),
+        ),
+        ("同時進行中的 LLM 請求數量上限", format!("concurrency = {}", defaults.concurrency)),
+        (
+            "已讀取內容、等待 API 額度的檔案數上限，未設定時與 concurrency 相同",
+            "max_queue_depth = 4".to_string(),
+        ),
+        ("LLM 後端：openai、llama、claude 或 mistral", format!("backend = {}", quote(&defaults.backend.to_string()))),
+        (
+            "LLM API 網址，未設定時依後端使用預設值",
+            format!("llm_url = {}", quote(DEFAULT_OPENAI_URL)),
+        ),
+        ("模型名稱", format!("model = {}", quote(&defaults.model))),
+        ("OpenAI 後端分析資料夾時使用的模型，未設定時與 model 相同", format!("folder_analysis_model = {}", quote("gpt-4o-mini"))),
+        ("claude 後端使用的模型", format!("claude_model = {}", quote(DEFAULT_CLAUDE_MODEL))),
+        (
+            "mistral 後端使用的模型：mistral-small-latest、mistral-medium-latest、mistral-large-latest 或 open-mistral-7b",
+            format!("mistral_model = {}", quote(DEFAULT_MISTRAL_MODEL)),
+        ),
+        (
+            "Azure OpenAI 資源網址，需與 azure_deployment 一起設定",
+            format!("azure_endpoint = {}", quote("https://<resource>.openai.azure.com")),
+        ),
+        ("Azure OpenAI 部署名稱", format!("azure_deployment = {}", quote("gpt-35-turbo"))),
+        ("Azure OpenAI API 版本", format!("azure_api_version = {}", quote(DEFAULT_AZURE_API_VERSION))),
+        ("API 暫時性錯誤的最大重試次數", format!("max_retries = {}", defaults.max_retries)),
+        ("摘要快取檔案", format!("cache_file = {}", quote(&defaults.cache_file))),
+        (
+            "摘要輸出語言：zh-TW、zh-CN、en、ja、ko",
+            format!("language = {}", quote(&defaults.language.to_string())),
+        ),
+        (
+            "資料夾分析提示語（{folders}、{extra_folders}、{language} 為佔位符）",
+            format!("prompt_folder_analysis = {}", quote(FOLDER_ANALYSIS_PROMPT)),
+        ),
+        (
+            "檔案摘要提示語（{} 為程式碼內容，{language} 為輸出語言）",
+            format!("prompt_file_summary = {}", quote(FILE_SUMMARY_PROMPT)),
+        ),
+        (
+            "一兩段專案說明，加在檔案摘要與資料夾分析提示語的開頭；--extra-context 指定的檔案優先",
+            format!("project_context = {}", quote("Ghost 是以 Node.js 撰寫的開源 CMS，posts 與 members 為其核心資料模型。")),
+        ),
+        (
+            "專案總覽輸入的 token 上限，超過時只取每份摘要的第一句",
+            format!("rollup_max_tokens = {}", defaults.rollup_max_tokens),
+        ),
+        (
+            "單一 LLM 請求的提示語 token 上限，超過時將檔案分段摘要",
+            format!("max_tokens_per_request = {}", defaults.max_tokens_per_request),
+        ),
+        (
+            "模型的 context window（tokens），每段程式碼最多使用其中的 60%；未設定時 llama 後端為 4096，其他後端為 16384",
+            format!("model_context_window = {}", DEFAULT_CONTEXT_WINDOW),
+        ),
+        (
+            "每 1K tokens 的價格（美元），未設定時依下方 pricing 表查詢",
+            "price_per_1k_tokens = 0.0005".to_string(),
+        ),
+        (
+            "計算檔案內容的 SHA-256，內容變動時才重新生成摘要",
+            format!("hash_files = {}", defaults.hash_files),
+        ),
+        (
+            "超過此大小（bytes）的檔案不生成摘要",
+            format!("max_file_size = {}", defaults.max_file_size),
+        ),
+        (
+            "檔案摘要的字元數上限，超過時在句子邊界截斷（原文可由 /api/file-summary 取得）",
+            format!("summary_max_length = {}", defaults.summary_max_length),
+        ),
+        (
+            "累計使用的 token 數達到此值後不再送出摘要請求，其餘檔案標示為 [BUDGET EXCEEDED]",
+            "token_budget = 1000000".to_string(),
+        ),
+        ("建立連線的逾時（毫秒）", format!("connect_timeout_ms = {}", defaults.connect_timeout_ms)),
+        (
+            "單一 LLM 請求的逾時（毫秒），未設定時 llama 後端為 300000，其他後端為 120000",
+            format!("request_timeout_ms = {}", DEFAULT_REQUEST_TIMEOUT_MS),
+        ),
+        (
+            "單一檔案所有分段、重試與合併請求的總逾時（毫秒），逾時的檔案摘要標示為 [TIMEOUT]",
+            format!("per_file_total_timeout_ms = {}", defaults.per_file_total_timeout_ms),
+        ),
+        (
+            "掃描目錄的最大深度（根目錄為 0），超過的子目錄會在目錄樹中標示 [MAX DEPTH REACHED]",
+            format!("max_depth = {}", defaults.max_depth),
+        ),
+        (
+            "目錄樹的排列順序：alphabetical（名稱）、size（大小，由大到小）或 mtime（修改時間，由新到舊）",
+            format!("sort_by = \"{}\"", defaults.sort_by),
+        ),
+        (
+            "略過測試檔案（*_test.go、test_*.py、*.spec.ts、*_spec.rs 等）",
+            format!("exclude_tests = {}", defaults.exclude_tests),
+        ),
+        (
+            "不以 LLM 過濾資料夾，選定所有非隱藏資料夾後直接開始摘要（include_dirs、exclude_dirs 需搭配此設定）",
+            format!("skip_folders_analysis = {}", defaults.skip_folders_analysis),
+        ),
+        ("只摘要這些資料夾（相對於專案目錄）", "include_dirs = [\"src\", \"lib\"]".to_string()),
+        ("不摘要這些資料夾及其子目錄", "exclude_dirs = [\"src/generated\"]".to_string()),
+        ("摘要完成後監看選定的資料夾，檔案變動時重新生成摘要", format!("watch = {}", defaults.watch)),
+        ("摘要完成後在終端機輸出附上摘要的目錄樹", format!("print_tree = {}", defaults.print_tree)),
+        (
+            "每個檔案摘要完成後寫入 <output_dir>/<相對路徑>.qpr_summary，目錄不存在時自動建立",
+            format!("output_dir = {}", quote("qpr_summaries")),
+        ),
+        ("將 .qpr_summary 寫在原始檔案旁邊（等同 output_dir 設為專案目錄）", format!("sidecar = {}", defaults.sidecar)),
+        (
+            "OpenAI、Claude 或 Mistral 的 API 金鑰，優先順序低於 --api-key 與 OPENAI_API_KEY／ANTHROPIC_API_KEY／MISTRAL_API_KEY 環境變數",
+            "api_key = \"sk-...\"".to_string(),
+        ),
+        (
+            "網頁伺服器的 API token，設定後請求需帶 Authorization: Bearer <token>",
+            "api_token = \"change-me\"".to_string(),
+        ),
+    ];
+
+    let mut template = String::from("# Quick Project Report 設定檔\n# 取消註解以覆寫預設值，命令列參數的優先順序高於此檔案\n");
+    for (comment, line) in entries {
+        template.push_str(&format!("\n# {}\n# {}\n", comment, line));
+    }
+
+    // 表格必須放在所有欄位之後
+    template.push_str(concat!(
+        "\n# 各後端的並行請求數上限，未設定的後端使用 concurrency；llama 後端收到錯誤回應時會自動降低（最低 1）\n",
+        "# [concurrency_per_backend]\n",
+        "# openai_concurrency = 8\n",
+        "# llama_concurrency = 2\n",
+        "# claude_concurrency = 4\n",
+        "# mistral_concurrency = 4\n",
+    ));
+    template.push_str(concat!(
+        "\n# 程式碼檔案規則：additional 加入副檔名、exclude 移除副檔名、include_filenames 依檔名（glob）納入\n",
+        "# [extensions]\n",
+        "# additional = [\"vue\", \"svelte\"]\n",
+        "# exclude = [\"md\"]\n",
+        "# include_filenames = [\"Makefile\", \"*.config.ts\"]\n",
+    ));
+    template.push_str(concat!(
+        "\n# 依檔案語言加在檔案摘要提示語前的說明（鍵為 rust、python、javascript、typescript、cpp、go 等，未內建的語言為副檔名）\n",
+        "# [prompts.by_language]\n",
+        "# rust = \"這是 Rust 程式碼，請特別說明 trait、泛型與錯誤處理方式。\"\n",
+        "# python = \"這是 Python 程式碼，請說明主要的類別、函式與相依套件。\"\n",
+    ));
+    template.push_str("\n# 各模型每 1K tokens 的價格（美元），用於 --dry-run 的費用估算\n# [pricing]\n");
+    for (model, price) in DEFAULT_PRICING {
+        template.push_str(&format!("# {} = {}\n", quote(model), price));
+    }
+    template.push_str(concat!(
+        "\n# 在同一個伺服器中分析多個專案，設定後取代 project_path（每個專案一個區塊）\n",
+        "# [[projects]]\n",
+        "# path = \"../backend\"\n",
+        "# [[projects]]\n",
+        "# path = \"../frontend\"\n",
+    ));
+    template
+}
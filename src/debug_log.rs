@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+// ===========================
+// LLM 原始回應（--debug-llm，/api/raw-llm-response）
+// ===========================
+
+// 同一個檔案有多個回應（分段摘要、合併請求或重試）時的分隔線
+const RESPONSE_SEPARATOR: &str = "\n\n----------\n\n";
+
+// 只在 --debug-llm 時建立，避免一般使用時在記憶體中保存大量原始回應
+#[derive(Debug, Default)]
+pub struct DebugLog {
+    // 檔案路徑 -> 最近一次摘要時 LLM 回傳的 HTTP 回應本文（解析與截斷前），多個回應依序串接
+    pub raw_response: HashMap<String, String>,
+}
+
+impl DebugLog {
+    pub fn raw_response(log: &Mutex<DebugLog>, path: &str) -> Option<String> {
+        log.lock().expect("LLM 原始回應紀錄已損毀").raw_response.get(path).cloned()
+    }
+}
+
+tokio::task_local! {
+    // 目前摘要中的檔案，讓 LLM 後端不必知道檔案路徑也能記錄原始回應
+    static CURRENT_FILE: (Arc<Mutex<DebugLog>>, String);
+}
+
+// 在 future 執行期間將 LLM 的原始回應記錄到 path，並先清除上一次摘要的紀錄；log 為 None 時不記錄
+pub async fn scope<F: Future>(log: Option<Arc<Mutex<DebugLog>>>, path: String, future: F) -> F::Output {
+    match log {
+        Some(log) => {
+            log.lock().expect("LLM 原始回應紀錄已損毀").raw_response.remove(&path);
+            CURRENT_FILE.scope((log, path), future).await
+        }
+        None => future.await,
+    }
+}
+
+// 記錄一個回應；不在 scope 中（例如資料夾分析或未啟用 --debug-llm）時忽略
+pub fn record_raw_response(text: &str) {
+    let _ = CURRENT_FILE.try_with(|(log, path)| {
+        let mut log = log.lock().expect("LLM 原始回應紀錄已損毀");
+        let entry = log.raw_response.entry(path.clone()).or_default();
+        if !entry.is_empty() {
+            entry.push_str(RESPONSE_SEPARATOR);
+        }
+        entry.push_str(text);
+    });
+}
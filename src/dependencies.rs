@@ -0,0 +1,415 @@
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use crate::extensions::Language;
+
+// ===========================
+// 檔案相依圖（/api/dependency-graph）
+// ===========================
+
+// JS/TS 相對匯入省略副檔名時依序嘗試的副檔名
+const JS_EXTENSIONS: &[&str] = &["ts", "tsx", "js", "jsx", "mjs", "cjs"];
+
+// D3.js、Cytoscape.js 可直接使用的節點與邊；專案內的檔案以相對於專案目錄的路徑為 id，
+// 無法對應到專案檔案的匯入（標準函式庫、第三方套件）以套件名稱為 id
+#[derive(Debug, Default, Serialize)]
+pub struct DependencyGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GraphNode {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: NodeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<Language>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    File,
+    External,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+}
+
+// 匯入對應到的目標
+enum Target {
+    File(PathBuf),
+    External(String),
+}
+
+// 取出檔案中匯入的模組或路徑，依出現順序且不重複；不支援的語言回傳空清單
+// （Rust `use` 與 `mod x;`、Python `import`/`from`、JS/TS `import`/`export ... from`/`require()`、C/C++ `#include`）
+pub fn extract_imports(content: &str, language: &Language) -> Vec<String> {
+    let imports = match language {
+        Language::Rust => rust_imports(content),
+        Language::Python => python_imports(content),
+        Language::JavaScript | Language::TypeScript => js_imports(content),
+        Language::C | Language::Cpp => c_includes(content),
+        _ => Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    imports.into_iter().filter(|import| seen.insert(import.clone())).collect()
+}
+
+// 以所有檔案（絕對路徑 -> 匯入清單）建立相依圖，沒有匯入的檔案也會成為節點
+pub fn build_graph(project_root: &Path, imports: &HashMap<String, Vec<String>>) -> DependencyGraph {
+    let known: HashSet<PathBuf> = imports.keys().map(PathBuf::from).collect();
+    let id = |path: &Path| path.strip_prefix(project_root).unwrap_or(path).to_string_lossy().to_string();
+
+    let mut files: Vec<&String> = imports.keys().collect();
+    files.sort();
+    let mut graph = DependencyGraph::default();
+    let mut edges = BTreeSet::new();
+    let mut externals = BTreeSet::new();
+    for file in files {
+        let path = Path::new(file);
+        let language = Language::from_path(path);
+        for import in &imports[file] {
+            let to = match resolve(project_root, path, &language, import, &known) {
+                Some(Target::File(target)) if target != path => id(&target),
+                Some(Target::External(name)) => {
+                    externals.insert(name.clone());
+                    name
+                }
+                _ => continue,
+            };
+            edges.insert((id(path), to));
+        }
+        graph.nodes.push(GraphNode {
+            id: id(path),
+            kind: NodeKind::File,
+            language: Some(language),
+        });
+    }
+    graph.nodes.extend(externals.into_iter().map(|name| GraphNode {
+        id: name,
+        kind: NodeKind::External,
+        language: None,
+    }));
+    graph.edges = edges.into_iter().map(|(from, to)| GraphEdge { from, to }).collect();
+    graph
+}
+
+fn resolve(root: &Path, file: &Path, language: &Language, import: &str, known: &HashSet<PathBuf>) -> Option<Target> {
+    match language {
+        Language::Rust => resolve_rust(file, import, known),
+        Language::Python => resolve_python(root, file, import, known),
+        Language::JavaScript | Language::TypeScript => resolve_js(file, import, known),
+        Language::C | Language::Cpp => resolve_include(root, file, import, known),
+        _ => None,
+    }
+}
+
+// ---------- Rust ----------
+
+fn rust_imports(content: &str) -> Vec<String> {
+    let mut imports = Vec::new();
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        // mod x; 宣告的子模組
+        let module = ["pub mod ", "pub(crate) mod ", "mod "]
+            .iter()
+            .find_map(|prefix| line.strip_prefix(prefix))
+            .and_then(|rest| rest.strip_suffix(';'));
+        if let Some(module) = module {
+            imports.push(format!("self::{}", module.trim()));
+            continue;
+        }
+        let rest = ["pub use ", "pub(crate) use ", "pub(super) use ", "use "]
+            .iter()
+            .find_map(|prefix| line.strip_prefix(prefix));
+        let Some(rest) = rest else {
+            continue;
+        };
+        // 跨多行的 use 語句讀到分號為止
+        let mut statement = rest.to_string();
+        while !statement.contains(';') {
+            let Some(next) = lines.next() else {
+                break;
+            };
+            statement.push(' ');
+            statement.push_str(next.trim());
+        }
+        let tree = statement.split(';').next().unwrap_or_default();
+        expand_use_tree("", tree.trim().trim_start_matches("::"), &mut imports);
+    }
+    imports
+}
+
+// 展開 `a::{b, c::{d, e}}` 為 a::b、a::c::d、a::c::e
+fn expand_use_tree(prefix: &str, tree: &str, imports: &mut Vec<String>) {
+    let tree = tree.trim();
+    if let (Some(open), true) = (tree.find('{'), tree.ends_with('}')) {
+        let base = join_rust_path(prefix, tree[..open].trim().trim_end_matches("::"));
+        for part in split_top_level(&tree[open + 1..tree.len() - 1]) {
+            expand_use_tree(&base, part, imports);
+        }
+        return;
+    }
+    let path = tree.split(" as ").next().unwrap_or_default().trim();
+    let path = path.trim_end_matches("::*").trim_end_matches('*');
+    let path = join_rust_path(prefix, path);
+    if !path.is_empty() {
+        imports.push(path);
+    }
+}
+
+fn join_rust_path(prefix: &str, path: &str) -> String {
+    match (prefix.is_empty(), path.is_empty() || path == "self") {
+        (_, true) => prefix.to_string(),
+        (true, false) => path.to_string(),
+        (false, false) => format!("{}::{}", prefix, path),
+    }
+}
+
+// 以不在括號內的逗號分隔
+fn split_top_level(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts.into_iter().filter(|part| !part.trim().is_empty()).collect()
+}
+
+// crate::、self::、super:: 與目前模組的子模組對應到專案檔案，其餘為外部 crate
+fn resolve_rust(file: &Path, import: &str, known: &HashSet<PathBuf>) -> Option<Target> {
+    let segments: Vec<&str> = import.split("::").collect();
+    let (base, rest) = match segments[0] {
+        "crate" => (crate_root(file, known)?, &segments[1..]),
+        "self" | "super" => {
+            let supers = segments.iter().take_while(|segment| **segment == "super").count();
+            let skip = if segments[0] == "self" { 1 } else { supers };
+            let mut base = module_dir(file);
+            for _ in 0..supers {
+                base = base.parent()?.to_path_buf();
+            }
+            (base, &segments[skip..])
+        }
+        first => {
+            // 2018 版起可直接以名稱使用目前模組以 mod 宣告的子模組
+            let local = module_dir(file).join(first);
+            if module_file(&local, known).is_some() {
+                (module_dir(file), &segments[..])
+            } else {
+                return Some(Target::External(first.to_string()));
+            }
+        }
+    };
+    // 最長的模組路徑優先，其餘段落為模組中的項目名稱
+    (0..=rest.len()).rev().find_map(|n| {
+        let dir = rest[..n].iter().fold(base.clone(), |dir, segment| dir.join(segment));
+        module_file(&dir, known).map(Target::File)
+    })
+}
+
+// 模組目錄對應的檔案：a/mod.rs、a.rs，crate 根目錄則為 main.rs 或 lib.rs
+fn module_file(dir: &Path, known: &HashSet<PathBuf>) -> Option<PathBuf> {
+    let candidates = [
+        dir.join("mod.rs"),
+        dir.with_extension("rs"),
+        dir.join("main.rs"),
+        dir.join("lib.rs"),
+    ];
+    candidates.into_iter().find(|candidate| known.contains(candidate))
+}
+
+// 檔案的子模組所在的目錄：mod.rs、main.rs、lib.rs 為所在目錄，其他檔案為同名的目錄
+fn module_dir(file: &Path) -> PathBuf {
+    let parent = file.parent().unwrap_or(Path::new(""));
+    match file.file_name().and_then(|name| name.to_str()) {
+        Some("mod.rs" | "main.rs" | "lib.rs") => parent.to_path_buf(),
+        _ => parent.join(file.file_stem().unwrap_or_default()),
+    }
+}
+
+// 往上層找到含有 main.rs 或 lib.rs 的目錄
+fn crate_root(file: &Path, known: &HashSet<PathBuf>) -> Option<PathBuf> {
+    file.ancestors()
+        .skip(1)
+        .find(|dir| known.contains(&dir.join("main.rs")) || known.contains(&dir.join("lib.rs")))
+        .map(Path::to_path_buf)
+}
+
+// ---------- Python ----------
+
+fn python_imports(content: &str) -> Vec<String> {
+    let mut imports = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("import ") {
+            imports.extend(
+                rest.split(',')
+                    .filter_map(|name| name.split_whitespace().next())
+                    .map(str::to_string),
+            );
+        } else if let Some(rest) = line.strip_prefix("from ") {
+            let mut parts = rest.splitn(2, " import ");
+            let module = parts.next().unwrap_or_default().trim();
+            let names = parts.next().unwrap_or_default();
+            if module.is_empty() {
+                continue;
+            }
+            // from . import utils：匯入的是同一套件中的模組
+            if module.chars().all(|c| c == '.') {
+                imports.extend(
+                    names
+                        .trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace())
+                        .split(',')
+                        .filter_map(|name| name.split_whitespace().next())
+                        .filter(|name| *name != "*")
+                        .map(|name| format!("{}{}", module, name)),
+                );
+            } else {
+                imports.push(module.to_string());
+            }
+        }
+    }
+    imports
+}
+
+// 相對匯入以檔案所在目錄為起點；絕對匯入由檔案所在目錄往上找到專案目錄，找不到時為外部套件
+fn resolve_python(root: &Path, file: &Path, import: &str, known: &HashSet<PathBuf>) -> Option<Target> {
+    let dots = import.chars().take_while(|c| *c == '.').count();
+    let segments: Vec<&str> = import[dots..].split('.').filter(|s| !s.is_empty()).collect();
+    let parent = file.parent()?;
+    let bases: Vec<&Path> = if dots > 0 {
+        vec![parent.ancestors().nth(dots - 1)?]
+    } else {
+        parent.ancestors().take_while(|dir| dir.starts_with(root)).collect()
+    };
+    let found = bases.iter().find_map(|base| {
+        (1..=segments.len()).rev().find_map(|n| {
+            let module = segments[..n].iter().fold(base.to_path_buf(), |dir, segment| dir.join(segment));
+            [module.with_extension("py"), module.join("__init__.py")]
+                .into_iter()
+                .find(|candidate| known.contains(candidate))
+        })
+    });
+    match (found, dots) {
+        (Some(path), _) => Some(Target::File(path)),
+        (None, 0) => segments.first().map(|name| Target::External(name.to_string())),
+        (None, _) => None,
+    }
+}
+
+// ---------- JavaScript / TypeScript ----------
+
+fn js_imports(content: &str) -> Vec<String> {
+    let mut imports = Vec::new();
+    for line in content.lines().map(str::trim) {
+        // import x from 'a'、export { y } from 'b'、多行 import 結尾的 } from 'c'、import 'd'
+        let is_import = line.starts_with("import") || line.starts_with("export");
+        if let Some(index) = line.find(" from ").filter(|index| is_import || line[..*index].contains('}')) {
+            imports.extend(string_literal(&line[index + " from ".len()..]));
+        } else if is_import {
+            if let Some(rest) = line.strip_prefix("import ") {
+                imports.extend(string_literal(rest).filter(|_| rest.starts_with(['\'', '"'])));
+            }
+        }
+        // require('a')、動態 import('b')
+        for call in ["require(", "import("] {
+            let mut rest = line;
+            while let Some(index) = rest.find(call) {
+                rest = &rest[index + call.len()..];
+                imports.extend(string_literal(rest).filter(|_| rest.starts_with(['\'', '"', '`'])));
+            }
+        }
+    }
+    imports
+}
+
+// 開頭第一個以引號包住的字串
+fn string_literal(text: &str) -> Option<String> {
+    let text = text.trim_start();
+    let quote = text.chars().next().filter(|c| matches!(c, '\'' | '"' | '`'))?;
+    let rest = &text[1..];
+    rest.find(quote).map(|end| rest[..end].to_string())
+}
+
+// 相對路徑對應到專案檔案（可省略副檔名或指向含 index 檔案的目錄），其餘為 npm 套件
+fn resolve_js(file: &Path, import: &str, known: &HashSet<PathBuf>) -> Option<Target> {
+    if !import.starts_with('.') {
+        let mut segments = import.split('/');
+        let name = match segments.next() {
+            Some(scope) if scope.starts_with('@') => format!("{}/{}", scope, segments.next().unwrap_or_default()),
+            Some(name) => name.to_string(),
+            None => return None,
+        };
+        return Some(Target::External(name));
+    }
+    let base = normalize(&file.parent()?.join(import));
+    let mut candidates = vec![base.clone()];
+    for extension in JS_EXTENSIONS {
+        candidates.push(PathBuf::from(format!("{}.{}", base.display(), extension)));
+    }
+    for extension in JS_EXTENSIONS {
+        candidates.push(base.join(format!("index.{}", extension)));
+    }
+    candidates.into_iter().find(|candidate| known.contains(candidate)).map(Target::File)
+}
+
+// ---------- C / C++ ----------
+
+// "x.h" 保留原字串，<x.h> 保留角括號以區分系統標頭檔
+fn c_includes(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("#include"))
+        .filter_map(|rest| {
+            let rest = rest.trim();
+            if rest.starts_with('<') {
+                rest.find('>').map(|end| rest[..=end].to_string())
+            } else {
+                string_literal(rest)
+            }
+        })
+        .collect()
+}
+
+fn resolve_include(root: &Path, file: &Path, import: &str, known: &HashSet<PathBuf>) -> Option<Target> {
+    if let Some(header) = import.strip_prefix('<') {
+        return Some(Target::External(header.trim_end_matches('>').to_string()));
+    }
+    [file.parent()?, root]
+        .into_iter()
+        .map(|dir| normalize(&dir.join(import)))
+        .find(|candidate| known.contains(candidate))
+        .map(Target::File)
+        .or_else(|| Some(Target::External(import.to_string())))
+}
+
+// 去除路徑中的 . 與 ..，不存取檔案系統
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+use std::io;
+use warp::http::StatusCode;
+use warp::reply::Reply;
+
+// ===========================
+// 錯誤型別
+// ===========================
+
+#[derive(Debug)]
+pub enum QprError {
+    // 檔案讀寫失敗
+    IoError(io::Error),
+    // 無法連線或 HTTP 請求失敗
+    HttpError(reqwest::Error),
+    // JSON 解析失敗
+    ParseError(serde_json::Error),
+    // LLM API 回傳非 2xx 狀態碼
+    LlmError { status: u16, body: String },
+    // LLM 回應的格式不符預期
+    InvalidResponse(String),
+    // 路徑位於專案目錄之外
+    PathNotAllowed(String),
+    // 設定檔或命令列參數有誤
+    ConfigError(String),
+    // git 指令執行失敗
+    GitError(String),
+    // 尚未有任何檔案摘要可供彙整
+    NoSummaries,
+    // 目前的分析階段不允許此操作
+    PhaseConflict(String),
+    // 缺少或錯誤的 API token
+    Unauthorized,
+    // 請求內容不合法
+    InvalidRequest(String),
+    // 找不到指定的檔案或資源
+    NotFound(String),
+    // 無法監看檔案變動（--watch）
+    WatchError(String),
+}
+
+impl fmt::Display for QprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QprError::IoError(e) => write!(f, "檔案讀寫失敗：{}", e),
+            QprError::HttpError(e) => write!(f, "HTTP 請求失敗：{}", e),
+            QprError::ParseError(e) => write!(f, "JSON 解析失敗：{}", e),
+            QprError::LlmError { status, body } => write!(f, "API 回傳 HTTP {}：{}", status, body),
+            QprError::InvalidResponse(message) => write!(f, "LLM 回應格式錯誤：{}", message),
+            QprError::PathNotAllowed(path) => write!(f, "路徑不在專案目錄內：{}", path),
+            QprError::ConfigError(message) => write!(f, "設定錯誤：{}", message),
+            QprError::GitError(message) => write!(f, "Git 操作失敗：{}", message),
+            QprError::NoSummaries => write!(f, "尚無可用的檔案摘要"),
+            QprError::PhaseConflict(message) => write!(f, "{}", message),
+            QprError::Unauthorized => write!(f, "unauthorized"),
+            QprError::InvalidRequest(message) => write!(f, "請求內容有誤：{}", message),
+            QprError::NotFound(message) => write!(f, "找不到：{}", message),
+            QprError::WatchError(message) => write!(f, "檔案監看失敗：{}", message),
+        }
+    }
+}
+
+impl std::error::Error for QprError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            QprError::IoError(e) => Some(e),
+            QprError::HttpError(e) => Some(e),
+            QprError::ParseError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for QprError {
+    fn from(e: io::Error) -> Self {
+        QprError::IoError(e)
+    }
+}
+
+impl From<reqwest::Error> for QprError {
+    fn from(e: reqwest::Error) -> Self {
+        QprError::HttpError(e)
+    }
+}
+
+impl From<serde_json::Error> for QprError {
+    fn from(e: serde_json::Error) -> Self {
+        QprError::ParseError(e)
+    }
+}
+
+impl QprError {
+    // 對應到 API 回應的 HTTP 狀態碼
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            QprError::Unauthorized => StatusCode::UNAUTHORIZED,
+            QprError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            QprError::PathNotAllowed(_) => StatusCode::FORBIDDEN,
+            QprError::NotFound(_) => StatusCode::NOT_FOUND,
+            QprError::HttpError(_)
+            | QprError::LlmError { .. }
+            | QprError::InvalidResponse(_)
+            | QprError::ParseError(_) => StatusCode::BAD_GATEWAY,
+            QprError::NoSummaries | QprError::PhaseConflict(_) => StatusCode::CONFLICT,
+            QprError::IoError(_)
+            | QprError::ConfigError(_)
+            | QprError::GitError(_)
+            | QprError::WatchError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // 以 `{"error": "..."}` 的 JSON 回應錯誤
+    pub fn into_response(self) -> warp::reply::Response {
+        warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "error": self.to_string() })),
+            self.status_code(),
+        )
+        .into_response()
+    }
+}
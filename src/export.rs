@@ -0,0 +1,382 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::error::QprError;
+use crate::{Directory, FileInfo, Progress, MAX_DEPTH_MARKER};
+
+// ===========================
+// 報告匯出
+// ===========================
+
+// 匯出檔案的建議名稱
+pub const MARKDOWN_EXPORT_FILENAME: &str = "qpr_report.md";
+pub const JSON_EXPORT_FILENAME: &str = "qpr_report.json";
+pub const HTML_EXPORT_FILENAME: &str = "qpr_report.html";
+pub const CSV_EXPORT_FILENAME: &str = "qpr_report.csv";
+
+// CSV 的欄位；摘要中的換行以此取代，讓每個檔案保持一列
+const CSV_HEADER: &str = "path,name,extension,size_bytes,line_count,summary";
+const CSV_NEWLINE_REPLACEMENT: &str = " ↵ ";
+
+// --export-format：不啟動網頁伺服器，直接輸出報告檔案
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Html,
+    Markdown,
+    Csv,
+}
+
+impl ExportFormat {
+    // 未指定 --export-output 時的檔名
+    pub fn default_filename(self) -> &'static str {
+        match self {
+            ExportFormat::Json => JSON_EXPORT_FILENAME,
+            ExportFormat::Html => HTML_EXPORT_FILENAME,
+            ExportFormat::Markdown => MARKDOWN_EXPORT_FILENAME,
+            ExportFormat::Csv => CSV_EXPORT_FILENAME,
+        }
+    }
+}
+
+// 單一檔案的 HTML 報告，不引用任何 CDN；{title} 與 {data} 於輸出時替換
+const HTML_REPORT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="zh-Hant">
+<head>
+<meta charset="utf-8">
+<title>Quick Project Report：{title}</title>
+<style>
+    body { font-family: sans-serif; margin: 0; display: flex; height: 100vh; }
+    #tree { width: 40%; overflow: auto; padding: 16px; border-right: 1px solid #ddd; box-sizing: border-box; }
+    #detail { flex: 1; overflow: auto; padding: 16px; box-sizing: border-box; }
+    #tree ul { list-style: none; margin: 0; padding-left: 18px; }
+    #tree > ul { padding-left: 0; }
+    #tree summary { cursor: pointer; font-weight: bold; }
+    #tree .file { cursor: pointer; padding: 1px 4px; border-radius: 3px; }
+    #tree .file:hover, #tree .file.selected { background: #e8f0fe; }
+    #tree .change-new { color: #2e7d32; }
+    #tree .change-modified { color: #ef6c00; }
+    #tree .change-deleted { color: #c62828; text-decoration: line-through; }
+    #detail h2 { word-break: break-all; }
+    .summary { white-space: pre-wrap; line-height: 1.6; }
+    .empty { color: #888; }
+</style>
+</head>
+<body>
+<div id="tree"></div>
+<div id="detail">
+    <h1>Quick Project Report：{title}</h1>
+    <div id="content"></div>
+</div>
+<script id="report-data" type="application/json">{data}</script>
+<script>
+    const report = JSON.parse(document.getElementById('report-data').textContent);
+
+    function element(tag, className, text) {
+        const node = document.createElement(tag);
+        if (className) node.className = className;
+        if (text !== undefined) node.textContent = text;
+        return node;
+    }
+
+    function showText(title, text) {
+        const content = document.getElementById('content');
+        content.replaceChildren(element('h2', null, title));
+        if (text) {
+            content.appendChild(element('div', 'summary', text));
+        } else {
+            content.appendChild(element('p', 'empty', '此檔案沒有摘要。'));
+        }
+    }
+
+    function buildDirectory(dir, open) {
+        const details = document.createElement('details');
+        details.open = open;
+        details.appendChild(element('summary', null, dir.name || dir.path));
+        const list = document.createElement('ul');
+        for (const subdir of dir.subdirs || []) {
+            const item = document.createElement('li');
+            item.appendChild(buildDirectory(subdir, false));
+            list.appendChild(item);
+        }
+        for (const file of dir.files || []) {
+            const item = document.createElement('li');
+            const status = file.change_status && file.change_status !== 'unchanged' ? ' change-' + file.change_status : '';
+            const label = element('span', 'file' + status, file.name);
+            label.title = file.summary || '';
+            label.onclick = () => {
+                document.querySelectorAll('#tree .file.selected').forEach(node => node.classList.remove('selected'));
+                label.classList.add('selected');
+                showText(dir.path + '/' + file.name, file.summary);
+            };
+            item.appendChild(label);
+            list.appendChild(item);
+        }
+        details.appendChild(list);
+        return details;
+    }
+
+    const root = document.createElement('ul');
+    const rootItem = document.createElement('li');
+    rootItem.appendChild(buildDirectory(report.tree, true));
+    root.appendChild(rootItem);
+    document.getElementById('tree').appendChild(root);
+    if (report.rollup_summary) {
+        showText('專案總覽', report.rollup_summary);
+    }
+</script>
+</body>
+</html>
+"#;
+
+// 轉義 HTML 中的特殊字元
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// 程式碼區塊的語言標記，與前端 Prism.js 的對應一致
+pub fn fence_language(file_name: &str) -> &'static str {
+    let ext = Path::new(file_name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    match ext.as_str() {
+        "rs" => "rust",
+        "py" => "python",
+        "js" => "javascript",
+        "ts" => "typescript",
+        "java" => "java",
+        "cpp" => "cpp",
+        "c" | "h" => "c",
+        "go" => "go",
+        "sh" => "bash",
+        "rb" => "ruby",
+        "bat" => "batch",
+        "cs" => "csharp",
+        "resx" => "xml",
+        "md" => "markdown",
+        _ => "",
+    }
+}
+
+// 目錄樹中的一個項目，輸出時目錄與檔案依名稱混合排序
+enum TreeEntry<'a> {
+    Dir(&'a Directory),
+    File(&'a FileInfo),
+    // 達到最大深度而未掃描的子目錄
+    MaxDepth,
+}
+
+impl TreeEntry<'_> {
+    fn name(&self) -> &str {
+        match self {
+            TreeEntry::Dir(dir) => &dir.name,
+            TreeEntry::File(file) => &file.name,
+            TreeEntry::MaxDepth => MAX_DEPTH_MARKER,
+        }
+    }
+}
+
+// 程式碼內含有 ``` 時使用更長的圍欄，避免提前結束區塊
+fn code_fence(content: &str) -> String {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in content.chars() {
+        if c == '`' {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    "`".repeat(longest.max(2) + 1)
+}
+
+impl Directory {
+    // 是否有任何檔案位於此目錄或其子目錄中
+    fn has_files(&self) -> bool {
+        !self.files.is_empty() || self.subdirs.iter().any(Directory::has_files)
+    }
+
+    // 將 Progress 中的摘要合併進目錄樹的副本，不修改共享的樹，避免與摘要任務的寫入順序互相影響
+    pub fn with_summaries(&self, summaries: &BTreeMap<String, String>) -> Directory {
+        let mut dir = self.clone();
+        dir.merge_summaries(summaries);
+        dir
+    }
+
+    fn merge_summaries(&mut self, summaries: &BTreeMap<String, String>) {
+        for file in &mut self.files {
+            let file_path = Path::new(&self.path).join(&file.name).to_string_lossy().to_string();
+            if let Some(summary) = summaries.get(&file_path) {
+                file.summary = Some(summary.clone());
+            }
+        }
+        for subdir in &mut self.subdirs {
+            subdir.merge_summaries(summaries);
+        }
+    }
+
+    // 將目錄樹轉為 Markdown：目錄為 `##`，檔案為 `###`，附上摘要與程式碼。
+    // 只讀取位於 project_path 之內的檔案；會讀取每個檔案（阻塞），async 函式中請透過 run_blocking 呼叫
+    pub fn to_markdown(&self, project_path: &str, depth: usize) -> String {
+        let mut markdown = String::new();
+        if depth == 0 {
+            markdown.push_str(&format!("# Quick Project Report：{}\n\n", self.name));
+        }
+
+        if !self.files.is_empty() {
+            markdown.push_str(&format!("## {}\n\n", self.path));
+            for file in &self.files {
+                let file_path = Path::new(&self.path).join(&file.name);
+                let summary = file.summary.as_deref().unwrap_or("_此檔案沒有摘要。_");
+                let content = crate::resolve_project_path(project_path, &file_path.to_string_lossy())
+                    .and_then(|path| Ok(fs::read_to_string(path)?))
+                    .unwrap_or_else(|_| "無法取得檔案內容。".to_string());
+                let fence = code_fence(&content);
+
+                markdown.push_str(&format!("### {}\n\n{}\n\n", file.name, summary.trim()));
+                markdown.push_str(&format!(
+                    "{}{}\n{}\n{}\n\n",
+                    fence,
+                    fence_language(&file.name),
+                    content.trim_end(),
+                    fence
+                ));
+            }
+        }
+
+        for subdir in self.subdirs.iter().filter(|d| d.has_files()) {
+            markdown.push_str(&subdir.to_markdown(project_path, depth + 1));
+        }
+        markdown
+    }
+
+    // 將目錄樹與摘要以 JSON 嵌入 <script>，產生可直接開啟或以郵件分享的單一 HTML 檔案
+    pub fn to_html_report(&self, rollup_summary: Option<&str>) -> Result<String, QprError> {
+        let data = serde_json::to_string(&serde_json::json!({
+            "tree": self,
+            "rollup_summary": rollup_summary,
+        }))?;
+        // `<` 只會出現在 JSON 字串中，轉成 \u003c 以免摘要內容提前結束 <script>
+        let data = data.replace('<', "\\u003c");
+        Ok(HTML_REPORT_TEMPLATE
+            .replace("{title}", &escape_html(&self.name))
+            .replace("{data}", &data))
+    }
+
+    // 以 `tree` 指令的格式輸出目錄樹（只列出含有檔案的目錄）；show_summaries 時在檔案下一行附上摘要
+    pub fn to_tree_string(&self, show_summaries: bool) -> String {
+        let mut tree = format!("{}\n", self.path);
+        let (dirs, files) = self.write_tree(&mut tree, "", show_summaries);
+        tree.push_str(&format!("\n{} directories, {} files\n", dirs, files));
+        tree
+    }
+
+    // 寫入子項目並回傳目錄數與檔案數
+    fn write_tree(&self, tree: &mut String, prefix: &str, show_summaries: bool) -> (usize, usize) {
+        let mut entries: Vec<TreeEntry> = self
+            .subdirs
+            .iter()
+            .filter(|d| d.has_files() || d.max_depth_reached)
+            .map(TreeEntry::Dir)
+            .chain(self.files.iter().map(TreeEntry::File))
+            .collect();
+        entries.sort_by(|a, b| a.name().cmp(b.name()));
+        if self.max_depth_reached {
+            entries.push(TreeEntry::MaxDepth);
+        }
+
+        let (mut dirs, mut files) = (0, 0);
+        for (i, entry) in entries.iter().enumerate() {
+            let (branch, indent) = if i + 1 == entries.len() {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            tree.push_str(&format!("{}{}{}\n", prefix, branch, entry.name()));
+            let child_prefix = format!("{}{}", prefix, indent);
+            match entry {
+                TreeEntry::Dir(dir) => {
+                    let (subdirs, subfiles) = dir.write_tree(tree, &child_prefix, show_summaries);
+                    dirs += subdirs + 1;
+                    files += subfiles;
+                }
+                TreeEntry::File(file) => {
+                    files += 1;
+                    let summary = file.summary.as_deref().filter(|_| show_summaries);
+                    for line in summary.into_iter().flat_map(|s| s.trim().lines()) {
+                        tree.push_str(&format!("{}  {}\n", child_prefix, line.trim()));
+                    }
+                }
+                TreeEntry::MaxDepth => {}
+            }
+        }
+        (dirs, files)
+    }
+}
+
+// 依 --export-format 輸出報告內容；專案總覽只用於 HTML 報告的首頁
+pub fn render_report(
+    format: ExportFormat,
+    progress: &Progress,
+    tree: &Directory,
+    project_path: &str,
+) -> Result<String, QprError> {
+    let report = tree.with_summaries(&progress.annotated_summaries());
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(&report)?),
+        ExportFormat::Markdown => Ok(report.to_markdown(project_path, 0)),
+        ExportFormat::Html => report.to_html_report(progress.rollup_summary.as_deref()),
+        ExportFormat::Csv => Ok(progress_to_csv(progress, tree)),
+    }
+}
+
+// 以 RFC 4180 格式輸出每個檔案的路徑、大小、行數與摘要，方便以試算表檢視；
+// 行數為摘要時記錄的值，未讀取內容的檔案（例如超過大小上限而略過）留空
+pub fn progress_to_csv(progress: &Progress, tree: &Directory) -> String {
+    let mut files = Vec::new();
+    tree.with_summaries(&progress.annotated_summaries())
+        .collect_all_files_with_metadata(&mut files);
+
+    let mut csv = format!("{}\r\n", CSV_HEADER);
+    for file in files {
+        let line_count = progress
+            .line_counts
+            .get(&file.path)
+            .map(|count| count.to_string())
+            .unwrap_or_default();
+        let summary = file
+            .summary
+            .as_deref()
+            .map(|summary| summary.trim().replace("\r\n", "\n").replace(['\r', '\n'], CSV_NEWLINE_REPLACEMENT))
+            .unwrap_or_default();
+        let fields = [
+            file.path.as_str(),
+            file.name.as_str(),
+            file.extension.as_str(),
+            &file.size_bytes.to_string(),
+            &line_count,
+            &summary,
+        ];
+        let row: Vec<Cow<str>> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+// 含有逗號、雙引號或換行的欄位以雙引號包住，內部的雙引號重複一次
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
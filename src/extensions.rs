@@ -0,0 +1,348 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::config::ExtensionsConfig;
+use crate::error::QprError;
+
+// ===========================
+// 程式碼檔案判斷（副檔名與檔名規則）
+// ===========================
+
+// 只讀取檔案開頭這麼多 bytes 來尋找測試標記
+const TEST_MARKER_SCAN_BYTES: usize = 200;
+
+// 執行期的程式碼檔案規則：內建副檔名加上 qpr.toml 的 [extensions] 設定
+#[derive(Debug, Clone)]
+pub struct ExtensionSet {
+    // 小寫、不含前導 `.` 的副檔名
+    extensions: HashSet<String>,
+    // 以檔名比對的規則（例如 `Makefile`、`*.config.ts`），優先於副檔名
+    include_filenames: GlobSet,
+}
+
+impl ExtensionSet {
+    pub fn new(defaults: &[&str], config: &ExtensionsConfig) -> Result<Self, QprError> {
+        let normalize = |ext: &str| ext.trim().trim_start_matches('.').to_lowercase();
+
+        let builtin: HashSet<String> = defaults.iter().map(|ext| normalize(ext)).collect();
+        let additional: HashSet<String> = config.additional.iter().map(|ext| normalize(ext)).collect();
+        let mut extensions: HashSet<String> = builtin.union(&additional).cloned().collect();
+        // exclude（含 --ignore-extensions）優先於內建清單與 additional；
+        // 移除內建的副檔名時發出警告，只在 additional 中的副檔名以 debug 記錄
+        for ext in &config.exclude {
+            let ext = normalize(ext);
+            if !extensions.remove(&ext) {
+                continue;
+            }
+            if builtin.contains(&ext) {
+                tracing::warn!("exclude（或 --ignore-extensions）移除了內建清單中的副檔名 {}，將略過此副檔名", ext);
+            } else {
+                tracing::debug!("副檔名 {} 同時列在 additional 與 exclude（或 --ignore-extensions），將略過此副檔名", ext);
+            }
+        }
+
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &config.include_filenames {
+            let glob = Glob::new(pattern)
+                .map_err(|e| QprError::ConfigError(format!("include_filenames 規則 {} 無效：{}", pattern, e)))?;
+            builder.add(glob);
+        }
+        let include_filenames = builder
+            .build()
+            .map_err(|e| QprError::ConfigError(format!("無法建立 include_filenames 規則：{}", e)))?;
+
+        Ok(ExtensionSet {
+            extensions,
+            include_filenames,
+        })
+    }
+
+    // 檔名符合 include_filenames，或副檔名在清單中即視為程式碼檔案，並回傳檔案的語言
+    pub fn is_code_file(&self, path: &Path) -> Option<Language> {
+        let file_name = path.file_name()?;
+        let is_code = self.include_filenames.is_match(file_name)
+            || path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|ext| self.extensions.contains(&ext.to_lowercase()));
+        is_code.then(|| Language::from_path(path))
+    }
+}
+
+// 程式碼檔案的語言，用於選擇 [prompts.by_language] 的提示語前綴
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Language {
+    Rust,
+    Python,
+    JavaScript,
+    TypeScript,
+    Java,
+    Cpp,
+    C,
+    Go,
+    Shell,
+    Ruby,
+    Batch,
+    CSharp,
+    Resx,
+    Markdown,
+    // 沒有內建對應的副檔名（例如 [extensions] additional 加入的 vue），或沒有副檔名時的小寫檔名
+    Unknown(String),
+}
+
+impl Language {
+    // 依副檔名判斷語言（不分大小寫）
+    pub fn from_path(path: &Path) -> Self {
+        let key = path
+            .extension()
+            .or_else(|| path.file_name())
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match key.as_str() {
+            "rs" => Language::Rust,
+            "py" => Language::Python,
+            "js" => Language::JavaScript,
+            "ts" => Language::TypeScript,
+            "java" => Language::Java,
+            "cpp" => Language::Cpp,
+            "c" | "h" => Language::C,
+            "go" => Language::Go,
+            "sh" => Language::Shell,
+            "rb" => Language::Ruby,
+            "bat" => Language::Batch,
+            "cs" => Language::CSharp,
+            "resx" => Language::Resx,
+            "md" => Language::Markdown,
+            _ => Language::Unknown(key),
+        }
+    }
+
+    // /api/file-content 回應的 Content-Type，沒有對應的語言為 text/plain
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Language::Rust => "text/x-rust; charset=utf-8",
+            Language::Python => "text/x-python; charset=utf-8",
+            Language::JavaScript => "text/javascript; charset=utf-8",
+            Language::TypeScript => "text/x-typescript; charset=utf-8",
+            Language::Java => "text/x-java; charset=utf-8",
+            Language::Cpp => "text/x-c++src; charset=utf-8",
+            Language::C => "text/x-csrc; charset=utf-8",
+            Language::Go => "text/x-go; charset=utf-8",
+            Language::Shell => "text/x-shellscript; charset=utf-8",
+            Language::Ruby => "text/x-ruby; charset=utf-8",
+            Language::CSharp => "text/x-csharp; charset=utf-8",
+            Language::Markdown => "text/markdown; charset=utf-8",
+            Language::Resx => "application/xml; charset=utf-8",
+            Language::Batch | Language::Unknown(_) => "text/plain; charset=utf-8",
+        }
+    }
+
+    // qpr.toml [prompts.by_language] 的鍵，也是 API 輸出的值
+    pub fn name(&self) -> &str {
+        match self {
+            Language::Rust => "rust",
+            Language::Python => "python",
+            Language::JavaScript => "javascript",
+            Language::TypeScript => "typescript",
+            Language::Java => "java",
+            Language::Cpp => "cpp",
+            Language::C => "c",
+            Language::Go => "go",
+            Language::Shell => "shell",
+            Language::Ruby => "ruby",
+            Language::Batch => "batch",
+            Language::CSharp => "csharp",
+            Language::Resx => "resx",
+            Language::Markdown => "markdown",
+            Language::Unknown(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl From<Language> for String {
+    fn from(language: Language) -> Self {
+        language.to_string()
+    }
+}
+
+impl From<String> for Language {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "rust" => Language::Rust,
+            "python" => Language::Python,
+            "javascript" => Language::JavaScript,
+            "typescript" => Language::TypeScript,
+            "java" => Language::Java,
+            "cpp" => Language::Cpp,
+            "c" => Language::C,
+            "go" => Language::Go,
+            "shell" => Language::Shell,
+            "ruby" => Language::Ruby,
+            "batch" => Language::Batch,
+            "csharp" => Language::CSharp,
+            "resx" => Language::Resx,
+            "markdown" => Language::Markdown,
+            _ => Language::Unknown(name),
+        }
+    }
+}
+
+// 編譯期檢查內建副檔名清單：不可為空字串、不可以 `.` 開頭、不可重複
+pub const fn validate_extensions(exts: &[&str]) -> bool {
+    let mut i = 0;
+    while i < exts.len() {
+        let ext = exts[i].as_bytes();
+        if ext.is_empty() || ext[0] == b'.' {
+            return false;
+        }
+        let mut j = i + 1;
+        while j < exts.len() {
+            if bytes_eq(ext, exts[j].as_bytes()) {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+// const fn 中無法使用 `==` 比較字串
+const fn bytes_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+// 依檔名判斷是否為測試檔案（例如 `*_test.go`、`test_*.py`、`*.spec.ts`、`*_spec.rs`），
+// 檔名無法判斷的 Rust 檔案則檢查開頭是否有 `#[cfg(test)]`
+pub fn is_test_file(path: &Path) -> bool {
+    let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let file_name = file_name.to_lowercase();
+    let stem = file_name.split('.').next().unwrap_or("");
+    if stem.starts_with("test_")
+        || stem.ends_with("_test")
+        || stem.ends_with("_tests")
+        || stem.ends_with("_spec")
+        || file_name.contains(".test.")
+        || file_name.contains(".spec.")
+    {
+        return true;
+    }
+
+    if path.extension().is_some_and(|ext| ext == "rs") {
+        let mut head = Vec::with_capacity(TEST_MARKER_SCAN_BYTES);
+        if let Ok(file) = File::open(path) {
+            let _ = file.take(TEST_MARKER_SCAN_BYTES as u64).read_to_end(&mut head);
+        }
+        return String::from_utf8_lossy(&head).contains("#[cfg(test)]");
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    const DEFAULTS: &[&str] = &["rs", "py", "md"];
+
+    // 收集 tracing 輸出的 writer
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // 建立 ExtensionSet 並回傳期間輸出的 debug 以上的紀錄
+    fn build_with_logs(config: &ExtensionsConfig) -> (ExtensionSet, String) {
+        let logs = CapturedLogs::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer({
+                let logs = logs.clone();
+                move || logs.clone()
+            })
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .finish();
+        let set = tracing::subscriber::with_default(subscriber, || ExtensionSet::new(DEFAULTS, config).unwrap());
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        (set, output)
+    }
+
+    fn extensions_config(additional: &[&str], exclude: &[&str]) -> ExtensionsConfig {
+        ExtensionsConfig {
+            additional: additional.iter().map(|ext| ext.to_string()).collect(),
+            exclude: exclude.iter().map(|ext| ext.to_string()).collect(),
+            include_filenames: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn excluding_builtin_extension_warns_and_removes_it() {
+        let (set, logs) = build_with_logs(&extensions_config(&[], &[".MD"]));
+        assert!(set.is_code_file(Path::new("README.md")).is_none());
+        assert!(set.is_code_file(Path::new("main.rs")).is_some());
+        assert!(logs.contains("WARN") && logs.contains("內建清單中的副檔名 md"), "{}", logs);
+    }
+
+    #[test]
+    fn excluding_additional_extension_logs_debug_and_removes_it() {
+        let (set, logs) = build_with_logs(&extensions_config(&["vue", "svelte", "py"], &["vue", "py"]));
+        assert!(set.is_code_file(Path::new("App.vue")).is_none());
+        assert!(set.is_code_file(Path::new("App.svelte")).is_some());
+        assert!(set.is_code_file(Path::new("main.py")).is_none());
+        let lines: Vec<&str> = logs.lines().collect();
+        assert!(lines.iter().any(|l| l.contains("DEBUG") && l.contains("副檔名 vue") && l.contains("additional")), "{}", logs);
+        assert!(!lines.iter().any(|l| l.contains("WARN") && l.contains("vue")), "{}", logs);
+        // 同時是內建與 additional 的副檔名仍視為移除內建副檔名
+        assert!(lines.iter().any(|l| l.contains("WARN") && l.contains("副檔名 py")), "{}", logs);
+        assert!(!logs.contains("svelte"), "{}", logs);
+    }
+
+    #[test]
+    fn excluding_unknown_extension_does_not_warn() {
+        let (set, logs) = build_with_logs(&extensions_config(&[], &["resx"]));
+        assert!(set.is_code_file(Path::new("main.py")).is_some());
+        assert!(logs.is_empty(), "{}", logs);
+    }
+
+    #[test]
+    fn validate_extensions_rejects_empty_dotted_and_duplicate_entries() {
+        assert!(validate_extensions(&["rs", "py"]));
+        assert!(!validate_extensions(&["rs", ""]));
+        assert!(!validate_extensions(&[".rs"]));
+        assert!(!validate_extensions(&["rs", "py", "rs"]));
+    }
+}
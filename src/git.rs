@@ -0,0 +1,332 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tempfile::TempDir;
+use tokio::process::Command;
+
+use crate::error::QprError;
+
+// ===========================
+// Git 儲存庫來源（--git-url）
+// ===========================
+
+// 允許的遠端網址格式
+const ALLOWED_SCHEMES: &[&str] = &["https://", "http://", "ssh://", "git://", "file://"];
+
+// 要複製的 Git 儲存庫與認證資訊
+pub struct GitSource {
+    url: String,
+    token: Option<String>,
+}
+
+// 複製到暫存目錄的儲存庫，drop 時一併刪除暫存目錄
+pub struct GitCheckout {
+    _temp_dir: TempDir,
+    path: PathBuf,
+}
+
+impl GitCheckout {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl GitSource {
+    // 檢查網址格式；token 僅用於 http(s) 網址
+    pub fn new(url: &str, token: Option<String>) -> Result<Self, QprError> {
+        let url = url.trim();
+        let has_scheme = ALLOWED_SCHEMES.iter().any(|scheme| url.starts_with(scheme));
+        // scp 形式：user@host:path
+        let is_scp_like = !url.contains("://")
+            && url
+                .split_once(':')
+                .is_some_and(|(host, path)| host.contains('@') && !host.contains('/') && !path.is_empty());
+        if !has_scheme && !is_scp_like {
+            return Err(QprError::ConfigError(format!(
+                "不支援的 Git 網址：{}（需為 https://、http://、ssh://、git://、file:// 或 user@host:path）",
+                url
+            )));
+        }
+        if url.starts_with('-') || url.chars().any(char::is_whitespace) {
+            return Err(QprError::ConfigError(format!("無效的 Git 網址：{}", url)));
+        }
+
+        Ok(GitSource {
+            url: url.to_string(),
+            token: token.filter(|t| !t.is_empty()),
+        })
+    }
+
+    // 儲存庫名稱，作為暫存目錄下的資料夾名稱
+    fn repo_name(&self) -> String {
+        let name = self
+            .url
+            .trim_end_matches('/')
+            .rsplit(['/', ':'])
+            .next()
+            .unwrap_or("")
+            .trim_end_matches(".git");
+        if name.is_empty() {
+            "repo".to_string()
+        } else {
+            name.to_string()
+        }
+    }
+
+    // http(s) 網址的認證標頭，與 https://<token>@host 相同（token 為帳號、密碼為空）；
+    // 網址已含認證資訊或不是 http(s) 時不加入
+    fn auth_header(&self) -> Option<String> {
+        let token = self.token.as_ref()?;
+        let rest = ["https://", "http://"]
+            .iter()
+            .find_map(|scheme| self.url.strip_prefix(scheme))?;
+        if rest.split('/').next().unwrap_or("").contains('@') {
+            return None;
+        }
+        Some(format!("Authorization: Basic {}", BASE64_STANDARD.encode(format!("{}:", token))))
+    }
+
+    // 錯誤訊息中不可出現 token
+    fn redact(&self, text: &str) -> String {
+        match &self.token {
+            Some(token) => text.replace(token.as_str(), "***"),
+            None => text.to_string(),
+        }
+    }
+
+    // 以 `git clone --depth=1` 複製到新的暫存目錄。
+    // token 以環境變數設定的 http.extraHeader 傳給 git：不會出現在命令列參數（ps）中，
+    // 也不會寫入複製結果的 .git/config（remote.origin.url 不含 token）
+    pub async fn clone_to_temp(&self) -> Result<GitCheckout, QprError> {
+        let temp_dir = tempfile::Builder::new().prefix("qpr-").tempdir()?;
+        let path = temp_dir.path().join(self.repo_name());
+
+        let mut command = Command::new("git");
+        command
+            .arg("clone")
+            .arg("--depth=1")
+            .arg("--")
+            .arg(&self.url)
+            .arg(&path)
+            // 認證失敗時不要停下來等待輸入帳號密碼
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null());
+        if let Some(header) = self.auth_header() {
+            command
+                .env("GIT_CONFIG_COUNT", "1")
+                .env("GIT_CONFIG_KEY_0", "http.extraHeader")
+                .env("GIT_CONFIG_VALUE_0", header);
+        }
+        let output = command
+            .output()
+            .await
+            .map_err(|e| QprError::GitError(format!("無法執行 git（請確認已安裝 git）：{}", e)))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(QprError::GitError(format!(
+                "無法複製儲存庫 {}：{}",
+                self.redact(&self.url),
+                self.redact(stderr.trim())
+            )));
+        }
+
+        Ok(GitCheckout {
+            _temp_dir: temp_dir,
+            path,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::AsyncWriteExt;
+    use warp::Filter;
+
+    // 在 dir 中執行 git，失敗時讓測試中止
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=qpr", "-c", "user.email=qpr@example.com", "-c", "init.defaultBranch=main"])
+            .args(args)
+            .current_dir(dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    // 含一個提交的本機 bare 儲存庫 <fixture>/project.git
+    fn bare_repository(fixture: &Path) -> PathBuf {
+        let work = fixture.join("work");
+        fs::create_dir_all(work.join("src")).unwrap();
+        fs::write(work.join("src/main.rs"), "fn main() {}\n").unwrap();
+        git(&work, &["init", "--quiet"]);
+        git(&work, &["add", "."]);
+        git(&work, &["commit", "--quiet", "-m", "initial"]);
+        git(fixture, &["clone", "--quiet", "--bare", "work", "project.git"]);
+        fixture.join("project.git")
+    }
+
+    fn file_url(path: &Path) -> String {
+        format!("file://{}", path.display())
+    }
+
+    #[tokio::test]
+    async fn clones_local_bare_repository_into_temp_dir() {
+        let fixture = TempDir::new().unwrap();
+        let source = GitSource::new(&file_url(&bare_repository(fixture.path())), None).unwrap();
+
+        let checkout = source.clone_to_temp().await.unwrap();
+        let path = checkout.path().to_path_buf();
+        assert_eq!(path.file_name().unwrap(), "project");
+        assert_eq!(fs::read_to_string(path.join("src/main.rs")).unwrap(), "fn main() {}\n");
+
+        // drop 時刪除暫存目錄
+        drop(checkout);
+        assert!(!path.exists());
+    }
+
+    // 以 `git http-backend`（CGI）提供 fixture 目錄中儲存庫的 smart HTTP 伺服器；
+    // 請求沒有 expected_auth 的 Authorization 標頭時回傳 401。回傳伺服器網址與收到的 Authorization 標頭
+    async fn serve_git_http(fixture: &Path, expected_auth: &'static str) -> (String, Arc<Mutex<Vec<Option<String>>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let project_root = fixture.to_path_buf();
+        let routes = warp::method()
+            .and(warp::path::full())
+            .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+            .and(warp::header::headers_cloned())
+            .and(warp::body::bytes())
+            .then({
+                let received = received.clone();
+                move |method: warp::http::Method, path: warp::path::FullPath, query: String, headers: warp::http::HeaderMap, body: warp::hyper::body::Bytes| {
+                    let received = received.clone();
+                    let project_root = project_root.clone();
+                    async move {
+                        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+                        let auth = header("authorization");
+                        received.lock().unwrap().push(auth.clone());
+                        if auth.as_deref() != Some(expected_auth) {
+                            return warp::http::Response::builder()
+                                .status(401)
+                                .header("WWW-Authenticate", "Basic realm=\"qpr\"")
+                                .body(Vec::new())
+                                .unwrap();
+                        }
+                        let mut child = Command::new("git")
+                            .arg("http-backend")
+                            .env("GIT_PROJECT_ROOT", &project_root)
+                            .env("GIT_HTTP_EXPORT_ALL", "1")
+                            .env("REQUEST_METHOD", method.as_str())
+                            .env("PATH_INFO", path.as_str())
+                            .env("QUERY_STRING", query)
+                            .env("CONTENT_TYPE", header("content-type").unwrap_or_default())
+                            .env("HTTP_CONTENT_ENCODING", header("content-encoding").unwrap_or_default())
+                            .env("GIT_PROTOCOL", header("git-protocol").unwrap_or_default())
+                            .stdin(Stdio::piped())
+                            .stdout(Stdio::piped())
+                            .spawn()
+                            .unwrap();
+                        child.stdin.take().unwrap().write_all(&body).await.unwrap();
+                        let output = child.wait_with_output().await.unwrap();
+
+                        // CGI 輸出：標頭與本文以空行分隔，Status 標頭為 HTTP 狀態碼
+                        let stdout = output.stdout;
+                        let end = stdout.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+                        let mut response = warp::http::Response::builder();
+                        for line in String::from_utf8_lossy(&stdout[..end]).lines() {
+                            let (name, value) = line.split_once(':').unwrap();
+                            response = match name {
+                                "Status" => response.status(value.trim()[..3].parse::<u16>().unwrap()),
+                                _ => response.header(name, value.trim()),
+                            };
+                        }
+                        response.body(stdout[end + 4..].to_vec()).unwrap()
+                    }
+                }
+            });
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}", addr), received)
+    }
+
+    #[tokio::test]
+    async fn token_is_sent_as_header_and_not_stored_in_checkout() {
+        let fixture = TempDir::new().unwrap();
+        bare_repository(fixture.path());
+        // base64("secret-token:")
+        let (base_url, received) = serve_git_http(fixture.path(), "Basic c2VjcmV0LXRva2VuOg==").await;
+        let url = format!("{}/project.git", base_url);
+        let source = GitSource::new(&url, Some("secret-token".to_string())).unwrap();
+
+        let checkout = source.clone_to_temp().await.unwrap();
+        assert_eq!(fs::read_to_string(checkout.path().join("src/main.rs")).unwrap(), "fn main() {}\n");
+        // 每個請求都帶有認證標頭
+        let received = received.lock().unwrap().clone();
+        assert!(!received.is_empty());
+        assert!(received.iter().all(|auth| auth.as_deref() == Some("Basic c2VjcmV0LXRva2VuOg==")));
+        // 設定檔中的遠端網址不含 token
+        let git_config = fs::read_to_string(checkout.path().join(".git/config")).unwrap();
+        assert!(git_config.contains(&format!("url = {}", url)), "{}", git_config);
+        assert!(!git_config.contains("secret-token"), "{}", git_config);
+    }
+
+    #[tokio::test]
+    async fn clone_error_is_reported_without_token() {
+        // 沒有服務的本機埠號，連線立即失敗
+        let source = GitSource::new("http://127.0.0.1:9/missing.git", Some("secret-token".to_string())).unwrap();
+
+        let Err(QprError::GitError(message)) = source.clone_to_temp().await else {
+            panic!("複製不存在的儲存庫應該失敗");
+        };
+        assert!(message.contains("missing.git"), "{}", message);
+        assert!(!message.contains("secret-token"), "{}", message);
+    }
+
+    #[test]
+    fn validates_url_format() {
+        for url in [
+            "https://github.com/owner/repo.git",
+            "ssh://git@github.com/owner/repo",
+            "git@github.com:owner/repo.git",
+            "file:///srv/git/repo.git",
+        ] {
+            assert!(GitSource::new(url, None).is_ok(), "{}", url);
+        }
+        for url in ["ftp://example.com/repo", "/srv/git/repo.git", "github.com:repo", "https://host/a b", "-uhttps://x"] {
+            assert!(GitSource::new(url, None).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn token_header_is_sent_to_http_urls_only() {
+        let header = |url: &str| GitSource::new(url, Some("t0ken".to_string())).unwrap().auth_header();
+        // base64("t0ken:")
+        assert_eq!(header("https://github.com/owner/repo").as_deref(), Some("Authorization: Basic dDBrZW46"));
+        assert_eq!(header("http://host/repo").as_deref(), Some("Authorization: Basic dDBrZW46"));
+        // 已含認證資訊或非 http(s) 網址時不加入
+        assert_eq!(header("https://user@github.com/owner/repo"), None);
+        assert_eq!(header("git@github.com:owner/repo.git"), None);
+        assert_eq!(header("file:///srv/git/repo.git"), None);
+        // 空字串視為沒有 token
+        let source = GitSource::new("https://github.com/owner/repo", Some(String::new())).unwrap();
+        assert_eq!(source.auth_header(), None);
+
+        let source = GitSource::new("https://github.com/owner/repo", Some("t0ken".to_string())).unwrap();
+        assert_eq!(
+            source.redact("unable to access 'https://t0ken@github.com/owner/repo/'"),
+            "unable to access 'https://***@github.com/owner/repo/'"
+        );
+    }
+
+    #[test]
+    fn repo_name_strips_git_suffix() {
+        let name = |url: &str| GitSource::new(url, None).unwrap().repo_name();
+        assert_eq!(name("https://github.com/owner/repo.git"), "repo");
+        assert_eq!(name("https://github.com/owner/repo/"), "repo");
+        assert_eq!(name("git@github.com:tool.git"), "tool");
+    }
+}
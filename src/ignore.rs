@@ -0,0 +1,127 @@
+use globset::{GlobBuilder, GlobMatcher};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// ===========================
+// 忽略規則（.qprignore / .gitignore）
+// ===========================
+
+// 每個目錄中會讀取的忽略檔案，後者優先
+const IGNORE_FILE_NAMES: &[&str] = &[".gitignore", ".qprignore"];
+
+// 單一忽略規則
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    base: PathBuf,
+    matcher: GlobMatcher,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreRule {
+    // 解析一行 .gitignore 格式的規則，空行與註解回傳 None
+    fn parse(line: &str, base: &Path) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+
+        // 含有 `/` 的規則相對於所在目錄，否則可匹配任意深度
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        let glob = if anchored {
+            pattern.to_string()
+        } else {
+            format!("**/{}", pattern)
+        };
+
+        let matcher = GlobBuilder::new(&glob)
+            .literal_separator(true)
+            .build()
+            .ok()?
+            .compile_matcher();
+
+        Some(IgnoreRule {
+            base: base.to_path_buf(),
+            matcher,
+            negated,
+            dir_only,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        match path.strip_prefix(&self.base) {
+            Ok(relative) => self.matcher.is_match(relative),
+            Err(_) => false,
+        }
+    }
+}
+
+// 累積自所有祖先目錄的忽略規則，於遞迴走訪時逐層傳遞
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreSet {
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreSet {
+    // 以內建的預設規則建立，規則相對於專案根目錄
+    pub fn new(root: &Path, default_patterns: &[&str]) -> Self {
+        let rules = default_patterns
+            .iter()
+            .filter_map(|pattern| IgnoreRule::parse(pattern, root))
+            .collect();
+        IgnoreSet { rules }
+    }
+
+    // 從專案根目錄一路累積到 `path` 的上一層目錄的規則
+    pub fn from_ancestors(root: &Path, path: &Path, default_patterns: &[&str]) -> Self {
+        let mut set = IgnoreSet::new(root, default_patterns);
+        if let Ok(relative) = path.strip_prefix(root) {
+            let mut current = root.to_path_buf();
+            for component in relative.parent().into_iter().flat_map(|p| p.components()) {
+                set = set.with_dir(&current);
+                current.push(component);
+            }
+            if current != path {
+                set = set.with_dir(&current);
+            }
+        }
+        set
+    }
+
+    // 回傳加入 `dir` 內 .gitignore 與 .qprignore 規則後的新集合
+    pub fn with_dir(&self, dir: &Path) -> Self {
+        let mut set = self.clone();
+        for file_name in IGNORE_FILE_NAMES {
+            if let Ok(content) = fs::read_to_string(dir.join(file_name)) {
+                set.rules
+                    .extend(content.lines().filter_map(|line| IgnoreRule::parse(line, dir)));
+            }
+        }
+        set
+    }
+
+    // 依 .gitignore 語意判斷是否忽略，最後一條符合的規則決定結果
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
@@ -0,0 +1,1693 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::chunker::SmartChunker;
+use crate::config::{self, QprConfig};
+use crate::debug_log;
+use crate::error::QprError;
+use crate::metrics::Metrics;
+use crate::progress_bar;
+use crate::timeline;
+use crate::{ANTHROPIC_VERSION, CLAUDE_MAX_TOKENS, MAX_RETRY_DELAY_MS, RETRYABLE_STATUS_CODES, RETRY_BASE_DELAY_MS};
+
+// ===========================
+// LLM 後端
+// ===========================
+
+// 可選用的 LLM 後端種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[value(name = "openai")]
+    OpenAi,
+    Llama,
+    Claude,
+    // Mistral AI，API 與 OpenAI 相容
+    Mistral,
+    // 不連線的測試用後端，回覆由輸入的雜湊決定
+    Mock,
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendKind::OpenAi => write!(f, "openai"),
+            BackendKind::Llama => write!(f, "llama"),
+            BackendKind::Claude => write!(f, "claude"),
+            BackendKind::Mistral => write!(f, "mistral"),
+            BackendKind::Mock => write!(f, "mock"),
+        }
+    }
+}
+
+// 所有 LLM 後端共同的介面
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    // 後端名稱，用於輸出訊息
+    fn name(&self) -> &'static str;
+
+    // 為單一檔案內容生成摘要，prompt_override 為目錄的 .qpr_prompt，None 時使用全域提示語；
+    // extension 為檔案的副檔名（不含 `.`），用於依函式邊界分段
+    async fn summarize_file(
+        &self,
+        file_content: &str,
+        extension: &str,
+        prompt_override: Option<&str>,
+    ) -> Result<String, QprError>;
+
+    // 與 summarize_file 相同，但在生成時將回覆的片段依序送到 tx，供網頁即時顯示；
+    // 不支援串流的後端在完成後一次送出整份摘要
+    async fn summarize_file_streaming(
+        &self,
+        file_content: &str,
+        extension: &str,
+        prompt_override: Option<&str>,
+        tx: mpsc::Sender<String>,
+    ) -> Result<String, QprError> {
+        let summary = self.summarize_file(file_content, extension, prompt_override).await?;
+        let _ = tx.send(summary.clone()).await;
+        Ok(summary)
+    }
+
+    // 過濾資料夾，回傳包含 analysis_key 的 JSON 字串
+    async fn analyze_folders(
+        &self,
+        folders: &str,
+        extra_folders: &str,
+    ) -> Result<String, QprError>;
+
+    // 以已組好的提示語取得回覆（用於總覽等非單一檔案的請求）
+    async fn complete(&self, prompt: &str) -> Result<String, QprError>;
+
+    // 目前為止所有請求累計使用的 token 數
+    fn total_tokens_used(&self) -> u64;
+
+    // 此後端的並行請求數上限，呼叫端在送出請求前取得 permit
+    fn semaphore(&self) -> Arc<Semaphore>;
+}
+
+// 後端自己的並行數上限（依 concurrency_per_backend 在建立後端時決定）
+pub struct ConcurrencyLimit {
+    semaphore: Arc<Semaphore>,
+    // 目前有效的上限，降低時同步收回 semaphore 的 permit
+    limit: AtomicUsize,
+}
+
+impl ConcurrencyLimit {
+    fn new(limit: u32) -> Self {
+        ConcurrencyLimit {
+            semaphore: Arc::new(Semaphore::new(limit as usize)),
+            limit: AtomicUsize::new(limit as usize),
+        }
+    }
+
+    // 降低一個並行數（最低 1）；permit 都在使用中時，等其中一個釋放後再收回
+    fn decrease(&self) {
+        let Ok(previous) = self
+            .limit
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |limit| (limit > 1).then(|| limit - 1))
+        else {
+            return;
+        };
+        let semaphore = self.semaphore.clone();
+        tokio::spawn(async move {
+            if let Ok(permit) = semaphore.acquire_owned().await {
+                permit.forget();
+            }
+        });
+        tracing::warn!("LLM 回傳錯誤狀態碼，並行數降為 {}", previous - 1);
+    }
+}
+
+// 所有摘要任務共用同一個 HTTP client 的連線池；每個主機保留的閒置連線數
+const HTTP_POOL_MAX_IDLE_PER_HOST: usize = 20;
+
+// 依設定建立對應的 LLM 後端
+pub fn create_backend(
+    config: &QprConfig,
+    metrics: Arc<Metrics>,
+) -> Result<Arc<dyn LlmBackend>, QprError> {
+    let settings = LlmSettings::from_config(config, metrics);
+    let client = Client::builder()
+        .pool_max_idle_per_host(HTTP_POOL_MAX_IDLE_PER_HOST)
+        .connect_timeout(Duration::from_millis(config.connect_timeout_ms))
+        .timeout(Duration::from_millis(config.request_timeout_ms()))
+        .build()?;
+    let limit = ConcurrencyLimit::new(config.backend_concurrency());
+    let backend: Arc<dyn LlmBackend> = match config.backend {
+        BackendKind::OpenAi => {
+            let api_key = config::resolve_api_key(config)?;
+            Arc::new(OpenAiBackend {
+                client,
+                api_key,
+                settings,
+                limit,
+            })
+        }
+        BackendKind::Llama => Arc::new(LlamaBackend { client, settings, limit }),
+        BackendKind::Claude => {
+            let api_key = config::resolve_api_key(config)?;
+            Arc::new(ClaudeBackend {
+                client,
+                api_key,
+                settings,
+                limit,
+            })
+        }
+        BackendKind::Mistral => {
+            let api_key = config::resolve_api_key(config)?;
+            Arc::new(MistralBackend(OpenAiBackend {
+                client,
+                api_key,
+                settings,
+                limit,
+            }))
+        }
+        BackendKind::Mock => Arc::new(MockLlmBackend::new(config, settings.metrics)),
+    };
+    Ok(backend)
+}
+
+// --extra-context 的專案說明，{} 為說明內容
+const PROJECT_CONTEXT_PROMPT: &str = "Project context: \n{}";
+// llama 與資料夾分析提示語的系統段落前綴
+const SYSTEM_PREFIX: &str = "SYSTEM:";
+
+// 健康檢查送出的最小請求，確認金鑰、網址與模型名稱都可用
+const HEALTH_CHECK_PROMPT: &str = "Summarize the following text in one sentence: hello world";
+
+// /api/health 與啟動時檢查的結果
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum HealthReport {
+    Ok { backend: &'static str, latency_ms: u64 },
+    Error { backend: &'static str, message: String },
+}
+
+impl HealthReport {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, HealthReport::Ok { .. })
+    }
+}
+
+// 對後端送出一個最小的請求，回傳是否成功與所花的時間
+pub async fn check_health(backend: &dyn LlmBackend) -> HealthReport {
+    let started = Instant::now();
+    match backend.complete(HEALTH_CHECK_PROMPT).await {
+        Ok(_) => HealthReport::Ok {
+            backend: backend.name(),
+            latency_ms: started.elapsed().as_millis() as u64,
+        },
+        Err(e) => HealthReport::Error {
+            backend: backend.name(),
+            message: e.to_string(),
+        },
+    }
+}
+
+// API 金鑰放在哪個標頭：OpenAI 使用 `Authorization: Bearer`，Azure OpenAI 使用 `api-key`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiKeyHeader {
+    Bearer,
+    Azure,
+}
+
+// 後端共用的請求設定
+#[derive(Debug, Clone)]
+pub struct LlmSettings {
+    pub url: String,
+    pub api_key_header: ApiKeyHeader,
+    pub model: String,
+    // 分析資料夾時使用的模型
+    pub folder_analysis_model: String,
+    pub max_retries: u8,
+    pub language: String,
+    pub file_summary_prompt: String,
+    pub file_rollup_prompt: String,
+    pub folder_analysis_prompt: String,
+    // 加在檔案摘要與資料夾分析提示語開頭的專案說明
+    pub project_context: Option<String>,
+    // 單一請求的提示語 token 上限，超過時將檔案分段摘要
+    pub max_tokens_per_request: usize,
+    // llama 每段程式碼的 token 上限（context window 的 60%）
+    pub chunk_tokens: usize,
+    // 所有請求累計使用的 token 數
+    pub tokens_used: Arc<AtomicU64>,
+    // 請求次數與延遲（/metrics）
+    pub metrics: Arc<Metrics>,
+}
+
+impl LlmSettings {
+    fn from_config(config: &QprConfig, metrics: Arc<Metrics>) -> Self {
+        LlmSettings {
+            url: config.llm_url(),
+            api_key_header: if config.azure_url().is_some() {
+                ApiKeyHeader::Azure
+            } else {
+                ApiKeyHeader::Bearer
+            },
+            model: config.model_name(),
+            folder_analysis_model: config.folder_analysis_model_name(),
+            max_retries: config.max_retries,
+            language: config.language.prompt_name().to_string(),
+            file_summary_prompt: config.file_summary_prompt(),
+            file_rollup_prompt: config.file_rollup_prompt(),
+            folder_analysis_prompt: config.folder_analysis_prompt(),
+            project_context: config.project_context.clone(),
+            // 設定的上限超過模型的 context window 時，以 context window 為準
+            max_tokens_per_request: config.max_tokens_per_request.min(config.chunk_tokens()),
+            chunk_tokens: config.chunk_tokens(),
+            tokens_used: Arc::new(AtomicU64::new(0)),
+            metrics,
+        }
+    }
+
+    // 目錄有 .qpr_prompt 時使用其內容，否則使用全域的檔案摘要提示語
+    fn summary_template<'a>(&'a self, prompt_override: Option<&'a str>) -> &'a str {
+        prompt_override.unwrap_or(&self.file_summary_prompt)
+    }
+
+    // 將摘要提示語中的佔位符替換為語言與程式碼內容
+    fn build_summary_prompt(&self, template: &str, content: &str) -> String {
+        self.with_project_context(
+            template
+                .replace("{language}", &self.language)
+                .replace("{}", content),
+        )
+    }
+
+    // 在提示語開頭加上專案說明；以 SYSTEM: 開頭的提示語（llama 與資料夾分析）加在 SYSTEM: 之後，維持對話格式
+    fn with_project_context(&self, prompt: String) -> String {
+        let Some(context) = &self.project_context else {
+            return prompt;
+        };
+        let context = PROJECT_CONTEXT_PROMPT.replace("{}", context);
+        match prompt.strip_prefix(SYSTEM_PREFIX) {
+            Some(rest) => format!("{}{}\n\n{}", SYSTEM_PREFIX, context, rest),
+            None => format!("{}\n\n{}", context, prompt),
+        }
+    }
+
+    // OpenAI、Claude 與 dry-run：提示語超過 token 上限時依空行與行數分段，每段一個提示語
+    fn token_chunked_prompts(&self, prompt_override: Option<&str>, content: &str) -> Vec<String> {
+        let template = self.summary_template(prompt_override);
+        split_file_for_summary(self, template, content)
+            .iter()
+            .map(|chunk| self.build_summary_prompt(template, chunk))
+            .collect()
+    }
+
+    // Llama：以整行為單位將內容切成最多 chunk_tokens 個 token 的片段，盡量在函式或類別的開頭切開
+    fn line_chunked_prompts(&self, prompt_override: Option<&str>, content: &str, extension: &str) -> Vec<String> {
+        let template = self.summary_template(prompt_override);
+        SmartChunker::chunk(content, extension, self.chunk_tokens, |text| count_tokens(text, &self.model))
+            .iter()
+            .map(|chunk| self.build_summary_prompt(template, chunk))
+            .collect()
+    }
+
+    fn build_folder_prompt(&self, folders: &str, extra_folders: &str) -> String {
+        self.with_project_context(
+            self.folder_analysis_prompt
+                .replace("{language}", &self.language)
+                .replace("{folders}", folders)
+                .replace("{extra_folders}", extra_folders),
+        )
+    }
+
+    fn add_tokens_used(&self, tokens: u64) {
+        self.tokens_used.fetch_add(tokens, Ordering::Relaxed);
+    }
+}
+
+// ===========================
+// GPT 請求和回應結構
+// ===========================
+
+#[derive(Serialize, Deserialize)]
+struct GPTRequest {
+    model: String,
+    messages: Vec<Message>,
+    // 以 SSE 逐段回傳（summarize_file_with_gpt_streaming）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+// 串流回應中的單一 SSE 事件（data: {...}）
+#[derive(Deserialize)]
+struct GPTStreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+}
+
+#[derive(Deserialize)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: Delta,
+}
+
+#[derive(Default, Deserialize)]
+struct Delta {
+    content: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Message {
+    role: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct GPTResponse {
+    choices: Vec<Choice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize)]
+struct Usage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+    total_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+// 定義用於解析 GPT 分析回應的結構，analysis_key 為相對於專案根目錄的資料夾路徑
+#[derive(Serialize, Deserialize)]
+struct GPTAnalysis {
+    analysis_key: Vec<String>,
+}
+
+// 以模型對應的 tokenizer 計算文字的 token 數，未知的模型使用 cl100k_base
+pub fn count_tokens(text: &str, model: &str) -> usize {
+    let bpe = match get_tokenizer(model) {
+        Some(Tokenizer::O200kBase) => tiktoken_rs::o200k_base_singleton(),
+        Some(Tokenizer::P50kBase) => tiktoken_rs::p50k_base_singleton(),
+        Some(Tokenizer::P50kEdit) => tiktoken_rs::p50k_edit_singleton(),
+        Some(Tokenizer::R50kBase) | Some(Tokenizer::Gpt2) => tiktoken_rs::r50k_base_singleton(),
+        _ => tiktoken_rs::cl100k_base_singleton(),
+    };
+    bpe.encode_ordinary(text).len()
+}
+
+// 將內容切成每段不超過 max_tokens 的片段：先依空行分段，單段過大時再依行切割
+fn split_by_tokens(content: &str, max_tokens: usize, model: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    for block in content.split("\n\n") {
+        if count_tokens(block, model) <= max_tokens {
+            pieces.push(block.to_string());
+        } else {
+            pieces.extend(block.lines().map(str::to_string));
+        }
+    }
+
+    // 將相鄰的片段合併，直到接近上限
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0;
+    for piece in pieces {
+        let piece_tokens = count_tokens(&piece, model) + 1;
+        if !current.is_empty() && current_tokens + piece_tokens > max_tokens {
+            chunks.push(std::mem::take(&mut current));
+            current_tokens = 0;
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(&piece);
+        current_tokens += piece_tokens;
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+// 從 LLM 回應中取出 analysis_key 清單，容許前後夾雜說明文字或程式碼區塊
+pub fn parse_folder_analysis(
+    response: &str,
+) -> Result<Vec<String>, QprError> {
+    let json_str = extract_analysis_json(response)
+        .ok_or_else(|| QprError::InvalidResponse("無法從回應中提取 JSON 結構".to_string()))?;
+    let analysis: GPTAnalysis = serde_json::from_str(json_str)?;
+    Ok(analysis.analysis_key)
+}
+
+// 尋找包含 "analysis_key" 的 { ... } 結構
+fn extract_analysis_json(content: &str) -> Option<&str> {
+    let key = content.find("\"analysis_key\"")?;
+    let start = content[..key].rfind('{')?;
+    let end = key + content[key..].find('}')? + 1;
+    Some(&content[start..end])
+}
+
+// ===========================
+// 重試機制
+// ===========================
+
+// 第 attempt 次重試前的等待時間：base_delay_ms * 2^attempt 加上最多 base_delay_ms 的隨機抖動，
+// 以飽和運算計算並限制在 MAX_RETRY_DELAY_MS 以內
+fn retry_delay_ms(attempt: u8, base_delay_ms: u64) -> u64 {
+    base_delay_ms
+        .saturating_mul(2u64.saturating_pow(attempt as u32))
+        .saturating_add(rand::random_range(0..=base_delay_ms))
+        .min(MAX_RETRY_DELAY_MS)
+}
+
+// 遇到暫時性錯誤（429、500、503）時以指數退避加隨機抖動重新執行
+async fn retry_with_backoff<F, Fut, T>(
+    mut f: F,
+    max_retries: u8,
+    base_delay_ms: u64,
+) -> Result<T, QprError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, QprError>>,
+{
+    let mut attempt: u8 = 0;
+    loop {
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(err) => {
+                let status = match &err {
+                    QprError::LlmError { status, .. } => Some(*status),
+                    _ => None,
+                };
+                timeline::record_api_error(status, attempt as u32 + 1);
+                let retryable = matches!(
+                    &err,
+                    QprError::LlmError { status, .. } if RETRYABLE_STATUS_CODES.contains(status)
+                );
+                if !retryable || attempt >= max_retries {
+                    return Err(err);
+                }
+
+                let delay_ms = retry_delay_ms(attempt, base_delay_ms);
+                tracing::warn!("API 暫時性錯誤（{}），{} 毫秒後重試（第 {} 次）", err, delay_ms, attempt + 1);
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+// ===========================
+// OpenAI 後端
+// ===========================
+
+pub struct OpenAiBackend {
+    client: Client,
+    api_key: String,
+    settings: LlmSettings,
+    limit: ConcurrencyLimit,
+}
+
+#[async_trait]
+impl LlmBackend for OpenAiBackend {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    async fn summarize_file(
+        &self,
+        file_content: &str,
+        _extension: &str,
+        prompt_override: Option<&str>,
+    ) -> Result<String, QprError> {
+        summarize_file_with_gpt(&self.client, &self.api_key, &self.settings, file_content, prompt_override).await
+    }
+
+    async fn summarize_file_streaming(
+        &self,
+        file_content: &str,
+        _extension: &str,
+        prompt_override: Option<&str>,
+        tx: mpsc::Sender<String>,
+    ) -> Result<String, QprError> {
+        summarize_file_with_gpt_streaming(&self.client, &self.api_key, &self.settings, file_content, prompt_override, tx)
+            .await
+    }
+
+    async fn analyze_folders(
+        &self,
+        folders: &str,
+        extra_folders: &str,
+    ) -> Result<String, QprError> {
+        analyze_folders_with_gpt(&self.client, &self.api_key, &self.settings, folders, extra_folders)
+            .await
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String, QprError> {
+        chat_with_gpt(&self.client, &self.api_key, &self.settings, prompt.to_string()).await
+    }
+
+    fn total_tokens_used(&self) -> u64 {
+        self.settings.tokens_used.load(Ordering::Relaxed)
+    }
+
+    fn semaphore(&self) -> Arc<Semaphore> {
+        self.limit.semaphore.clone()
+    }
+}
+
+// 送出 GPT 請求並取出第一個回覆內容，同時累計 token 用量
+async fn send_gpt_request(
+    client: &Client,
+    settings: &LlmSettings,
+    api_key: &str,
+    request: &GPTRequest,
+) -> Result<String, QprError> {
+    // Azure 以部署名稱決定模型，請求中的 model 欄位只是佔位值
+    let builder = client.post(&settings.url);
+    let builder = match settings.api_key_header {
+        ApiKeyHeader::Bearer => builder.header("Authorization", format!("Bearer {}", api_key)),
+        ApiKeyHeader::Azure => builder.header("api-key", api_key),
+    };
+    let (status, res_text) = settings
+        .metrics
+        .time_request(async {
+            let res = builder.json(request).send().await?;
+            let status = res.status();
+            Ok::<_, QprError>((status, res.text().await?))
+        })
+        .await?;
+    tracing::debug!(status = status.as_u16(), bytes = res_text.len(), "GPT 回應");
+    debug_log::record_raw_response(&res_text);
+    if !status.is_success() {
+        return Err(QprError::LlmError {
+            status: status.as_u16(),
+            body: res_text,
+        });
+    }
+
+    let res_json: GPTResponse = serde_json::from_str(&res_text)?;
+
+    if let Some(first_choice) = res_json.choices.first() {
+        let message = &first_choice.message.content;
+        // API 未回傳 usage 時自行計算提示語與回覆的 token 數
+        let tokens = match &res_json.usage {
+            Some(usage) => {
+                tracing::debug!(
+                    prompt_tokens = usage.prompt_tokens,
+                    completion_tokens = usage.completion_tokens,
+                    "GPT token 用量"
+                );
+                usage.total_tokens
+            }
+            None => request
+                .messages
+                .iter()
+                .chain(std::iter::once(&first_choice.message))
+                .map(|m| count_tokens(&m.content, &settings.model) as u64)
+                .sum(),
+        };
+        settings.add_tokens_used(tokens);
+        return Ok(message.clone());
+    }
+
+    Err(QprError::InvalidResponse("無法從 GPT 回應中提取內容".to_string()))
+}
+
+// 以單一使用者訊息呼叫 GPT，遇到暫時性錯誤時重試
+async fn chat_with_gpt(
+    client: &Client,
+    api_key: &str,
+    settings: &LlmSettings,
+    prompt: String,
+) -> Result<String, QprError> {
+    chat_with_gpt_model(client, api_key, settings, &settings.model, prompt).await
+}
+
+// 與 chat_with_gpt 相同，但指定模型
+async fn chat_with_gpt_model(
+    client: &Client,
+    api_key: &str,
+    settings: &LlmSettings,
+    model: &str,
+    prompt: String,
+) -> Result<String, QprError> {
+    let request = GPTRequest {
+        model: model.to_string(),
+        messages: vec![Message {
+            role: "user".to_string(),
+            content: prompt,
+        }],
+        stream: false,
+    };
+
+    retry_with_backoff(
+        || send_gpt_request(client, settings, api_key, &request),
+        settings.max_retries,
+        RETRY_BASE_DELAY_MS,
+    )
+    .await
+}
+
+// 依設定的後端組出摘要單一檔案時各段的提示語，不送出請求（/api/prompt-preview）；
+// 分段時後端另外會以 file_rollup_prompt 合併各段摘要
+pub fn build_summary_prompts(
+    content: &str,
+    extension: &str,
+    prompt_override: Option<&str>,
+    config: &QprConfig,
+) -> Vec<String> {
+    let settings = LlmSettings::from_config(config, Arc::default());
+    match config.backend {
+        BackendKind::OpenAi | BackendKind::Claude | BackendKind::Mistral => {
+            settings.token_chunked_prompts(prompt_override, content)
+        }
+        BackendKind::Llama => settings.line_chunked_prompts(prompt_override, content, extension),
+        // mock 後端不分段
+        BackendKind::Mock => vec![settings.build_summary_prompt(settings.summary_template(prompt_override), content)],
+    }
+}
+
+// 依 token 上限切割檔案內容，提示語未超過上限時只回傳一段
+fn split_file_for_summary(settings: &LlmSettings, template: &str, file_content: &str) -> Vec<String> {
+    let prompt = settings.build_summary_prompt(template, file_content);
+    if count_tokens(&prompt, &settings.model) <= settings.max_tokens_per_request {
+        return vec![file_content.to_string()];
+    }
+
+    // 扣除提示語本身的 token 數，剩下的才是每段程式碼可用的額度
+    let template_tokens = count_tokens(
+        &settings.build_summary_prompt(template, ""),
+        &settings.model,
+    );
+    let chunk_tokens = settings.max_tokens_per_request.saturating_sub(template_tokens).max(1);
+    split_by_tokens(file_content, chunk_tokens, &settings.model)
+}
+
+// GPT 過濾檔案並生成摘要，超過 token 上限時分段摘要後再合併
+#[tracing::instrument(skip_all, fields(bytes = file_content.len()))]
+async fn summarize_file_with_gpt(
+    client: &Client,
+    api_key: &str,
+    settings: &LlmSettings,
+    file_content: &str,
+    prompt_override: Option<&str>,
+) -> Result<String, QprError> {
+    let mut prompts = settings.token_chunked_prompts(prompt_override, file_content);
+    if prompts.len() <= 1 {
+        let prompt = prompts.pop().unwrap_or_default();
+        return chat_with_gpt(client, api_key, settings, prompt).await;
+    }
+
+    let mut summaries = Vec::new();
+    let chunk_progress = progress_bar::start_chunks(prompts.len());
+    for prompt in prompts {
+        summaries.push(chat_with_gpt(client, api_key, settings, prompt).await?);
+        chunk_progress.inc();
+    }
+
+    // 合併所有摘要為一個大段落，進行最終的摘要調用
+    let final_prompt = settings.build_summary_prompt(&settings.file_rollup_prompt, &summaries.join(" "));
+    chat_with_gpt(client, api_key, settings, final_prompt).await
+}
+
+// 與 summarize_file_with_gpt 相同，但最後一次請求（未分段時為唯一的請求，分段時為合併請求）以串流送出，
+// 回覆的片段依序送到 tx
+#[tracing::instrument(skip_all, fields(bytes = file_content.len()))]
+async fn summarize_file_with_gpt_streaming(
+    client: &Client,
+    api_key: &str,
+    settings: &LlmSettings,
+    file_content: &str,
+    prompt_override: Option<&str>,
+    tx: mpsc::Sender<String>,
+) -> Result<String, QprError> {
+    let mut prompts = settings.token_chunked_prompts(prompt_override, file_content);
+    let final_prompt = if prompts.len() <= 1 {
+        prompts.pop().unwrap_or_default()
+    } else {
+        let mut summaries = Vec::new();
+        let chunk_progress = progress_bar::start_chunks(prompts.len());
+        for prompt in prompts {
+            summaries.push(chat_with_gpt(client, api_key, settings, prompt).await?);
+            chunk_progress.inc();
+        }
+        settings.build_summary_prompt(&settings.file_rollup_prompt, &summaries.join(" "))
+    };
+
+    let request = GPTRequest {
+        model: settings.model.clone(),
+        messages: vec![Message {
+            role: "user".to_string(),
+            content: final_prompt,
+        }],
+        stream: true,
+    };
+    // 只有收到第一個片段前的錯誤（HTTP 狀態碼）會重試，不會重複送出已送出的片段
+    retry_with_backoff(
+        || send_gpt_stream_request(client, settings, api_key, &request, &tx),
+        settings.max_retries,
+        RETRY_BASE_DELAY_MS,
+    )
+    .await
+}
+
+// 送出串流請求並逐行解析 SSE，直到 data: [DONE] 或連線結束；回傳完整的回覆
+async fn send_gpt_stream_request(
+    client: &Client,
+    settings: &LlmSettings,
+    api_key: &str,
+    request: &GPTRequest,
+    tx: &mpsc::Sender<String>,
+) -> Result<String, QprError> {
+    let builder = client.post(&settings.url);
+    let builder = match settings.api_key_header {
+        ApiKeyHeader::Bearer => builder.header("Authorization", format!("Bearer {}", api_key)),
+        ApiKeyHeader::Azure => builder.header("api-key", api_key),
+    };
+    let message = settings
+        .metrics
+        .time_request(async {
+            let res = builder.json(request).send().await?;
+            let status = res.status();
+            if !status.is_success() {
+                return Err(QprError::LlmError {
+                    status: status.as_u16(),
+                    body: res.text().await?,
+                });
+            }
+
+            let mut message = String::new();
+            let mut buffer: Vec<u8> = Vec::new();
+            let mut body = res.bytes_stream();
+            'stream: while let Some(bytes) = body.next().await {
+                buffer.extend_from_slice(&bytes?);
+                // 一個事件可能跨越多個封包，只處理已收到完整一行的部分
+                while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    let line = String::from_utf8_lossy(&line);
+                    let Some(data) = line.trim().strip_prefix("data:") else {
+                        continue;
+                    };
+                    let data = data.trim();
+                    if data == "[DONE]" {
+                        break 'stream;
+                    }
+                    let chunk: GPTStreamChunk = serde_json::from_str(data)?;
+                    if let Some(text) = chunk.choices.into_iter().next().and_then(|c| c.delta.content) {
+                        if !text.is_empty() {
+                            message.push_str(&text);
+                            // 接收端已關閉（例如 WebSocket 斷線）時仍完成摘要
+                            let _ = tx.send(text).await;
+                        }
+                    }
+                }
+            }
+            Ok(message)
+        })
+        .await?;
+    tracing::debug!(bytes = message.len(), "GPT 串流回應");
+    // 串流回應記錄組合後的內容，而非逐行的 SSE 事件
+    debug_log::record_raw_response(&message);
+
+    // 串流回應沒有 usage，自行計算提示語與回覆的 token 數
+    let tokens: u64 = request
+        .messages
+        .iter()
+        .map(|m| count_tokens(&m.content, &settings.model) as u64)
+        .sum::<u64>()
+        + count_tokens(&message, &settings.model) as u64;
+    settings.add_tokens_used(tokens);
+    Ok(message)
+}
+
+// GPT 過濾資料夾
+#[tracing::instrument(skip_all)]
+async fn analyze_folders_with_gpt(
+    client: &Client,
+    api_key: &str,
+    settings: &LlmSettings,
+    folders: &str,
+    extra_folders: &str,
+) -> Result<String, QprError> {
+    let prompt = settings.build_folder_prompt(folders, extra_folders);
+    chat_with_gpt_model(client, api_key, settings, &settings.folder_analysis_model, prompt).await
+}
+
+// ===========================
+// Mistral AI 後端
+// ===========================
+
+// 請求與回應格式與 OpenAI 相同，沿用 OpenAI 後端，只有網址（DEFAULT_MISTRAL_URL）與模型不同
+pub struct MistralBackend(OpenAiBackend);
+
+#[async_trait]
+impl LlmBackend for MistralBackend {
+    fn name(&self) -> &'static str {
+        "mistral"
+    }
+
+    async fn summarize_file(
+        &self,
+        file_content: &str,
+        extension: &str,
+        prompt_override: Option<&str>,
+    ) -> Result<String, QprError> {
+        self.0.summarize_file(file_content, extension, prompt_override).await
+    }
+
+    async fn summarize_file_streaming(
+        &self,
+        file_content: &str,
+        extension: &str,
+        prompt_override: Option<&str>,
+        tx: mpsc::Sender<String>,
+    ) -> Result<String, QprError> {
+        self.0.summarize_file_streaming(file_content, extension, prompt_override, tx).await
+    }
+
+    async fn analyze_folders(
+        &self,
+        folders: &str,
+        extra_folders: &str,
+    ) -> Result<String, QprError> {
+        self.0.analyze_folders(folders, extra_folders).await
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String, QprError> {
+        self.0.complete(prompt).await
+    }
+
+    fn total_tokens_used(&self) -> u64 {
+        self.0.total_tokens_used()
+    }
+
+    fn semaphore(&self) -> Arc<Semaphore> {
+        self.0.semaphore()
+    }
+}
+
+// ===========================
+// Claude（Anthropic Messages API）後端
+// ===========================
+
+#[derive(Serialize)]
+struct ClaudeRequest {
+    model: String,
+    max_tokens: u32,
+    messages: Vec<Message>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeResponse {
+    content: Vec<ClaudeContent>,
+    usage: Option<ClaudeUsage>,
+}
+
+// 回覆內容區塊，摘要只使用 type 為 text 的區塊
+#[derive(Deserialize)]
+struct ClaudeContent {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct ClaudeUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+pub struct ClaudeBackend {
+    client: Client,
+    api_key: String,
+    settings: LlmSettings,
+    limit: ConcurrencyLimit,
+}
+
+#[async_trait]
+impl LlmBackend for ClaudeBackend {
+    fn name(&self) -> &'static str {
+        "claude"
+    }
+
+    async fn summarize_file(
+        &self,
+        file_content: &str,
+        _extension: &str,
+        prompt_override: Option<&str>,
+    ) -> Result<String, QprError> {
+        summarize_file_with_claude(&self.client, &self.api_key, &self.settings, file_content, prompt_override).await
+    }
+
+    async fn analyze_folders(
+        &self,
+        folders: &str,
+        extra_folders: &str,
+    ) -> Result<String, QprError> {
+        // 使用與 OpenAI 相同的提示語，回覆同樣以 parse_folder_analysis 解析
+        let prompt = self.settings.build_folder_prompt(folders, extra_folders);
+        chat_with_claude(&self.client, &self.api_key, &self.settings, prompt).await
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String, QprError> {
+        chat_with_claude(&self.client, &self.api_key, &self.settings, prompt.to_string()).await
+    }
+
+    fn total_tokens_used(&self) -> u64 {
+        self.settings.tokens_used.load(Ordering::Relaxed)
+    }
+
+    fn semaphore(&self) -> Arc<Semaphore> {
+        self.limit.semaphore.clone()
+    }
+}
+
+// 送出 Claude 請求並取出第一個文字區塊，同時累計 token 用量
+async fn send_claude_request(
+    client: &Client,
+    settings: &LlmSettings,
+    api_key: &str,
+    request: &ClaudeRequest,
+) -> Result<String, QprError> {
+    let builder = client
+        .post(&settings.url)
+        .header("x-api-key", api_key)
+        .header("anthropic-version", ANTHROPIC_VERSION);
+    let (status, res_text) = settings
+        .metrics
+        .time_request(async {
+            let res = builder.json(request).send().await?;
+            let status = res.status();
+            Ok::<_, QprError>((status, res.text().await?))
+        })
+        .await?;
+    tracing::debug!(status = status.as_u16(), bytes = res_text.len(), "Claude 回應");
+    debug_log::record_raw_response(&res_text);
+    if !status.is_success() {
+        return Err(QprError::LlmError {
+            status: status.as_u16(),
+            body: res_text,
+        });
+    }
+
+    let res_json: ClaudeResponse = serde_json::from_str(&res_text)?;
+    let Some(content) = res_json.content.iter().find(|c| c.kind == "text") else {
+        return Err(QprError::InvalidResponse("無法從 Claude 回應中提取內容".to_string()));
+    };
+    // API 未回傳 usage 時自行計算提示語與回覆的 token 數
+    let tokens = match &res_json.usage {
+        Some(usage) => usage.input_tokens + usage.output_tokens,
+        None => request
+            .messages
+            .iter()
+            .map(|m| m.content.as_str())
+            .chain(std::iter::once(content.text.as_str()))
+            .map(|text| count_tokens(text, &settings.model) as u64)
+            .sum(),
+    };
+    settings.add_tokens_used(tokens);
+    Ok(content.text.clone())
+}
+
+// 以單一使用者訊息呼叫 Claude，遇到暫時性錯誤時重試
+async fn chat_with_claude(
+    client: &Client,
+    api_key: &str,
+    settings: &LlmSettings,
+    prompt: String,
+) -> Result<String, QprError> {
+    let request = ClaudeRequest {
+        model: settings.model.clone(),
+        max_tokens: CLAUDE_MAX_TOKENS,
+        messages: vec![Message {
+            role: "user".to_string(),
+            content: prompt,
+        }],
+    };
+
+    retry_with_backoff(
+        || send_claude_request(client, settings, api_key, &request),
+        settings.max_retries,
+        RETRY_BASE_DELAY_MS,
+    )
+    .await
+}
+
+// Claude 生成檔案摘要，超過 token 上限時分段摘要後再合併
+async fn summarize_file_with_claude(
+    client: &Client,
+    api_key: &str,
+    settings: &LlmSettings,
+    file_content: &str,
+    prompt_override: Option<&str>,
+) -> Result<String, QprError> {
+    let mut prompts = settings.token_chunked_prompts(prompt_override, file_content);
+    if prompts.len() <= 1 {
+        let prompt = prompts.pop().unwrap_or_default();
+        return chat_with_claude(client, api_key, settings, prompt).await;
+    }
+
+    let mut summaries = Vec::new();
+    let chunk_progress = progress_bar::start_chunks(prompts.len());
+    for prompt in prompts {
+        summaries.push(chat_with_claude(client, api_key, settings, prompt).await?);
+        chunk_progress.inc();
+    }
+
+    let final_prompt = settings.build_summary_prompt(&settings.file_rollup_prompt, &summaries.join(" "));
+    chat_with_claude(client, api_key, settings, final_prompt).await
+}
+
+// ===========================
+// Llama（llama.cpp server）後端
+// ===========================
+
+const LLAMA_STOP_TOKENS: &[&str] = &[
+    "</s>", "<|end|>", "<|eot_id|>", "<|end_of_text|>", "<|im_end|>", "<|EOT|>",
+    "<|END_OF_TURN_TOKEN|>", "<|end_of_turn|>", "<|endoftext|>", "ASSISTANT", "USER",
+];
+
+pub struct LlamaBackend {
+    client: Client,
+    settings: LlmSettings,
+    // 本地伺服器回傳錯誤狀態碼時自動降低並行數
+    limit: ConcurrencyLimit,
+}
+
+impl LlamaBackend {
+    fn throttle_on_error<T>(&self, result: Result<T, QprError>) -> Result<T, QprError> {
+        if let Err(QprError::LlmError { .. }) = &result {
+            self.limit.decrease();
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl LlmBackend for LlamaBackend {
+    fn name(&self) -> &'static str {
+        "llama"
+    }
+
+    async fn summarize_file(
+        &self,
+        file_content: &str,
+        extension: &str,
+        prompt_override: Option<&str>,
+    ) -> Result<String, QprError> {
+        let result =
+            summarize_file_with_llama(&self.client, &self.settings, file_content, extension, prompt_override).await;
+        self.throttle_on_error(result)
+    }
+
+    async fn analyze_folders(
+        &self,
+        folders: &str,
+        extra_folders: &str,
+    ) -> Result<String, QprError> {
+        let result = analyze_folders_with_llama(&self.client, &self.settings, folders, extra_folders).await;
+        self.throttle_on_error(result)
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String, QprError> {
+        // 套用 llama.cpp 的對話格式
+        let prompt = format!("USER:{}\nASSISTANT", prompt);
+        let request_body = llama_request_body(&prompt, 0.28, 0.80, 40);
+        let result = retry_with_backoff(
+            || send_llama_request(&self.client, &self.settings, &request_body),
+            self.settings.max_retries,
+            RETRY_BASE_DELAY_MS,
+        )
+        .await;
+        self.throttle_on_error(result)
+    }
+
+    fn total_tokens_used(&self) -> u64 {
+        self.settings.tokens_used.load(Ordering::Relaxed)
+    }
+
+    fn semaphore(&self) -> Arc<Semaphore> {
+        self.limit.semaphore.clone()
+    }
+}
+
+// 建立 llama.cpp /completion 請求的 body
+fn llama_request_body(prompt: &str, temperature: f32, repeat_penalty: f32, top_k: u32) -> serde_json::Value {
+    serde_json::json!({
+        "n_predict": 4096,
+        "temperature": temperature,
+        "stop": LLAMA_STOP_TOKENS,
+        "repeat_last_n": 0,
+        "repeat_penalty": repeat_penalty,
+        "penalize_nl": false,
+        "top_k": top_k,
+        "top_p": 0.79,
+        "min_p": 0.43,
+        "tfs_z": 1,
+        "typical_p": 1,
+        "presence_penalty": 0,
+        "frequency_penalty": 0,
+        "mirostat": 0,
+        "mirostat_tau": 5,
+        "mirostat_eta": 0.1,
+        "grammar": "",
+        "n_probs": 0,
+        "min_keep": 0,
+        "prompt": prompt.trim()
+    })
+}
+
+// 送出 Llama 請求並取出 content 欄位，同時累計 token 用量
+async fn send_llama_request(
+    client: &Client,
+    settings: &LlmSettings,
+    request_body: &serde_json::Value,
+) -> Result<String, QprError> {
+    let (status, res_text) = settings
+        .metrics
+        .time_request(async {
+            let res = client.post(&settings.url).json(request_body).send().await?;
+            let status = res.status();
+            Ok::<_, QprError>((status, res.text().await?))
+        })
+        .await?;
+    tracing::debug!(status = status.as_u16(), bytes = res_text.len(), "Llama 回應");
+    debug_log::record_raw_response(&res_text);
+    if !status.is_success() {
+        return Err(QprError::LlmError {
+            status: status.as_u16(),
+            body: res_text,
+        });
+    }
+
+    let res_json: serde_json::Value = serde_json::from_str(&res_text)?;
+
+    // 檢查 JSON 回應中是否存在 "content" 欄位
+    if let Some(content) = res_json.get("content").and_then(|c| c.as_str()) {
+        // llama.cpp 回傳 tokens_evaluated / tokens_predicted，缺少時自行計算
+        let reported = ["tokens_evaluated", "tokens_predicted"]
+            .iter()
+            .filter_map(|key| res_json.get(key).and_then(|v| v.as_u64()))
+            .sum::<u64>();
+        let tokens = if reported > 0 {
+            reported
+        } else {
+            let prompt = request_body.get("prompt").and_then(|p| p.as_str()).unwrap_or("");
+            (count_tokens(prompt, &settings.model) + count_tokens(content, &settings.model)) as u64
+        };
+        settings.add_tokens_used(tokens);
+        return Ok(content.to_string());
+    }
+
+    Err(QprError::InvalidResponse("無法從 Llama 回應中提取內容".to_string()))
+}
+
+// 使用 Llama 分段生成摘要，再將各段摘要合併成最終摘要
+async fn summarize_file_with_llama(
+    client: &Client,
+    settings: &LlmSettings,
+    file_content: &str,
+    extension: &str,
+    prompt_override: Option<&str>,
+) -> Result<String, QprError> {
+    let mut summaries = Vec::new();
+    let prompts = settings.line_chunked_prompts(prompt_override, file_content, extension);
+    let chunk_progress = progress_bar::start_chunks(prompts.len());
+
+    for prompt in prompts {
+        let request_body = llama_request_body(&prompt, 0.2, 0.80, 40);
+
+        let summary = retry_with_backoff(
+            || send_llama_request(client, settings, &request_body),
+            settings.max_retries,
+            RETRY_BASE_DELAY_MS,
+        )
+        .await?;
+        summaries.push(summary);
+        chunk_progress.inc();
+    }
+
+    // 合併所有摘要為一個大段落，進行最終的摘要調用
+    let final_summary = summaries.join(" ");
+    let final_prompt = settings.build_summary_prompt(&settings.file_rollup_prompt, &final_summary);
+    let final_request_body = llama_request_body(&final_prompt, 0.28, 0.80, 40);
+
+    retry_with_backoff(
+        || send_llama_request(client, settings, &final_request_body),
+        settings.max_retries,
+        RETRY_BASE_DELAY_MS,
+    )
+    .await
+}
+
+// 使用 Llama 過濾資料夾
+async fn analyze_folders_with_llama(
+    client: &Client,
+    settings: &LlmSettings,
+    folders: &str,
+    extra_folders: &str,
+) -> Result<String, QprError> {
+    let prompt = settings.build_folder_prompt(folders.trim(), extra_folders.trim());
+    let request_body = llama_request_body(&prompt, 0.28, 0.84, 31);
+
+    retry_with_backoff(
+        || send_llama_request(client, settings, &request_body),
+        settings.max_retries,
+        RETRY_BASE_DELAY_MS,
+    )
+    .await
+}
+
+// ===========================
+// Dry-run 後端（不呼叫任何 API）
+// ===========================
+
+// 回傳佔位摘要並記錄原本會送出的請求數與 token 數，用於 --dry-run
+pub struct DryRunBackend {
+    settings: LlmSettings,
+    requests: AtomicU64,
+    limit: ConcurrencyLimit,
+}
+
+impl DryRunBackend {
+    pub fn new(config: &QprConfig) -> Self {
+        DryRunBackend {
+            settings: LlmSettings::from_config(config, Arc::default()),
+            requests: AtomicU64::new(0),
+            limit: ConcurrencyLimit::new(config.backend_concurrency()),
+        }
+    }
+
+    // 原本會送出的 API 請求數
+    pub fn request_count(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, prompt: &str) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.settings
+            .add_tokens_used(count_tokens(prompt, &self.settings.model) as u64);
+    }
+}
+
+#[async_trait]
+impl LlmBackend for DryRunBackend {
+    fn name(&self) -> &'static str {
+        "dry-run"
+    }
+
+    async fn summarize_file(
+        &self,
+        file_content: &str,
+        _extension: &str,
+        prompt_override: Option<&str>,
+    ) -> Result<String, QprError> {
+        let prompts = self.settings.token_chunked_prompts(prompt_override, file_content);
+        for prompt in &prompts {
+            self.record(prompt);
+        }
+        // 分段時還需要一次合併請求，其輸入為各段摘要，這裡只計入提示語本身
+        if prompts.len() > 1 {
+            self.record(&self.settings.build_summary_prompt(&self.settings.file_rollup_prompt, ""));
+        }
+        Ok("（dry-run）未生成摘要".to_string())
+    }
+
+    async fn analyze_folders(
+        &self,
+        folders: &str,
+        extra_folders: &str,
+    ) -> Result<String, QprError> {
+        self.record(&self.settings.build_folder_prompt(folders, extra_folders));
+        // 保留所有資料夾，以估算最大的摘要成本
+        let mut names: Vec<&str> = folders.lines().map(str::trim).filter(|n| !n.is_empty()).collect();
+        names.dedup();
+        Ok(serde_json::json!({ "analysis_key": names }).to_string())
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String, QprError> {
+        self.record(prompt);
+        Ok("（dry-run）未生成回覆".to_string())
+    }
+
+    fn total_tokens_used(&self) -> u64 {
+        self.settings.tokens_used.load(Ordering::Relaxed)
+    }
+
+    fn semaphore(&self) -> Arc<Semaphore> {
+        self.limit.semaphore.clone()
+    }
+}
+
+// ===========================
+// Mock 後端（--backend mock）
+// ===========================
+
+// 一次 mock 後端的呼叫紀錄
+#[derive(Debug, Clone)]
+pub struct MockCall {
+    pub kind: &'static str,
+    pub prompt: String,
+}
+
+// 不連線的後端：回覆由提示語的雜湊決定，相同輸入一律得到相同摘要，可在沒有 API 的環境跑完整流程
+pub struct MockLlmBackend {
+    settings: LlmSettings,
+    // 第 N 次呼叫（從 1 起算）回傳 HTTP 503，用於驗證重試
+    fail_on_call: Option<usize>,
+    // 依檔案路徑指定的摘要（with_response），取代由雜湊產生的回覆
+    responses: HashMap<String, String>,
+    // 每次呼叫的模擬延遲，讓並行的呼叫互相重疊
+    delay: Duration,
+    calls: Mutex<Vec<MockCall>>,
+    // 進行中的呼叫數與其最大值，用於確認並行數上限
+    in_flight: AtomicUsize,
+    peak_in_flight: AtomicUsize,
+    limit: ConcurrencyLimit,
+}
+
+// 呼叫結束（包含被取消）時減少進行中的呼叫數
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl MockLlmBackend {
+    pub fn new(config: &QprConfig, metrics: Arc<Metrics>) -> Self {
+        let mock = MockLlmBackend {
+            settings: LlmSettings::from_config(config, metrics),
+            fail_on_call: config.mock_fail_on_call,
+            responses: HashMap::new(),
+            delay: Duration::from_millis(config.mock_delay_ms),
+            calls: Mutex::new(Vec::new()),
+            in_flight: AtomicUsize::new(0),
+            peak_in_flight: AtomicUsize::new(0),
+            limit: ConcurrencyLimit::new(config.backend_concurrency()),
+        };
+        config
+            .mock_responses
+            .iter()
+            .fold(mock, |mock, (path, reply)| mock.with_response(path.clone(), reply.clone()))
+    }
+
+    // 摘要 path 時回覆 reply；path 可為完整路徑或結尾的相對路徑（例如 src/main.rs）
+    pub fn with_response(mut self, path: impl Into<String>, reply: impl Into<String>) -> Self {
+        self.responses.insert(path.into(), reply.into());
+        self
+    }
+
+    // 目前摘要中的檔案（見 timeline::scope）是否有指定的回覆
+    fn response_override(&self) -> Option<String> {
+        let path = timeline::current_path()?;
+        self.responses
+            .iter()
+            .find(|(key, _)| path == **key || path.ends_with(&format!("/{}", key.trim_start_matches('/'))))
+            .map(|(_, reply)| reply.clone())
+    }
+
+    // 同時進行中的呼叫數最多曾經有幾個
+    pub fn peak_in_flight(&self) -> usize {
+        self.peak_in_flight.load(Ordering::SeqCst)
+    }
+
+    // 目前為止的所有呼叫（包含失敗後重試的呼叫）
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().expect("mock 呼叫紀錄已損毀").clone()
+    }
+
+    // 依呼叫種類統計次數與提示語大小，例如 `analyze_folders 1 次（812 bytes）、summarize_file 12 次（9034 bytes）`
+    pub fn call_summary(&self) -> String {
+        let mut counts: Vec<(&'static str, usize, usize)> = Vec::new();
+        for call in self.calls() {
+            match counts.iter_mut().find(|(kind, _, _)| *kind == call.kind) {
+                Some((_, count, bytes)) => {
+                    *count += 1;
+                    *bytes += call.prompt.len();
+                }
+                None => counts.push((call.kind, 1, call.prompt.len())),
+            }
+        }
+        counts.sort();
+        counts
+            .iter()
+            .map(|(kind, count, bytes)| format!("{} {} 次（{} bytes）", kind, count, bytes))
+            .chain(std::iter::once(format!("最多同時 {} 個呼叫", self.peak_in_flight())))
+            .collect::<Vec<_>>()
+            .join("、")
+    }
+
+    fn respond(&self, kind: &'static str, prompt: &str) -> Result<String, QprError> {
+        let call = {
+            let mut calls = self.calls.lock().expect("mock 呼叫紀錄已損毀");
+            calls.push(MockCall {
+                kind,
+                prompt: prompt.to_string(),
+            });
+            calls.len()
+        };
+        tracing::debug!(call, kind, prompt_bytes = prompt.len(), "mock 呼叫");
+        if self.fail_on_call == Some(call) {
+            return Err(QprError::LlmError {
+                status: 503,
+                body: format!("mock 後端第 {} 次呼叫設定為失敗", call),
+            });
+        }
+        self.settings
+            .add_tokens_used(count_tokens(prompt, &self.settings.model) as u64);
+        Ok(format!("[mock] {}", &crate::cache::content_hash(prompt.as_bytes())[..16]))
+    }
+
+    // 與實際後端相同，暫時性錯誤時以指數退避重試
+    async fn call(&self, kind: &'static str, prompt: &str) -> Result<String, QprError> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        let _guard = InFlightGuard(&self.in_flight);
+        self.peak_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        retry_with_backoff(
+            || async {
+                if !self.delay.is_zero() {
+                    tokio::time::sleep(self.delay).await;
+                }
+                self.respond(kind, prompt)
+            },
+            self.settings.max_retries,
+            RETRY_BASE_DELAY_MS,
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl LlmBackend for MockLlmBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn summarize_file(
+        &self,
+        file_content: &str,
+        _extension: &str,
+        prompt_override: Option<&str>,
+    ) -> Result<String, QprError> {
+        let prompt = self.settings.build_summary_prompt(self.settings.summary_template(prompt_override), file_content);
+        let reply = self.call("summarize_file", &prompt).await?;
+        let reply = self.response_override().unwrap_or(reply);
+        debug_log::record_raw_response(&reply);
+        Ok(reply)
+    }
+
+    async fn analyze_folders(
+        &self,
+        folders: &str,
+        extra_folders: &str,
+    ) -> Result<String, QprError> {
+        self.call("analyze_folders", &self.settings.build_folder_prompt(folders, extra_folders))
+            .await?;
+        // 保留所有資料夾
+        let mut names: Vec<&str> = folders.lines().map(str::trim).filter(|n| !n.is_empty()).collect();
+        names.dedup();
+        Ok(serde_json::json!({ "analysis_key": names }).to_string())
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String, QprError> {
+        self.call("complete", prompt).await
+    }
+
+    fn total_tokens_used(&self) -> u64 {
+        self.settings.tokens_used.load(Ordering::Relaxed)
+    }
+
+    fn semaphore(&self) -> Arc<Semaphore> {
+        self.limit.semaphore.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // 收到的 HTTP 請求
+    struct RecordedRequest {
+        request_line: String,
+        // 標頭名稱轉為小寫
+        headers: HashMap<String, String>,
+        body: serde_json::Value,
+    }
+
+    // 只接受一個連線的本機 HTTP 伺服器，以 response_body 回應 200，回傳 (位址, 收到的請求)
+    async fn serve_once(response_body: serde_json::Value) -> (String, tokio::task::JoinHandle<RecordedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut raw = Vec::new();
+            let mut buf = [0u8; 4096];
+            // 讀到標頭結束，再依 Content-Length 讀完本文
+            let (head, mut body) = loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                raw.extend_from_slice(&buf[..n]);
+                if let Some(end) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break (String::from_utf8(raw[..end].to_vec()).unwrap(), raw[end + 4..].to_vec());
+                }
+            };
+            let mut lines = head.lines();
+            let request_line = lines.next().unwrap().to_string();
+            let headers: HashMap<String, String> = lines
+                .filter_map(|line| line.split_once(':'))
+                .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+                .collect();
+            let length: usize = headers["content-length"].parse().unwrap();
+            while body.len() < length {
+                let n = stream.read(&mut buf).await.unwrap();
+                body.extend_from_slice(&buf[..n]);
+            }
+
+            let response_body = response_body.to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response_body.len(),
+                response_body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            RecordedRequest {
+                request_line,
+                headers,
+                body: serde_json::from_slice(&body).unwrap(),
+            }
+        });
+        (format!("http://{}", addr), handle)
+    }
+
+    fn chat_response(content: &str) -> serde_json::Value {
+        serde_json::json!({
+            "choices": [{ "message": { "role": "assistant", "content": content } }],
+            "usage": { "prompt_tokens": 7, "completion_tokens": 3, "total_tokens": 10 }
+        })
+    }
+
+    #[test]
+    fn mistral_defaults_to_chat_completions_url_and_small_model() {
+        let config = QprConfig {
+            backend: BackendKind::Mistral,
+            ..QprConfig::default()
+        };
+        assert_eq!(config.llm_url(), "https://api.mistral.ai/v1/chat/completions");
+        assert_eq!(config.model_name(), "mistral-small-latest");
+    }
+
+    #[tokio::test]
+    async fn mistral_sends_bearer_key_to_chat_completions() {
+        let (base_url, server) = serve_once(chat_response("Mistral 的摘要")).await;
+        let config = QprConfig {
+            backend: BackendKind::Mistral,
+            llm_url: Some(format!("{}/v1/chat/completions", base_url)),
+            cli_api_key: Some("mistral-test-key".to_string()),
+            mistral_model: Some("mistral-large-latest".to_string()),
+            ..QprConfig::default()
+        };
+        let backend = create_backend(&config, Arc::new(Metrics::default())).unwrap();
+        assert_eq!(backend.name(), "mistral");
+
+        let reply = backend.complete("hello").await.unwrap();
+        assert_eq!(reply, "Mistral 的摘要");
+        assert_eq!(backend.total_tokens_used(), 10);
+
+        // OpenAI 相容的請求格式，以 Bearer 標頭帶 API 金鑰
+        let request = server.await.unwrap();
+        assert_eq!(request.request_line, "POST /v1/chat/completions HTTP/1.1");
+        assert_eq!(request.headers["authorization"], "Bearer mistral-test-key");
+        assert!(!request.headers.contains_key("api-key"));
+        assert_eq!(request.body["model"], "mistral-large-latest");
+        assert_eq!(request.body["messages"], serde_json::json!([{ "role": "user", "content": "hello" }]));
+    }
+
+    #[test]
+    fn retry_delay_grows_exponentially_and_is_capped() {
+        let delay = retry_delay_ms(2, 100);
+        assert!((400..=500).contains(&delay), "{}", delay);
+        // 指數次方與乘法溢位時不會 panic，而是停在上限
+        assert_eq!(retry_delay_ms(u8::MAX, RETRY_BASE_DELAY_MS), MAX_RETRY_DELAY_MS);
+        assert_eq!(retry_delay_ms(63, RETRY_BASE_DELAY_MS), MAX_RETRY_DELAY_MS);
+        assert_eq!(retry_delay_ms(0, u64::MAX), MAX_RETRY_DELAY_MS);
+    }
+
+    #[test]
+    fn split_by_tokens_keeps_chunks_under_limit() {
+        let model = "gpt-4o";
+        let block = |i: usize| format!("fn block{}() {{\n    let value = {};\n}}", i, i);
+        let content = (0..20).map(block).collect::<Vec<_>>().join("\n\n");
+        let max_tokens = 40;
+
+        let chunks = split_by_tokens(&content, max_tokens, model);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| count_tokens(chunk, model) <= max_tokens), "{:?}", chunks);
+        // 只在空行或換行處切開，不遺漏任何內容
+        assert_eq!(chunks.join("\n").replace('\n', ""), content.replace('\n', ""));
+        assert!(chunks.iter().all(|chunk| chunk.starts_with("fn block")));
+    }
+
+    #[test]
+    fn split_by_tokens_splits_oversized_block_by_lines() {
+        let model = "gpt-4o";
+        let content = (0..30).map(|i| format!("let line{} = {};", i, i)).collect::<Vec<_>>().join("\n");
+        let chunks = split_by_tokens(&content, 25, model);
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| count_tokens(chunk, model) <= 25));
+        assert_eq!(chunks.join("\n"), content);
+
+        assert_eq!(split_by_tokens("short", 25, model), vec!["short"]);
+        assert!(split_by_tokens("\n\n  \n\n", 25, model).is_empty());
+    }
+
+    #[test]
+    fn parse_folder_analysis_extracts_keys_from_surrounding_text() {
+        let response = "以下是分析結果：\n```json\n{\"analysis_key\": [\"src\", \"crates/core/src\"]}\n```\n以上。";
+        assert_eq!(parse_folder_analysis(response).unwrap(), vec!["src", "crates/core/src"]);
+        assert_eq!(
+            parse_folder_analysis("{\"note\": 1, \"analysis_key\": []}").unwrap(),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn parse_folder_analysis_rejects_missing_or_invalid_json() {
+        assert!(matches!(parse_folder_analysis("沒有 JSON"), Err(QprError::InvalidResponse(_))));
+        assert!(parse_folder_analysis("{\"analysis_key\": \"src\"}").is_err());
+        assert!(extract_analysis_json("\"analysis_key\": [] }").is_none());
+    }
+}
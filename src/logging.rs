@@ -0,0 +1,54 @@
+use std::path::Path;
+
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter};
+
+use crate::error::QprError;
+
+// ===========================
+// 日誌輸出
+// ===========================
+
+// RUST_LOG 未設定時的日誌層級；warp 每個請求的日誌只在 --verbose 或 RUST_LOG 指定時輸出
+const DEFAULT_LOG_FILTER: &str = "info,warp=warn";
+
+// 初始化 tracing：標準輸出為易讀格式，指定 --log-file 時另以 JSON 寫入檔案；
+// 回傳的 guard 需保留到程式結束，否則檔案中最後的日誌可能遺失
+pub fn init(verbose: bool, log_file: Option<&str>) -> Result<Option<WorkerGuard>, QprError> {
+    // --verbose 等同 RUST_LOG=debug
+    let filter = if verbose {
+        EnvFilter::new("debug")
+    } else {
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER))
+    };
+    let stdout_layer = fmt::layer().with_target(false);
+
+    let (file_layer, guard) = match log_file {
+        Some(log_file) => {
+            let path = Path::new(log_file);
+            let file_name = path
+                .file_name()
+                .ok_or_else(|| QprError::ConfigError(format!("--log-file 不是檔案路徑：{}", log_file)))?;
+            let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            let appender = RollingFileAppender::builder()
+                .rotation(Rotation::NEVER)
+                .filename_prefix(file_name.to_string_lossy())
+                .build(dir)
+                .map_err(|e| QprError::ConfigError(format!("無法開啟日誌檔 {}：{}", log_file, e)))?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (Some(fmt::layer().json().with_ansi(false).with_writer(writer)), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(stdout_layer)
+        .with(file_layer)
+        .try_init()
+        .map_err(|e| QprError::ConfigError(format!("無法初始化日誌：{}", e)))?;
+    Ok(guard)
+}
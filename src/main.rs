@@ -1,168 +1,301 @@
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
 use std::fs;
-use std::path::Path;
-use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::env;
+use std::io::{self, BufRead, IsTerminal};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use warp::Filter;
 use warp::Reply; // 添加此导入
-use dotenv::dotenv;
-use std::env;
-use std::collections::HashMap;
-use futures::future::join_all;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use futures::stream::{self, StreamExt};
 
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Instant, SystemTime};
+use time::format_description::well_known::Rfc3339;
+use tokio::sync::{broadcast, mpsc, RwLock, Semaphore};
+use clap::Parser;
+
+mod annotations;
+mod auth;
+mod baseline;
+mod cache;
+mod chunker;
+mod cli;
+mod config;
+mod debug_log;
+mod dependencies;
+mod error;
+mod export;
+mod extensions;
+mod git;
+mod ignore;
+mod llm;
+mod logging;
+mod metrics;
+mod progress_bar;
+mod quality;
+mod registry;
+mod search;
+mod similarity;
+mod shutdown;
+mod stats;
+mod timeline;
+mod tls;
+mod watch;
+mod ws;
+
+use annotations::{Annotation, AnnotationMode};
+use baseline::{BaselineHashes, ChangeStatus};
+use cache::{CacheStatus, SummaryCache};
+use cli::{Cli, Command};
+use config::{QprConfig, SortOrder};
+use debug_log::DebugLog;
+use error::QprError;
+use extensions::{ExtensionSet, Language};
+use ignore::IgnoreSet;
+use llm::{BackendKind, LlmBackend};
+use metrics::Metrics;
+use progress_bar::SummaryProgress;
+use quality::SummaryQuality;
+use registry::{ProjectEntry, ProjectRegistry};
+use shutdown::Shutdown;
+use similarity::SimilarityIndex;
+use timeline::{CompletionEstimate, Timeline, TimelineEventKind};
+use stats::ProjectStats;
 
 // ===========================
 // 可配置的常數
 // ===========================
 
-// 伺服器埠號設定
+// 伺服器埠號設定（預設值，可由 qpr.toml 或 --port 覆寫）
 const SERVER_PORT: u16 = 3030;
+// 儲存的檔案摘要字元數上限（預設值，可由 qpr.toml 或 --summary-max-length 覆寫）
+const DEFAULT_SUMMARY_MAX_LENGTH: usize = 500;
+// 伺服器綁定的位址（預設值，可由 qpr.toml 或 --bind-addr 覆寫）
+const DEFAULT_BIND_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
 
-// 程式碼檔案的副檔名清單
+// 程式碼檔案的副檔名清單（預設值，可由 qpr.toml 的 [extensions] 調整）
 const CODE_FILE_EXTENSIONS: &[&str] = &[
     "rs", "py", "js", "ts", "java", "cpp", "c", "go", "sh", "rb", "bat", "cs", "resx","h","md",
 ];
+const _: () = assert!(
+    extensions::validate_extensions(CODE_FILE_EXTENSIONS),
+    "CODE_FILE_EXTENSIONS 不可包含空字串、重複項目或以 `.` 開頭的副檔名"
+);
 
-// GPT 提示語設定（包含佔位符 {}）
-const FOLDER_ANALYSIS_PROMPT: &str = "請根據以下資料夾名稱進行分析，過濾出可能是使用者撰寫的源代碼目錄 ,並返回一個 JSON 結構，key強迫為 'analysis_key'，值為符合條件的資料夾名稱的陣列：\n{folders}\n{extra_folders}";
+// GPT 提示語設定（包含佔位符 {}），可在 qpr.toml 中覆寫
+// 資料夾分析一律使用英文指示，模型較能穩定輸出 JSON（不受 --language 影響）
+const FOLDER_ANALYSIS_PROMPT: &str = "SYSTEM:Please analyze the following folder paths and filter out those that are likely to be user-written source code directories. If no directories are found, please use the default path: /root/c. The result should only return a JSON structure in the following format: {\"analysis_key\": [folder paths that meet the criteria]}, where 'analysis_key' is the only key, and the corresponding value is an array of folder paths that meet the criteria. Please ensure that the returned JSON structure contains only this key-value pair and does not include any additional information or explanations.\nEach entry in the list is a folder path relative to the project root ('.' is the root itself); return the matching entries exactly as written, as full relative paths such as \"src/utils\" rather than bare folder names.\nThe list of folder paths is as follows:\n{folders}\n{extra_folders}";
 
-const FILE_SUMMARY_PROMPT: &str = "請為以下程式碼生成一個簡短的功能摘要，不超過100個字。請用專業的軟體工程師風格描述該源代碼具體在做什麼，程式碼變數請保留原來的變數名稱英文，好讓我可以快速分析。請用繁體中文：\n{}";
+const FILE_SUMMARY_PROMPT: &str = "請為以下程式碼生成一個簡短的功能摘要，不超過100個字。請用專業的軟體工程師風格描述該源代碼具體在做什麼，程式碼變數請保留原來的變數名稱英文，好讓我可以快速分析。請用{language}：\n{}";
 
-// 專案目錄路徑設定
-const PROJECT_PATH: &str = "/root/Ghost";
+// 檔案過大而分段摘要時，合併各段摘要用的提示語
+const FILE_ROLLUP_PROMPT: &str = "以下是同一個檔案中各個程式碼片段的功能摘要，請將它們整合成一個完整的功能摘要，不超過150個字。請用專業的軟體工程師風格描述，程式碼變數請保留原來的變數名稱英文。請用{language}：\n{}";
 
-// ===========================
-// GPT 請求和回應結構
-// ===========================
+// 專案總覽提示語（{} 為所有檔案摘要）
+const PROJECT_ROLLUP_PROMPT: &str = "以下是一個專案中各個檔案的功能摘要。請以資深軟體架構師的角度，根據這些摘要撰寫一份約300字的專案架構總覽，說明主要模組、各模組的職責以及它們之間的關係。請用{language}：\n{}";
+// 定義單一目錄摘要的提示語（{directory} 為目錄路徑）
+const DIRECTORY_SUMMARY_PROMPT: &str = "以下是專案中 {directory} 目錄內各個檔案的功能摘要。請根據這些摘要，用一段不超過150字的文字說明這個目錄整體負責的功能與主要組成。請用{language}：\n{}";
 
-#[derive(Serialize, Deserialize)]
-struct GPTRequest {
-    model: String,
-    messages: Vec<Message>,
-}
+// Llama 提示語設定
+const LLAMA_FOLDER_ANALYSIS_PROMPT: &str = "SYSTEM:Please analyze the following folder paths and filter out those that are likely to be user-written source code directories. If no directories are found, please use the default path: /root/c. The result should only return a JSON structure in the following format: {\"analysis_key\": [folder paths that meet the criteria]}, where 'analysis_key' is the only key, and the corresponding value is an array of folder paths that meet the criteria. Each entry in the list is a folder path relative to the project root ('.' is the root itself); return the matching entries exactly as written, as full relative paths such as \"src/utils\" rather than bare folder names. Please ensure that the returned JSON structure contains only this key-value pair and does not include any additional information or explanations.\nThe list of folder paths is as follows\n\n\nUSER:{folders}{extra_folders}\nASSISTANT";
 
-#[derive(Serialize, Deserialize)]
-struct Message {
-    role: String,
-    content: String,
-}
+const LLAMA_FILE_SUMMARY_PROMPT: &str = "SYSTEM:你是一個專業的軟體分析工程師，給你程式碼你可以描述原始碼的大致實現那些具體功能，並精確地請以 「{language} 」的方式撰寫，每個大概寫個50個字。\nUSER:{}\nASSISTANT";
 
-#[derive(Deserialize)]
-struct GPTResponse {
-    choices: Vec<Choice>,
-}
+const LLAMA_FILE_ROLLUP_PROMPT: &str = "SYSTEM:你是一個專業的軟體分析工程師，給你程式碼你可以描述原始碼的大致實現那些具體功能，並精確地請以 「{language} 」的方式撰寫，你正在總結片段大概寫個150個字。\nUSER:{}\nASSISTANT";
 
-#[derive(Deserialize)]
-struct Choice {
-    message: Message,
-}
+// LLM 後端預設值
+const DEFAULT_OPENAI_URL: &str = "https://api.openai.com/v1/chat/completions";
+const DEFAULT_LLAMA_URL: &str = "http://127.0.0.1:9090/completion";
+const DEFAULT_CLAUDE_URL: &str = "https://api.anthropic.com/v1/messages";
+const DEFAULT_CLAUDE_MODEL: &str = "claude-3-haiku-20240307";
+// Anthropic Messages API 的版本標頭與每次回覆的 token 上限
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const CLAUDE_MAX_TOKENS: u32 = 1024;
+// Mistral AI 的 chat completions 與 OpenAI 相容，只是網址與模型不同
+const DEFAULT_MISTRAL_URL: &str = "https://api.mistral.ai/v1/chat/completions";
+const DEFAULT_MISTRAL_MODEL: &str = "mistral-small-latest";
+// 已知的 Mistral 模型，--mistral-model 不在清單中時只發出警告
+const KNOWN_MISTRAL_MODELS: &[&str] = &[
+    "mistral-small-latest",
+    "mistral-medium-latest",
+    "mistral-large-latest",
+    "open-mistral-7b",
+];
+// 未指定 --azure-api-version 時使用的 Azure OpenAI API 版本
+const DEFAULT_AZURE_API_VERSION: &str = "2024-06-01";
+const DEFAULT_MODEL: &str = "gpt-3.5-turbo";
+// 已知的 OpenAI 模型，--model／--folder-analysis-model 不在清單中時只發出警告
+const KNOWN_OPENAI_MODELS: &[&str] = &["gpt-3.5-turbo", "gpt-4", "gpt-4o", "gpt-4o-mini"];
 
-// 定義用於解析 GPT 分析回應的結構
-#[derive(Serialize, Deserialize)]
-struct GPTAnalysis {
-    analysis_key: Vec<String>,
-}
+// 設定檔與快取檔案
+const CONFIG_FILE: &str = "qpr.toml";
+const DEFAULT_CACHE_FILE: &str = ".qpr_cache.json";
+// 環境變數檔：.env.local 不加入版本控制，優先於 .env
+const ENV_FILE: &str = ".env";
+const ENV_LOCAL_FILE: &str = ".env.local";
 
-// 過濾隱藏目錄與不重要的目錄
-fn is_hidden_or_common_ignore(path: &Path) -> bool {
-    let hidden_dirs = vec![".git", ".github", ".pytest_cache", ".gitignore", "site-packages"];
-    if let Some(dir_name) = path.file_name() {
-        if let Some(dir_name_str) = dir_name.to_str() {
-            return hidden_dirs.contains(&dir_name_str);
-        }
-    }
-    false
-}
+// 預設忽略的目錄與檔案（.gitignore 格式，可在 .qprignore 中以 `!` 取消）
+const DEFAULT_IGNORE_PATTERNS: &[&str] = &[
+    ".git", ".github", ".pytest_cache", "site-packages", "node_modules", "target", "__pycache__",
+    "vendor", "dist", "build", ".venv", "venv", ".idea", ".vscode", ".tox", ".mypy_cache", ".next",
+];
 
-// GPT 過濾檔案並生成摘要
-async fn summarize_file_with_gpt(
-    file_content: String,
-    api_key: String,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let client = Client::new();
+// 掃描目錄的預設最大深度（根目錄為 0），避免過深或循環的目錄結構耗盡堆疊
+const DEFAULT_MAX_DEPTH: usize = 20;
+// 超過最大深度時，在目錄樹中標示被略過的子目錄
+const MAX_DEPTH_MARKER: &str = "[MAX DEPTH REACHED]";
+// 用完 --token-budget 後未送出請求的檔案，以此標記取代摘要
+const BUDGET_EXCEEDED_MARKER: &str = "[BUDGET EXCEEDED]";
+// 超過 per_file_total_timeout_ms 仍未完成的檔案，以此標記取代摘要
+const TIMEOUT_MARKER: &str = "[TIMEOUT]";
 
-    let prompt = FILE_SUMMARY_PROMPT.replace("{}", &file_content);
+// LLM 請求的逾時設定（毫秒），可在 qpr.toml 中覆寫
+const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 120_000;
+// 本機的 llama.cpp 生成較慢，單一請求的逾時較長
+const DEFAULT_LLAMA_REQUEST_TIMEOUT_MS: u64 = 300_000;
+// 單一檔案所有分段、重試與合併請求的總時間上限
+const DEFAULT_PER_FILE_TOTAL_TIMEOUT_MS: u64 = 600_000;
 
-    let request = GPTRequest {
-        model: "gpt-3.5-turbo".to_string(),
-        messages: vec![Message {
-            role: "user".to_string(),
-            content: prompt,
-        }],
-    };
+// 模型的 context window（tokens），可由 --model-context-window 覆寫
+const DEFAULT_CONTEXT_WINDOW: usize = 16_384;
+// 本機的 llama 模型通常只有 4K context
+const DEFAULT_LLAMA_CONTEXT_WINDOW: usize = 4_096;
 
-    let res = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&request)
-        .send()
-        .await?;
+// 專案目錄路徑設定（預設值，可由 qpr.toml 或 --path 覆寫）
+const PROJECT_PATH: &str = "/root/Ghost";
 
-    let res_text = res.text().await?;
-    let res_json: GPTResponse = serde_json::from_str(&res_text)?;
+// API 暫時性錯誤的重試設定
+const RETRYABLE_STATUS_CODES: &[u16] = &[429, 500, 503];
+const RETRY_BASE_DELAY_MS: u64 = 500;
+// 單次重試等待時間的上限（含抖動），避免 max_retries 很大時退避時間溢位或過長
+const MAX_RETRY_DELAY_MS: u64 = 60_000;
 
-    if let Some(first_choice) = res_json.choices.first() {
-        let message = &first_choice.message.content;
-        return Ok(message.clone());
-    }
+// SSE 事件廣播通道的緩衝容量
+const EVENT_CHANNEL_CAPACITY: usize = 256;
 
-    Err("無法從 GPT 回應中提取摘要".into())
-}
+// SSE 推送 progress_estimate 事件的間隔秒數
+const PROGRESS_ESTIMATE_INTERVAL_SECS: u64 = 10;
 
-// GPT 過濾資料夾
-async fn analyze_folders_with_gpt(
-    folders: &str,
-    extra_folders: &str,
-    api_key: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let client = Client::new();
+// 目錄專屬的檔案摘要提示語，作用於該目錄及其子目錄
+const PROMPT_OVERRIDE_FILE: &str = ".qpr_prompt";
 
-    let prompt = FOLDER_ANALYSIS_PROMPT
-        .replace("{folders}", folders)
-        .replace("{extra_folders}", extra_folders);
+// --output-dir／--sidecar 寫入的摘要檔案附加在原始檔名後的副檔名
+const SUMMARY_SIDECAR_SUFFIX: &str = ".qpr_summary";
 
-    let request = GPTRequest {
-        model: "gpt-3.5-turbo".to_string(),
-        messages: vec![Message {
-            role: "user".to_string(),
-            content: prompt,
-        }],
-    };
+// --watch 模式下檔案變動後等待的時間，期間再次變動會重新計時
+const WATCH_DEBOUNCE_MS: u64 = 2000;
 
-    let res = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&request)
-        .send()
-        .await?;
+// 收到結束訊號後，等待進行中的摘要請求完成的最長秒數
+const SHUTDOWN_DRAIN_SECS: u64 = 10;
 
-    let res_text = res.text().await?;
-    let res_json: GPTResponse = serde_json::from_str(&res_text)?;
+// /api/stats 列出的最慢檔案數量
+const SLOWEST_FILES_LIMIT: usize = 10;
 
-    if let Some(first_choice) = res_json.choices.first() {
-        let message = &first_choice.message.content;
-        return Ok(message.clone());
+// 定義檔案資訊結構
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FileInfo {
+    name: String,
+    // 尚未摘要時不輸出，減少 /filtered-tree 的回應大小
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    // 十六進位的 SHA-256 內容雜湊，僅在收集檔案且啟用 hash_files 時計算
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_hash: Option<String>,
+    // 檔案大小（bytes），供前端顯示
+    #[serde(default)]
+    size_bytes: u64,
+    // 相對於 --baseline 報告的狀態，未指定基準時為 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    change_status: Option<ChangeStatus>,
+    // 內容不是有效的 UTF-8，摘要時已將無效的位元組替換為 U+FFFD
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    encoding_lossy: bool,
+    // 依副檔名判斷的語言，掃描時設定；舊版報告或已刪除的檔案為 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<Language>,
+    // 摘要品質，每次更新摘要時重新評估；沒有摘要時為 Ok
+    #[serde(default, skip_serializing_if = "SummaryQuality::is_ok")]
+    quality: SummaryQuality,
+    // 掃描時的修改時間，輸出為 RFC 3339；舊版報告或已刪除的檔案為 None
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_rfc3339",
+        deserialize_with = "deserialize_rfc3339"
+    )]
+    modified_at: Option<SystemTime>,
+}
+
+fn serialize_rfc3339<S: serde::Serializer>(modified_at: &Option<SystemTime>, serializer: S) -> Result<S::Ok, S::Error> {
+    match modified_at {
+        Some(modified_at) => {
+            let text = time::OffsetDateTime::from(*modified_at)
+                .format(&Rfc3339)
+                .map_err(serde::ser::Error::custom)?;
+            serializer.serialize_some(&text)
+        }
+        None => serializer.serialize_none(),
     }
+}
 
-    Err("無法從 GPT 回應中提取總結".into())
+fn deserialize_rfc3339<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<SystemTime>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|text| {
+            time::OffsetDateTime::parse(&text, &Rfc3339)
+                .map(SystemTime::from)
+                .map_err(serde::de::Error::custom)
+        })
+        .transpose()
 }
 
-// 定義檔案資訊結構
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct FileInfo {
+// 攤平後的單一檔案資訊（/api/file-tree/flat）
+#[derive(Debug, Serialize, Clone)]
+struct FileMetadata {
+    path: String,
     name: String,
+    // 小寫、不含點的副檔名，沒有副檔名時為空字串
+    extension: String,
+    size_bytes: u64,
     summary: Option<String>,
+    dir_path: String,
+    change_status: Option<ChangeStatus>,
+    #[serde(skip_serializing_if = "SummaryQuality::is_ok")]
+    quality: SummaryQuality,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_rfc3339")]
+    modified_at: Option<SystemTime>,
+}
+
+// 掃描目錄時的選項
+struct ScanOptions {
+    extensions: ExtensionSet,
+    hash_files: bool,
+    // 相對於專案根目錄的最大遞迴深度
+    max_depth: usize,
+    // 略過測試檔案（--exclude-tests）
+    exclude_tests: bool,
+    // 同一層子目錄與檔案的排列順序（--sort-by）
+    sort_by: SortOrder,
 }
 
 // 定義目錄結構
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct Directory {
     name: String,
+    // 沒有子目錄或檔案時不輸出
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     subdirs: Vec<Directory>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     files: Vec<FileInfo>,
     path: String,
+    // 達到最大深度，底下還有子目錄未被掃描
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    max_depth_reached: bool,
+    // prune_to_depth 省略了此目錄的內容（/api/directory-tree/depth/<n>）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
 }
 
 impl Directory {
@@ -172,11 +305,28 @@ impl Directory {
             subdirs: Vec::new(),
             files: Vec::new(),
             path,
+            max_depth_reached: false,
+            truncated: false,
         }
     }
 
-    // 修改後的 from_path 函數，添加了排序功能
-    fn from_path(path: &Path, collect_files: bool) -> Self {
+    // 修改後的 from_path 函數，添加了排序功能；root 為專案根目錄，用於累積忽略規則。
+    // 會以 fs::read_dir 走訪目錄（阻塞），async 函式中請透過 run_blocking 呼叫
+    fn from_path(root: &Path, path: &Path, collect_files: bool, options: &ScanOptions) -> Self {
+        let ignore = IgnoreSet::from_ancestors(root, path, DEFAULT_IGNORE_PATTERNS);
+        let depth = path.strip_prefix(root).map(|p| p.components().count()).unwrap_or(0);
+        Directory::from_path_with_ignore(path, depth, collect_files, &ignore, options)
+    }
+
+    // 遞迴走訪目錄，沿途累積各層 .qprignore / .gitignore 的規則；depth 為相對於根目錄的深度
+    fn from_path_with_ignore(
+        path: &Path,
+        depth: usize,
+        collect_files: bool,
+        ignore: &IgnoreSet,
+        options: &ScanOptions,
+    ) -> Self {
+        let ignore = ignore.with_dir(path);
         let name = path
             .file_name()
             .unwrap_or_default()
@@ -192,130 +342,623 @@ impl Directory {
             let mut files = Vec::new();
             for entry in entries.flatten() {
                 let entry_path = entry.path();
-                if entry_path.is_dir() && !is_hidden_or_common_ignore(&entry_path) {
+                let is_dir = entry_path.is_dir();
+                if ignore.is_ignored(&entry_path, is_dir) {
+                    continue;
+                }
+                if is_dir {
                     dirs.push(entry_path);
-                } else if collect_files && entry_path.is_file() && Directory::is_code_file(&entry_path) {
-                    files.push(entry_path);
+                } else if collect_files && entry_path.is_file() {
+                    if let Some(language) = Directory::is_code_file(&entry_path, options) {
+                        files.push((entry_path, language));
+                    }
                 }
             }
 
-            // 對目錄和檔案進行排序
-            dirs.sort_by(|a, b| a.file_name().unwrap_or_default().cmp(&b.file_name().unwrap_or_default()));
-            files.sort_by(|a, b| a.file_name().unwrap_or_default().cmp(&b.file_name().unwrap_or_default()));
+            // 對目錄和檔案依名稱排序（逐位元組比較、區分大小寫），輸出順序不受 read_dir 的順序影響
+            dirs.sort_by(|a, b| a.file_name().unwrap_or_default().cmp(b.file_name().unwrap_or_default()));
+            files.sort_by(|(a, _), (b, _)| a.file_name().unwrap_or_default().cmp(b.file_name().unwrap_or_default()));
 
-            for entry_path in dirs {
-                dir.subdirs.push(Directory::from_path(&entry_path, collect_files));
+            // 達到 max_depth 時不再往下遞迴，只標記此目錄底下還有未掃描的子目錄
+            if depth < options.max_depth {
+                for entry_path in dirs {
+                    dir.subdirs.push(Directory::from_path_with_ignore(
+                        &entry_path,
+                        depth + 1,
+                        collect_files,
+                        &ignore,
+                        options,
+                    ));
+                }
+            } else if !dirs.is_empty() {
+                tracing::debug!("{} 已達最大深度 {}，略過 {} 個子目錄", path_str, options.max_depth, dirs.len());
+                dir.max_depth_reached = true;
             }
 
-            for entry_path in files {
+            for (entry_path, language) in files {
                 if let Some(file_name) = entry_path.file_name() {
                     if let Some(file_name_str) = file_name.to_str() {
+                        let content_hash = if options.hash_files {
+                            fs::read(&entry_path).ok().map(|content| cache::content_hash(&content))
+                        } else {
+                            None
+                        };
+                        let metadata = entry_path.metadata().ok();
+                        let size_bytes = metadata.as_ref().map(|m| m.len()).unwrap_or(0);
+                        let modified_at = metadata.and_then(|m| m.modified().ok());
                         dir.files.push(FileInfo {
                             name: file_name_str.to_string(),
                             summary: None,
+                            content_hash,
+                            size_bytes,
+                            change_status: None,
+                            encoding_lossy: false,
+                            language: Some(language),
+                            quality: SummaryQuality::Ok,
+                            modified_at,
                         });
                     }
                 }
             }
+            dir.sort_entries(options.sort_by);
         }
 
         dir
     }
 
-    // 判斷檔案是否為程式碼檔案
-    fn is_code_file(path: &Path) -> bool {
-        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-            CODE_FILE_EXTENSIONS.contains(&ext)
-        } else {
-            false
+    // 依 --sort-by 重新排列此層的子目錄與檔案；排序是穩定的，大小或時間相同時維持名稱順序
+    fn sort_entries(&mut self, order: SortOrder) {
+        match order {
+            SortOrder::Alphabetical => {}
+            SortOrder::BySize => {
+                self.subdirs.sort_by_cached_key(|d| std::cmp::Reverse(d.total_size_bytes()));
+                self.files.sort_by_key(|f| std::cmp::Reverse(f.size_bytes));
+            }
+            // 沒有修改時間的項目排在最後
+            SortOrder::ByModTime => {
+                self.subdirs.sort_by_cached_key(|d| std::cmp::Reverse(d.latest_modified_at()));
+                self.files.sort_by_key(|f| std::cmp::Reverse(f.modified_at));
+            }
         }
     }
 
-    // 收集所有資料夾名稱，格式化為字串（供 GPT 使用）
-    fn collect_folders(&self) -> String {
-        let mut result = String::new();
-        self.collect_folders_recursively(0, &mut result, false);
-        result
+    // 判斷檔案是否為要摘要的程式碼檔案並回傳其語言，啟用 exclude_tests 時排除測試檔案
+    fn is_code_file(path: &Path, options: &ScanOptions) -> Option<Language> {
+        if options.exclude_tests && extensions::is_test_file(path) {
+            return None;
+        }
+        options.extensions.is_code_file(path)
     }
 
     // 修改後的遞迴收集函數，新增 include_files 參數
-    fn collect_folders_recursively(&self, depth: usize, result: &mut String, include_files: bool) {
-        for _ in 0..depth {
-            result.push_str("  ");
-        }
-        result.push_str(&self.name);
-        result.push_str("\n");
+    fn collect_folders_recursively(&self, root: &Path, result: &mut String, include_files: bool) {
+        let relative = relative_path(root, Path::new(&self.path));
+        result.push_str(&relative);
+        result.push('\n');
         for subdir in &self.subdirs {
-            subdir.collect_folders_recursively(depth + 1, result, include_files);
+            subdir.collect_folders_recursively(root, result, include_files);
         }
         if include_files {
             for file in &self.files {
-                for _ in 0..(depth + 1) {
-                    result.push_str("  ");
-                }
-                result.push_str(&file.name);
-                result.push_str("\n");
+                result.push_str(&relative_path(root, &Path::new(&self.path).join(&file.name)));
+                result.push('\n');
             }
         }
     }
 
-    // 收集需要生成摘要的檔案
-    fn collect_files_to_summarize(&mut self, filtered_folders: &[String]) -> Vec<(String, String)> {
+    // 收集需要生成摘要的檔案，選定的資料夾會以 from_path 重新掃描（阻塞，async 函式中請使用 collect_files_blocking）
+    // 回傳 (路徑, 檔名, 目錄的 .qpr_prompt)；inherited_prompt 為上層目錄的自訂提示語
+    fn collect_files_to_summarize(
+        &mut self,
+        root: &Path,
+        filtered_folders: &[String],
+        options: &ScanOptions,
+        inherited_prompt: Option<String>,
+    ) -> Vec<(String, String, Option<String>)> {
         let mut files = Vec::new();
-        if filtered_folders.iter().any(|folder| self.name.to_lowercase() == folder.to_lowercase()) {
+        let prompt = read_prompt_override(Path::new(&self.path)).or(inherited_prompt);
+        // 以相對路徑比對，避免不同層級的同名資料夾（例如 src 與 vendor/lib/src）被一併選入
+        let relative = relative_path(root, Path::new(&self.path)).to_lowercase();
+        if filtered_folders.iter().any(|folder| normalize_folder_key(root, folder) == relative) {
             // 重新從檔案系統中收集其所有子目錄和檔案
-            *self = Directory::from_path(Path::new(&self.path), true);
+            *self = Directory::from_path(root, Path::new(&self.path), true, options);
 
-            // 收集當前目錄及其子目錄的所有檔案
-            self.collect_all_files(&mut files);
+            // 收集當前目錄及其子目錄的所有檔案，子目錄的 .qpr_prompt 優先
+            self.collect_files_with_prompt(prompt, &mut files);
         } else {
             // 遞迴檢查子目錄
             for subdir in &mut self.subdirs {
-                files.extend(subdir.collect_files_to_summarize(filtered_folders));
+                files.extend(subdir.collect_files_to_summarize(root, filtered_folders, options, prompt.clone()));
             }
         }
         files
     }
 
+    // 收集所有檔案並附上最接近的 .qpr_prompt
+    fn collect_files_with_prompt(&self, inherited_prompt: Option<String>, files: &mut Vec<(String, String, Option<String>)>) {
+        let prompt = read_prompt_override(Path::new(&self.path)).or(inherited_prompt);
+        for file in &self.files {
+            let file_path = Path::new(&self.path).join(&file.name).to_string_lossy().to_string();
+            files.push((file_path, file.name.clone(), prompt.clone()));
+        }
+        for subdir in &self.subdirs {
+            subdir.collect_files_with_prompt(prompt.clone(), files);
+        }
+    }
+
+    // 從目錄樹移除 excluded 中的資料夾（正規化的相對路徑）
+    fn remove_folders(&mut self, root: &Path, excluded: &[String]) {
+        self.subdirs
+            .retain(|subdir| !excluded.contains(&relative_path(root, Path::new(&subdir.path)).to_lowercase()));
+        for subdir in &mut self.subdirs {
+            subdir.remove_folders(root, excluded);
+        }
+    }
+
+    // 目錄樹中的檔案總數
+    fn file_count(&self) -> usize {
+        self.files.len() + self.subdirs.iter().map(Directory::file_count).sum::<usize>()
+    }
+
+    // 子目錄總數（不含自身）
+    fn dir_count(&self) -> usize {
+        self.subdirs.len() + self.subdirs.iter().map(Directory::dir_count).sum::<usize>()
+    }
+
+    // 目錄樹中所有檔案的大小總和（bytes）
+    fn total_size_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.size_bytes).sum::<u64>()
+            + self.subdirs.iter().map(Directory::total_size_bytes).sum::<u64>()
+    }
+
+    // 目錄樹中最近修改的檔案的修改時間
+    fn latest_modified_at(&self) -> Option<SystemTime> {
+        let files = self.files.iter().filter_map(|f| f.modified_at);
+        files.chain(self.subdirs.iter().filter_map(Directory::latest_modified_at)).max()
+    }
+
+    // 最深的巢狀層數，沒有子目錄時為 0
+    fn max_depth(&self) -> usize {
+        self.subdirs.iter().map(|d| d.max_depth() + 1).max().unwrap_or(0)
+    }
+
+    // 只保留前 max_depth 層（根目錄為第 0 層）的副本，更深的子目錄以只有名稱與路徑、truncated 為 true 的節點取代
+    fn prune_to_depth(&self, max_depth: usize) -> Directory {
+        let subdirs = self
+            .subdirs
+            .iter()
+            .map(|subdir| match max_depth.checked_sub(1) {
+                Some(remaining) => subdir.prune_to_depth(remaining),
+                None => Directory {
+                    truncated: true,
+                    ..Directory::new(subdir.name.clone(), subdir.path.clone())
+                },
+            })
+            .collect();
+        Directory {
+            name: self.name.clone(),
+            subdirs,
+            files: self.files.clone(),
+            path: self.path.clone(),
+            max_depth_reached: self.max_depth_reached,
+            truncated: false,
+        }
+    }
+
+    // 檢查由 JSON 讀入的目錄樹是否符合掃描時的結構：子目錄直接位於上層目錄之下、name 為 path 的最後一段、
+    // 同一目錄中沒有重複的名稱；依路徑查找目錄與檔案的邏輯都依賴這些條件。
+    // 名稱不可含分隔符號或 `..`，否則檔案路徑會跳出專案目錄（例如 name 為絕對路徑）
+    fn validate(&self) -> Result<(), String> {
+        if Path::new(&self.path)
+            .components()
+            .any(|c| matches!(c, Component::CurDir | Component::ParentDir))
+        {
+            return Err(format!("目錄路徑 {} 含有 `.` 或 `..`", self.path));
+        }
+        let last_component = Path::new(&self.path).file_name().unwrap_or_default().to_string_lossy();
+        if last_component != self.name {
+            return Err(format!("目錄 {} 的名稱 {:?} 與路徑的最後一段不符", self.path, self.name));
+        }
+        let mut names = HashSet::new();
+        for subdir in &self.subdirs {
+            if !is_plain_name(&subdir.name) {
+                return Err(format!("目錄 {} 中的子目錄名稱 {:?} 無效", self.path, subdir.name));
+            }
+            // 子目錄的路徑必須是上層路徑加上自身的名稱
+            if Path::new(&subdir.path).strip_prefix(&self.path).ok() != Some(Path::new(&subdir.name)) {
+                return Err(format!("子目錄 {} 不在上層目錄 {} 之下", subdir.path, self.path));
+            }
+            if !names.insert(subdir.name.as_str()) {
+                return Err(format!("目錄 {} 中有重複的名稱 {:?}", self.path, subdir.name));
+            }
+        }
+        for file in &self.files {
+            if !is_plain_name(&file.name) {
+                return Err(format!("目錄 {} 中的檔案名稱 {:?} 無效", self.path, file.name));
+            }
+            if !names.insert(file.name.as_str()) {
+                return Err(format!("目錄 {} 中有重複的名稱 {:?}", self.path, file.name));
+            }
+        }
+        self.subdirs.iter().try_for_each(Directory::validate)
+    }
+
+    // 將目錄樹中位於 from 之下的路徑改為位於 to 之下，根目錄名稱改為 to 的最後一段（POST /api/import）
+    fn rebase(&mut self, from: &Path, to: &Path) {
+        let relative = Path::new(&self.path).strip_prefix(from).unwrap_or(Path::new("")).to_path_buf();
+        if relative.as_os_str().is_empty() {
+            self.path = to.to_string_lossy().to_string();
+            self.name = to.file_name().unwrap_or_default().to_string_lossy().to_string();
+        } else {
+            self.path = to.join(relative).to_string_lossy().to_string();
+        }
+        for subdir in &mut self.subdirs {
+            subdir.rebase(from, to);
+        }
+    }
+
+    // 將 other 遞迴合併進此目錄樹，根目錄名稱改為 "<此樹> + <other>"（/projects/merged-tree）
+    fn merge(&mut self, other: &Directory) {
+        self.name = format!("{} + {}", self.name, other.name);
+        self.merge_contents(other);
+    }
+
+    // 同名的子目錄合併內容，同名檔案只保留一個（已有摘要者優先），other 獨有的項目附加在後；
+    // 合併後檔案的完整路徑以所在目錄的 path 為準
+    fn merge_contents(&mut self, other: &Directory) {
+        for file in &other.files {
+            match self.files.iter_mut().find(|f| f.name == file.name) {
+                Some(existing) => {
+                    if existing.summary.is_none() && file.summary.is_some() {
+                        *existing = file.clone();
+                    }
+                }
+                None => self.files.push(file.clone()),
+            }
+        }
+        for subdir in &other.subdirs {
+            match self.subdirs.iter_mut().find(|d| d.name == subdir.name) {
+                Some(existing) => existing.merge_contents(subdir),
+                None => self.subdirs.push(subdir.clone()),
+            }
+        }
+        self.max_depth_reached |= other.max_depth_reached;
+    }
+
+    // 因達到最大深度而未掃描子目錄的目錄數
+    fn max_depth_reached_count(&self) -> usize {
+        usize::from(self.max_depth_reached) + self.subdirs.iter().map(Directory::max_depth_reached_count).sum::<usize>()
+    }
+
     // 收集當前目錄及其所有子目錄的所有檔案
-    fn collect_all_files(&self, files: &mut Vec<(String, String)>) {
+    fn collect_all_files_with_metadata(&self, files: &mut Vec<FileMetadata>) {
         for file in &self.files {
             let file_path = Path::new(&self.path).join(&file.name).to_string_lossy().to_string();
-            files.push((file_path, file.name.clone()));
+            files.push(FileMetadata {
+                path: file_path,
+                name: file.name.clone(),
+                extension: file_extension(Path::new(&file.name)),
+                size_bytes: file.size_bytes,
+                summary: file.summary.clone(),
+                dir_path: self.path.clone(),
+                change_status: file.change_status,
+                quality: file.quality,
+                modified_at: file.modified_at,
+            });
+        }
+        for subdir in &self.subdirs {
+            subdir.collect_all_files_with_metadata(files);
+        }
+    }
+
+    // 收集目錄樹中已計算的內容雜湊
+    fn collect_content_hashes(&self, hashes: &mut BTreeMap<String, String>) {
+        for file in &self.files {
+            if let Some(hash) = &file.content_hash {
+                let file_path = Path::new(&self.path).join(&file.name).to_string_lossy().to_string();
+                hashes.insert(file_path, hash.clone());
+            }
         }
         for subdir in &self.subdirs {
-            subdir.collect_all_files(files);
+            subdir.collect_content_hashes(hashes);
+        }
+    }
+
+    // 相對於此目錄的路徑元件；路徑不在此目錄底下時回傳 None。
+    // 逐個元件比對而非比對字串前綴，/root/src_extra/b.rs 不會被當成 /root/src 底下的檔案
+    fn relative_components<'a>(&self, path: &'a str) -> Option<Vec<&'a str>> {
+        let root = path_components(&self.path);
+        let parts = path_components(path);
+        if parts.len() < root.len() || parts[..root.len()] != root[..] {
+            return None;
+        }
+        Some(parts[root.len()..].to_vec())
+    }
+
+    // 依完整路徑逐層往下找目錄，每層只比對子目錄名稱
+    fn find_dir_by_path(&self, path: &str) -> Option<&Directory> {
+        let mut dir = self;
+        for name in self.relative_components(path)? {
+            dir = dir.subdirs.iter().find(|d| d.name == name)?;
+        }
+        Some(dir)
+    }
+
+    fn find_dir_by_path_mut(&mut self, path: &str) -> Option<&mut Directory> {
+        let mut dir = self;
+        for name in dir.relative_components(path)? {
+            dir = dir.subdirs.iter_mut().find(|d| d.name == name)?;
+        }
+        Some(dir)
+    }
+
+    // 記錄檔案內容是否經過 UTF-8 替換
+    fn set_encoding_lossy(&mut self, file_path: &str, encoding_lossy: bool) {
+        if let Some(file) = self.find_file_by_path_mut(file_path) {
+            file.encoding_lossy = encoding_lossy;
         }
     }
 
+    // 清除整個目錄樹的檔案摘要
+    fn clear_summaries(&mut self) {
+        for file in &mut self.files {
+            file.summary = None;
+            file.quality = SummaryQuality::Ok;
+        }
+        for subdir in &mut self.subdirs {
+            subdir.clear_summaries();
+        }
+    }
+
+    // 重新評估整個目錄樹的摘要品質
+    fn assess_summary_quality(&mut self) {
+        for file in &mut self.files {
+            file.quality = file.summary.as_deref().map(quality::assess_summary_quality).unwrap_or_default();
+        }
+        for subdir in &mut self.subdirs {
+            subdir.assess_summary_quality();
+        }
+    }
+
+    // 依完整路徑找檔案
+    fn find_file_by_path(&self, path: &str) -> Option<&FileInfo> {
+        let mut parts = self.relative_components(path)?;
+        let name = parts.pop()?;
+        let mut dir = self;
+        for part in parts {
+            dir = dir.subdirs.iter().find(|d| d.name == part)?;
+        }
+        dir.files.iter().find(|f| f.name == name)
+    }
+
+    fn find_file_by_path_mut(&mut self, path: &str) -> Option<&mut FileInfo> {
+        let mut parts = self.relative_components(path)?;
+        let name = parts.pop()?;
+        let mut dir = self;
+        for part in parts {
+            dir = dir.subdirs.iter_mut().find(|d| d.name == part)?;
+        }
+        dir.files.iter_mut().find(|f| f.name == name)
+    }
+
     // 更新檔案摘要
     fn update_file_summary(&mut self, file_path: &str, summary: String) {
-        if self.path == file_path {
-            // 當前路徑即為檔案路徑
-            if let Some(file) = self.files.iter_mut().find(|f| {
-                let full_path = format!("{}/{}", self.path, f.name);
-                full_path == file_path
-            }) {
-                file.summary = Some(summary);
-            }
+        let path = Path::new(file_path);
+        let Some(dir) = self.parent_dir_mut(path) else {
             return;
+        };
+        if let Some(file) = dir
+            .files
+            .iter_mut()
+            .find(|f| path.file_name() == Some(OsStr::new(&f.name)))
+        {
+            file.quality = quality::assess_summary_quality(&summary);
+            file.summary = Some(summary);
         }
+    }
 
-        // 遞迴更新子目錄
-        for subdir in &mut self.subdirs {
-            if file_path.starts_with(&subdir.path) {
-                subdir.update_file_summary(file_path, summary.clone());
-            }
+    // 找到檔案所在的目錄節點，上層目錄不在樹中時回傳 None
+    fn parent_dir_mut(&mut self, path: &Path) -> Option<&mut Directory> {
+        let parent = path.parent()?.to_str()?;
+        self.find_dir_by_path_mut(parent)
+    }
+
+    // 將新檔案加入目錄樹（已存在時不重複加入），回傳是否為新加入；大小與修改時間由呼叫端從 metadata 取得
+    fn add_file(&mut self, file_path: &str, size_bytes: u64, modified_at: Option<SystemTime>) -> bool {
+        let path = Path::new(file_path);
+        let (Some(dir), Some(name)) = (self.parent_dir_mut(path), path.file_name()) else {
+            return false;
+        };
+        let name = name.to_string_lossy().to_string();
+        if dir.files.iter().any(|f| f.name == name) {
+            return false;
         }
+        dir.files.push(FileInfo {
+            name,
+            summary: None,
+            content_hash: None,
+            size_bytes,
+            change_status: None,
+            encoding_lossy: false,
+            language: Some(Language::from_path(path)),
+            quality: SummaryQuality::Ok,
+            modified_at,
+        });
+        dir.files.sort_by(|a, b| a.name.cmp(&b.name));
+        true
+    }
+
+    // 從目錄樹移除檔案，回傳是否有移除
+    fn remove_file(&mut self, file_path: &str) -> bool {
+        let path = Path::new(file_path);
+        let Some(dir) = self.parent_dir_mut(path) else {
+            return false;
+        };
+        let before = dir.files.len();
+        dir.files.retain(|f| path.file_name() != Some(OsStr::new(&f.name)));
+        dir.files.len() != before
+    }
+}
+
+// 以自身為根目錄，列出所有資料夾的相對路徑，每行一個（供 GPT 使用），根目錄為 `.`
+impl std::fmt::Display for Directory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut result = String::new();
+        self.collect_folders_recursively(Path::new(&self.path), &mut result, false);
+        f.write_str(&result)
+    }
+}
+
+// 與 Directory 的 Display 相同，但另外列出各資料夾中的檔案
+struct DisplayWithFiles<'a>(&'a Directory);
+
+impl std::fmt::Display for DisplayWithFiles<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut result = String::new();
+        self.0
+            .collect_folders_recursively(Path::new(&self.0.path), &mut result, true);
+        f.write_str(&result)
+    }
+}
+
+// 單一層的檔案或目錄名稱：非空、不含 `/`、`\` 或 `..`，且只有一個一般路徑元件
+fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    !name.contains(['/', '\\'])
+        && !name.contains("..")
+        && matches!((components.next(), components.next()), (Some(Component::Normal(_)), None))
+}
+
+// 將路徑拆成元件，同時接受 `/` 與系統原生分隔符號，略過空元件與 `.`
+fn path_components(path: &str) -> Vec<&str> {
+    path.split(['/', std::path::MAIN_SEPARATOR])
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect()
+}
+
+// 讀取目錄中的 .qpr_prompt；內容沒有 `{}` 時將程式碼附加在最後
+fn read_prompt_override(dir: &Path) -> Option<String> {
+    let prompt = fs::read_to_string(dir.join(PROMPT_OVERRIDE_FILE)).ok()?;
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        return None;
     }
+    if prompt.contains("{}") {
+        Some(prompt.to_string())
+    } else {
+        Some(format!("{}\n{{}}", prompt))
+    }
+}
+
+// 由檔案所在目錄往上找到專案根目錄為止，回傳最接近的 .qpr_prompt
+fn find_prompt_override(root: &Path, file_path: &Path) -> Option<String> {
+    file_path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(root))
+        .find_map(read_prompt_override)
 }
 
 // 從使用者輸入取得要保留的資料夾名稱
-fn get_folders_to_add() -> String {
-    println!("請輸入要保留的資料夾名稱（以逗號分隔，或輸入 'ok' 表示完成）：");
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).expect("無法讀取輸入");
-    input.trim().to_string()
+// 在獨立執行緒讀取標準輸入，避免阻塞 async runtime；讀到 EOF 時關閉 channel
+fn spawn_stdin_reader() -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+// 分析流程目前的階段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum AnalysisPhase {
+    // 正在選擇要摘要的資料夾
+    GatheringFolders,
+    Summarizing,
+    Done,
+}
+
+// 資料夾選擇的狀態
+#[derive(Debug, Default)]
+struct FolderSelection {
+    // 使用者補充的資料夾提示，會附加在資料夾分析的提示語後
+    extra_prompt: String,
+    filtered_folders: Vec<String>,
+    // skip_folders_analysis：不摘要的資料夾（正規化的相對路徑）及其子目錄，包含 exclude_dirs 與隱藏資料夾
+    excluded_folders: Vec<String>,
+}
+
+// GET /get-file 的查詢參數
+#[derive(Deserialize)]
+struct FileParams {
+    path: String,
+}
+
+// GET /api/file-content/<path> 的查詢參數
+#[derive(Deserialize)]
+struct FileContentParams {
+    // 為 true 時回傳以 Prism.js 上色的 HTML 頁面
+    highlight: Option<bool>,
+}
+
+// GET /api/search 的查詢參數
+#[derive(Deserialize)]
+struct SearchParams {
+    q: String,
+}
+
+// GET /api/prompt-preview 的查詢參數；file_path 可為絕對路徑或相對於專案目錄的路徑
+#[derive(Deserialize)]
+struct PromptPreviewParams {
+    file_path: String,
+    #[serde(default)]
+    chunk_index: usize,
+}
+
+// GET /api/cross-references 的查詢參數，預設區分大小寫
+#[derive(Deserialize)]
+struct CrossReferenceParams {
+    name: String,
+    case_sensitive: Option<bool>,
+}
+
+// GET /api/similar 的查詢參數；path 可為絕對路徑或相對於專案目錄的路徑
+#[derive(Deserialize)]
+struct SimilarParams {
+    path: String,
+    top_k: Option<usize>,
+}
+
+// GET /api/timeline 的查詢參數；since 為 Unix 毫秒，只回傳之後的事件
+#[derive(Deserialize)]
+struct TimelineParams {
+    since: Option<u64>,
+}
+
+// GET /api/file-tree/flat 的查詢參數
+#[derive(Deserialize)]
+struct FlatTreeParams {
+    ext: Option<String>,
+    has_summary: Option<bool>,
+    // name、path、size 或 mtime，未指定時依路徑排序
+    sort: Option<String>,
+    // 只列出相對於 --baseline 新增、修改或刪除的檔案
+    changed_only: Option<bool>,
+}
+
+// GET /api/search/by-extension/<ext> 的查詢參數
+#[derive(Deserialize)]
+struct ExtensionSearchParams {
+    // 預設不附上摘要，只瀏覽檔案清單
+    with_summaries: Option<bool>,
+    // 與 /api/file-tree/flat 相同：name、path、size 或 mtime
+    sort: Option<String>,
 }
 
 // 定義進度結構
@@ -323,140 +966,3237 @@ fn get_folders_to_add() -> String {
 struct Progress {
     total_files: usize,
     completed_files: usize,
-    summaries: HashMap<String, String>,
+    summaries: BTreeMap<String, String>,
+    failed_files: Vec<String>,
+    // 超過 max_file_size 而未送出摘要請求的檔案
+    skipped_large_files: Vec<String>,
+    // 用完 token_budget 後未送出摘要請求的檔案
+    budget_exceeded_files: Vec<String>,
+    // 不是有效 UTF-8、以替換字元讀取內容的檔案
+    encoding_lossy_files: Vec<String>,
+    rollup_summary: Option<String>,
+    // 目錄路徑 -> 該目錄的整體摘要（/api/directory-summary 生成後快取）
+    directory_summaries: BTreeMap<String, String>,
+    // 檔案路徑 -> 使用者的手動註記，匯出時依 mode 與摘要合併
+    annotations: BTreeMap<String, Annotation>,
+    // 檔案路徑 -> 該檔案的 LLM 摘要請求耗時（毫秒，包含分段與重試）
+    latency_ms: BTreeMap<String, u64>,
+    total_tokens_used: u64,
+    // 檔案路徑 -> 生成摘要時的內容雜湊，寫入快取用
+    #[serde(skip)]
+    content_hashes: BTreeMap<String, String>,
+    #[serde(skip)]
+    change_stats: ChangeStats,
+    // 檔案路徑 -> 摘要時讀取內容所計算的行數
+    #[serde(skip)]
+    line_counts: HashMap<String, usize>,
+    // 摘要完成後第一次查詢 /api/stats 時計算，摘要有變動時清除
+    #[serde(skip)]
+    project_stats: Option<ProjectStats>,
+    // 檔案路徑 -> 超過 summary_max_length 而被截斷的摘要原文
+    #[serde(skip)]
+    full_summaries: BTreeMap<String, String>,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // 使用有效的 API 金鑰
-    dotenv().ok();
-    let api_key = env::var("OPENAI_API_KEY").expect("未設置 OPENAI_API_KEY");
+impl Progress {
+    // 合併使用者註記後的摘要，用於匯出
+    // 摘要是否只是失敗、檔案過大或超出預算的標記，這些不寫入快取也不納入總覽
+    fn is_placeholder(&self, path: &str) -> bool {
+        self.failed_files.iter().any(|p| p == path)
+            || self.skipped_large_files.iter().any(|p| p == path)
+            || self.budget_exceeded_files.iter().any(|p| p == path)
+    }
 
-    // 指定專案目錄路徑
-    let path = Path::new(PROJECT_PATH);
-    let mut project = Directory::from_path(path, false); // 初次僅收集目錄
+    fn annotated_summaries(&self) -> BTreeMap<String, String> {
+        annotations::merge_annotations(&self.summaries, &self.annotations)
+    }
 
-    // 1. 初始收集資料夾
-    let folders = project.collect_folders();
-    println!("收集的資料夾：\n{}", folders);
+    // 摘要耗時最長的檔案 (路徑, 毫秒)，由慢到快
+    fn slowest_files(&self) -> Vec<(String, u64)> {
+        let mut files: Vec<(String, u64)> = self
+            .latency_ms
+            .iter()
+            .map(|(path, latency)| (path.clone(), *latency))
+            .collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        files.truncate(SLOWEST_FILES_LIMIT);
+        files
+    }
+}
 
-    // 2. 初始呼叫 GPT 進行資料夾過濾
-    let mut extra_prompt = String::new(); // 保存使用者補充的資料夾
-    let filtered_folders = analyze_folders_with_gpt(&folders, &extra_prompt, &api_key).await?;
-    println!("重新過濾後的結果：\n{}", filtered_folders);
+// 伺服器與摘要任務共享的狀態
+#[derive(Clone)]
+struct AppState {
+    project: Arc<RwLock<Directory>>,
+    progress: Arc<RwLock<Progress>>,
+    folder_selection: Arc<RwLock<FolderSelection>>,
+    phase: Arc<RwLock<AnalysisPhase>>,
+    scan_options: Arc<ScanOptions>,
+    config: Arc<QprConfig>,
+    backend: Arc<dyn LlmBackend>,
+    // 啟動時讀取的摘要快取；DELETE /api/cache 會清除
+    cache: Arc<RwLock<SummaryCache>>,
+    events: broadcast::Sender<ServerEvent>,
+    semaphore: Arc<Semaphore>,
+    metrics: Arc<Metrics>,
+    // --baseline 報告中的檔案雜湊，用於標記檔案的變動狀態
+    baseline: Option<Arc<BaselineHashes>>,
+    // 結束訊號與背景摘要任務
+    shutdown: Shutdown,
+    // 摘要完成後建立的 TF-IDF 索引（/api/similar），摘要或註記變動時重建
+    similarity: Arc<RwLock<Option<SimilarityIndex>>>,
+    // 摘要開始、完成、API 錯誤與快取命中的時間軸（/api/timeline）
+    timeline: Timeline,
+    // 終端機進度條；--no-progress-bar 或標準錯誤輸出不是終端機時為 None，改為逐檔輸出日誌
+    progress_bar: Option<SummaryProgress>,
+    // --debug-llm 時保存的 LLM 原始回應（/api/raw-llm-response），未啟用時為 None
+    debug_log: Option<Arc<std::sync::Mutex<DebugLog>>>,
+}
 
-    // 3. 解析 GPT 回應
-    let analysis: GPTAnalysis = serde_json::from_str(&filtered_folders)?;
-    let mut filtered_folder_list = analysis.analysis_key;
+// 摘要完成時推送給 SSE 客戶端的事件
+#[derive(Debug, Serialize, Clone)]
+struct SummaryEvent {
+    file_path: String,
+    summary: String,
+    completed: usize,
+    total: usize,
+    total_tokens_used: u64,
+}
 
-    // 4. 互動式資料夾選擇
-    loop {
-        let folders_to_add = get_folders_to_add();
-        if folders_to_add.to_lowercase() == "ok" {
-            break;
-        }
+// 檔案被刪除時推送的事件（SSE 事件類型為 delete）
+#[derive(Debug, Serialize, Clone)]
+struct DeleteEvent {
+    file_path: String,
+}
 
-        // 將新增資料夾加到 GPT 請求中
-        extra_prompt.push_str(&format!(", 請再額外判斷 {}", folders_to_add));
+// 透過 /api/events 推送的所有事件
+#[derive(Debug, Clone)]
+enum ServerEvent {
+    Summary(SummaryEvent),
+    Delete(DeleteEvent),
+}
 
-        // 再次過濾資料夾，包含新的資料夾清單
-        let updated_folders = project.collect_folders();
-        let filtered_folders = analyze_folders_with_gpt(&updated_folders, &extra_prompt, &api_key).await?;
-        println!("重新過濾後的結果：\n{}", filtered_folders);
+// 重新掃描的結果
+#[derive(Debug, Serialize)]
+struct RescanResult {
+    new_files: usize,
+    changed_files: usize,
+    removed_files: usize,
+}
 
-        // 解析更新後的 GPT 回應
-        let analysis: GPTAnalysis = serde_json::from_str(&filtered_folders)?;
-        filtered_folder_list = analysis.analysis_key.clone();
-    }
+// 與快取比對的檔案變動統計（/api/stats）
+#[derive(Debug, Default, Serialize, Clone)]
+struct ChangeStats {
+    unchanged_files: usize,
+    changed_files: usize,
+    new_files: usize,
+}
 
-    // 5. 列出最終選定的資料夾結構
-    println!("最終選定的資料夾為：\n{:#?}", filtered_folder_list);
+// /api/stats 的回應內容
+#[derive(Serialize)]
+struct Stats<'a> {
+    #[serde(flatten)]
+    changes: &'a ChangeStats,
+    skipped_large_files: &'a [String],
+    budget_exceeded_files: &'a [String],
+    encoding_lossy_files: &'a [String],
+    // 目前目錄樹的規模，檔案數僅包含已選定資料夾中收集到的檔案
+    file_count: usize,
+    dir_count: usize,
+    max_depth: usize,
+    // 摘要請求耗時最長的檔案 (路徑, 毫秒)
+    slowest_files: Vec<(String, u64)>,
+    // 摘要完成後才提供的專案統計
+    #[serde(flatten)]
+    project: Option<ProjectStats>,
+}
 
-    // 6. 為選定的資料夾收集檔案並生成摘要
-    let files_to_summarize = project.collect_files_to_summarize(&filtered_folder_list);
+// GET /api/file-summary/<path> 的回應：單一檔案的摘要與程式碼統計
+#[derive(Debug, Serialize)]
+struct FileSummary {
+    path: String,
+    summary: Option<String>,
+    // 截斷前的摘要；未超過 summary_max_length 時與 summary 相同
+    summary_full: Option<String>,
+    annotation: Option<String>,
+    size_bytes: u64,
+    line_count: usize,
+    extension: String,
+    content_hash: String,
+    // RFC 3339（UTC）
+    last_modified: Option<String>,
+    latency_ms: Option<u64>,
+}
 
-    // 定義進度狀態
-    let progress = Arc::new(RwLock::new(Progress {
-        total_files: files_to_summarize.len(),
-        completed_files: 0,
-        summaries: HashMap::new(),
-    }));
+// GET /api/prompt-preview 的回應：摘要該檔案時第 chunk_index 段實際送出的提示語
+#[derive(Debug, Serialize)]
+struct PromptPreview {
+    prompt: String,
+    estimated_tokens: usize,
+    would_chunk: bool,
+    chunk_count: usize,
+}
 
-    // 共享的項目目錄結構
-    let project_arc = Arc::new(RwLock::new(project));
-
-    // 異步生成檔案摘要
-    let mut tasks = Vec::new();
-    for (file_path, _file_name) in files_to_summarize {
-        let api_key_clone = api_key.clone();
-        let progress_clone = Arc::clone(&progress);
-        let project_clone = Arc::clone(&project_arc);
-        tasks.push(tokio::spawn(async move {
-            let file_content = fs::read_to_string(&file_path).unwrap_or_default();
-            let summary = if file_content.trim().is_empty() {
-                "檔案內容為空".to_string()
-            } else {
-                summarize_file_with_gpt(file_content.clone(), api_key_clone)
-                    .await
-                    .unwrap_or_else(|_| "摘要生成失敗".to_string())
-            };
+// GET /api/browse/<path> 回應中的單一目錄或檔案；目錄的大小為其下所有檔案的總和
+#[derive(Debug, Serialize)]
+struct BrowseEntry {
+    name: String,
+    path: String,
+    size_bytes: u64,
+    // 檔案為其摘要，目錄為已生成的目錄摘要
+    summary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    change_status: Option<ChangeStatus>,
+}
 
-            // 更新進度
-            {
-                let mut progress = progress_clone.write().await;
-                progress.completed_files += 1;
-                progress.summaries.insert(file_path.clone(), summary.clone());
-            }
+// GET /api/browse/<path> 的回應，只包含該目錄的直接子項目
+#[derive(Debug, Serialize)]
+struct BrowseResponse {
+    name: String,
+    path: String,
+    dirs: Vec<BrowseEntry>,
+    files: Vec<BrowseEntry>,
+}
 
-            // 更新項目目錄結構中的摘要
-            {
-                let mut project = project_clone.write().await;
-                project.update_file_summary(&file_path, summary);
-            }
+// /api/folders/confirm 的請求內容：直接指定要摘要的資料夾（相對路徑）
+#[derive(Debug, Deserialize)]
+struct ConfirmFoldersRequest {
+    folders: Vec<String>,
+}
 
-            println!("已完成摘要：{}", file_path);
-        }));
-    }
+// /api/status 的回應內容，供首頁依階段顯示不同畫面
+#[derive(Debug, Serialize)]
+struct StatusResponse {
+    phase: AnalysisPhase,
+    // LLM 正在分析資料夾（資料夾選擇被鎖定中）
+    analyzing: bool,
+    filtered_folders: Vec<String>,
+    total_files: usize,
+    completed_files: usize,
+}
 
-    // 等待所有任務完成
-    join_all(tasks).await;
+// POST /api/folders/suggest 的請求內容；folders_text 省略時使用目前目錄樹的資料夾清單
+#[derive(Debug, Deserialize)]
+struct SuggestFoldersRequest {
+    #[serde(default)]
+    folders_text: Option<String>,
+    #[serde(default)]
+    extra_hint: String,
+}
 
-    // 從 Arc 中取出項目目錄結構
-    let project = Arc::try_unwrap(project_arc).unwrap().into_inner();
+// /api/folders/manual-select 的請求內容
+#[derive(Debug, Deserialize)]
+struct ManualSelectRequest {
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    finalize: bool,
+}
 
-    // 7. 準備啟動 Web 伺服器顯示Quick Project Report 和進度
-    let project_arc = Arc::new(RwLock::new(project));
-    let progress_arc = Arc::clone(&progress);
+// POST /api/batch-summarize 的請求內容，路徑可為絕對路徑或相對於專案目錄的路徑
+#[derive(Debug, Deserialize)]
+struct BatchSummarizeRequest {
+    paths: Vec<String>,
+}
 
-    // 定義 /filtered-tree 端點
-    let project_clone = Arc::clone(&project_arc);
+// POST /api/batch-summarize 中未加入佇列的路徑與原因
+#[derive(Debug, Serialize)]
+struct SkippedPath {
+    path: String,
+    reason: String,
+}
+
+// POST /api/import 的回應：匯入的檔案數與目錄數（不含根目錄）
+#[derive(Debug, Serialize)]
+struct ImportResult {
+    imported_files: usize,
+    imported_dirs: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchSummarizeResponse {
+    queued: usize,
+    skipped: Vec<SkippedPath>,
+}
+
+// POST /api/annotate 的請求內容；annotation 為空字串時刪除註記
+#[derive(Debug, Deserialize)]
+struct AnnotateRequest {
+    file_path: String,
+    annotation: String,
+    #[serde(default)]
+    mode: AnnotationMode,
+}
+
+// /api/regenerate 的請求內容
+#[derive(Debug, Deserialize)]
+struct RegenerateRequest {
+    file_path: String,
+}
+
+// 相對於專案根目錄的路徑，以 `/` 分隔；根目錄本身為 `.`
+fn relative_path(root: &Path, path: &Path) -> String {
+    let relative: Vec<String> = path
+        .strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    if relative.is_empty() {
+        ".".to_string()
+    } else {
+        relative.join("/")
+    }
+}
+
+// 小寫、不含 `.` 的副檔名，沒有副檔名時為空字串
+fn file_extension(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+// 將 LLM 或使用者給的資料夾路徑整理成與 relative_path 相同的格式（小寫）
+fn normalize_folder_key(root: &Path, folder: &str) -> String {
+    let folder = folder.trim().replace('\\', "/");
+    let folder = match Path::new(&folder).strip_prefix(root) {
+        Ok(relative) => relative.to_string_lossy().to_string(),
+        Err(_) => folder,
+    };
+    let folder = folder.trim_start_matches("./").trim_matches('/');
+    if folder.is_empty() {
+        ".".to_string()
+    } else {
+        folder.to_lowercase()
+    }
+}
+
+// 確認路徑位於專案目錄內（避免 `..` 或符號連結跳脫），回傳正規化後的路徑
+fn resolve_project_path(root: &str, file_path: &str) -> Result<PathBuf, QprError> {
+    let root = fs::canonicalize(root)?;
+    let path = fs::canonicalize(file_path)?;
+    if !path.starts_with(&root) {
+        return Err(QprError::PathNotAllowed(file_path.to_string()));
+    }
+    Ok(path)
+}
+
+// 為單一檔案生成摘要，完成後更新進度與目錄結構
+async fn summarize_file_task(state: AppState, file_path: String, prompt_override: Option<String>) {
+    // 收到結束訊號後不再開始新的檔案，進行中的請求則照常完成
+    if state.shutdown.is_cancelled() {
+        return;
+    }
+
+    // 過大的檔案（例如自動產生的程式碼）不讀取內容，以標記取代摘要
+    let file_size = run_blocking({
+        let file_path = file_path.clone();
+        move || fs::metadata(file_path).map(|m| m.len()).unwrap_or(0)
+    })
+    .await;
+    if file_size > state.config.max_file_size {
+        skip_large_file(&state, file_path, file_size).await;
+        return;
+    }
+
+    let bytes = match read_file_blocking(&file_path).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            fail_unreadable_file(&state, file_path, e).await;
+            return;
+        }
+    };
+    let content_hash = state.scan_options.hash_files.then(|| cache::content_hash(&bytes));
+    let (file_content, encoding_lossy) = decode_utf8_lossy(bytes);
+    if encoding_lossy {
+        tracing::warn!("檔案不是有效的 UTF-8，以替換字元讀取：{}", file_path);
+    }
+    let cache_status = state.cache.read().await.lookup(&file_path, content_hash.as_deref());
+    let mut failed = false;
+    let mut latency_ms = None;
+    let summary = if file_content.trim().is_empty() {
+        "檔案內容為空".to_string()
+    } else if let CacheStatus::Unchanged(cached) = &cache_status {
+        // 內容未變動，使用快取中的摘要，不重新呼叫 LLM
+        state.timeline.record(TimelineEventKind::CacheHit { path: file_path.clone() });
+        cached.clone()
+    } else {
+        // 取得許可後才送出請求，限制同時進行的 API 呼叫數量
+        let _permit = state.semaphore.acquire().await.expect("semaphore 已關閉");
+        // 排隊等待額度期間收到結束訊號時，不再送出請求
+        if state.shutdown.is_cancelled() {
+            return;
+        }
+        // 已用完 token_budget 時不再送出請求；進行中的請求仍會完成，實際用量可能略超過預算
+        if state
+            .config
+            .token_budget
+            .is_some_and(|budget| state.backend.total_tokens_used() >= budget)
+        {
+            skip_budget_exceeded_file(&state, file_path).await;
+            return;
+        }
+        // [prompts.by_language]：依檔案語言在提示語前加上說明
+        let prompt_override = state
+            .config
+            .file_summary_template(&Language::from_path(Path::new(&file_path)), prompt_override);
+        state.timeline.record(TimelineEventKind::SummarizationStarted { path: file_path.clone() });
+        let started = Instant::now();
+        // 後端沒有回應時不讓單一檔案卡住整個摘要流程；逾時包含所有分段與重試
+        let result = tokio::time::timeout(
+            std::time::Duration::from_millis(state.config.per_file_total_timeout_ms),
+            timeline::scope(
+                state.timeline.clone(),
+                file_path.clone(),
+                debug_log::scope(
+                    state.debug_log.clone(),
+                    file_path.clone(),
+                    state
+                        .backend
+                        .summarize_file(&file_content, &file_extension(Path::new(&file_path)), prompt_override.as_deref()),
+                ),
+            ),
+        )
+        .await;
+        latency_ms = Some(started.elapsed().as_millis() as u64);
+        state.timeline.record(TimelineEventKind::SummarizationCompleted {
+            path: file_path.clone(),
+            duration_ms: latency_ms.unwrap_or_default(),
+        });
+        match result {
+            Ok(Ok(summary)) => summary,
+            Ok(Err(err)) => {
+                tracing::error!("摘要生成失敗：{}（{}）", file_path, err);
+                failed = true;
+                "摘要生成失敗".to_string()
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "摘要逾時（超過 {} ms），略過此檔案：{}",
+                    state.config.per_file_total_timeout_ms,
+                    file_path
+                );
+                failed = true;
+                TIMEOUT_MARKER.to_string()
+            }
+        }
+    };
+    // 快取的摘要已截斷過，只需取回原文
+    let (summary, full_summary) = if let CacheStatus::Unchanged(_) = &cache_status {
+        let full_summary = state.cache.read().await.full_summaries.get(&file_path).cloned();
+        (summary, full_summary)
+    } else {
+        limit_summary_length(summary, state.config.summary_max_length)
+    };
+
+    state.metrics.record_file(failed);
+
+    // 更新進度
+    let event = {
+        let mut progress = state.progress.write().await;
+        progress.completed_files += 1;
+        progress.failed_files.retain(|path| path != &file_path);
+        progress.budget_exceeded_files.retain(|path| path != &file_path);
+        if failed {
+            progress.failed_files.push(file_path.clone());
+        }
+        if let Some(hash) = &content_hash {
+            progress.content_hashes.insert(file_path.clone(), hash.clone());
+        }
+        progress.line_counts.insert(file_path.clone(), stats::count_lines(&file_content));
+        set_encoding_lossy(&mut progress, &file_path, encoding_lossy);
+        if let Some(latency_ms) = latency_ms {
+            progress.latency_ms.insert(file_path.clone(), latency_ms);
+        }
+        progress.project_stats = None;
+        match cache_status {
+            CacheStatus::Unchanged(_) => progress.change_stats.unchanged_files += 1,
+            CacheStatus::Changed => progress.change_stats.changed_files += 1,
+            CacheStatus::New => progress.change_stats.new_files += 1,
+        }
+        progress.summaries.insert(file_path.clone(), summary.clone());
+        set_full_summary(&mut progress, &file_path, full_summary);
+        progress.total_tokens_used = state.backend.total_tokens_used();
+        SummaryEvent {
+            file_path: file_path.clone(),
+            summary: summary.clone(),
+            completed: progress.completed_files,
+            total: progress.total_files,
+            total_tokens_used: progress.total_tokens_used,
+        }
+    };
+
+    // 通知所有 SSE 客戶端（沒有訂閱者時忽略錯誤）
+    let _ = state.events.send(ServerEvent::Summary(event));
+
+    // 更新項目目錄結構中的摘要
+    {
+        let mut project = state.project.write().await;
+        project.update_file_summary(&file_path, summary.clone());
+        project.set_encoding_lossy(&file_path, encoding_lossy);
+    }
+    if !failed {
+        write_summary_sidecar(&state.config, &file_path, &summary).await;
+    }
+
+    // 進度條已顯示目前的檔案，逐檔的日誌只在除錯時輸出
+    if state.progress_bar.is_some() {
+        tracing::debug!("已完成摘要：{}", file_path);
+    } else {
+        tracing::info!("已完成摘要：{}", file_path);
+    }
+}
+
+// --output-dir／--sidecar：將摘要寫入 <輸出目錄>/<相對路徑>.qpr_summary，寫入失敗只記錄警告
+async fn write_summary_sidecar(config: &QprConfig, file_path: &str, summary: &str) {
+    let Some(output_dir) = config.summary_output_dir() else {
+        return;
+    };
+    let Ok(relative) = Path::new(file_path).strip_prefix(&config.project_path) else {
+        return;
+    };
+    let mut sidecar = output_dir.join(relative).into_os_string();
+    sidecar.push(SUMMARY_SIDECAR_SUFFIX);
+    let sidecar = PathBuf::from(sidecar);
+    let result = run_blocking({
+        let sidecar = sidecar.clone();
+        let summary = summary.to_string();
+        move || {
+            match sidecar.parent() {
+                Some(dir) => fs::create_dir_all(dir),
+                None => Ok(()),
+            }
+            .and_then(|_| fs::write(&sidecar, summary))
+        }
+    })
+    .await;
+    if let Err(e) = result {
+        tracing::warn!("無法寫入摘要檔案 {}：{}", sidecar.display(), e);
+    }
+}
+
+// 摘要超過 max_chars 個字元時在句子邊界截斷，回傳 (截斷後的摘要, 截斷前的原文)
+fn limit_summary_length(summary: String, max_chars: usize) -> (String, Option<String>) {
+    let truncated = truncate_at_sentence_boundary(&summary, max_chars);
+    if truncated.len() == summary.len() {
+        return (summary, None);
+    }
+    let truncated = truncated.to_string();
+    (truncated, Some(summary))
+}
+
+// 從第 max_chars 個字元往前找最後一個句子結尾（`. ` 或 `。`），找不到時直接在 max_chars 截斷
+fn truncate_at_sentence_boundary(s: &str, max_chars: usize) -> &str {
+    let Some((limit, _)) = s.char_indices().nth(max_chars) else {
+        return s;
+    };
+    let head = &s[..limit];
+    let ideographic = head.rfind('。').map(|i| i + '。'.len_utf8());
+    // `. ` 的空白可以剛好落在上限的位置
+    let latin = if head.ends_with('.') && s[limit..].starts_with(' ') {
+        Some(limit)
+    } else {
+        head.rfind(". ").map(|i| i + 1)
+    };
+    match ideographic.max(latin) {
+        Some(end) => &s[..end],
+        None => &s[..limit],
+    }
+}
+
+// 記錄截斷前的摘要；未截斷時移除先前的紀錄
+fn set_full_summary(progress: &mut Progress, file_path: &str, full_summary: Option<String>) {
+    match full_summary {
+        Some(full_summary) => {
+            progress.full_summaries.insert(file_path.to_string(), full_summary);
+        }
+        None => {
+            progress.full_summaries.remove(file_path);
+        }
+    }
+}
+
+// ===========================
+// 阻塞的檔案系統操作
+// ===========================
+// fs::read、fs::metadata、fs::read_dir 等會占住目前的執行緒。摘要任務與 HTTP handler 都在 Tokio 的
+// 工作執行緒上執行，在 async 函式中新增檔案系統操作時，請以 run_blocking 包起來，不要直接呼叫 std::fs
+
+// 在 blocking 執行緒池中執行 f，不占用 Tokio 的工作執行緒
+async fn run_blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.expect("檔案系統任務已中止")
+}
+
+// 讀取整個檔案
+async fn read_file_blocking(path: &str) -> io::Result<Vec<u8>> {
+    let path = path.to_string();
+    run_blocking(move || fs::read(path)).await
+}
+
+// 在 blocking 執行緒池中呼叫 collect_files_to_summarize；project 暫時以空目錄取代，掃描完成後放回
+async fn collect_files_blocking(
+    project: &mut Directory,
+    root: &Path,
+    filtered_folders: Vec<String>,
+    options: Arc<ScanOptions>,
+) -> Vec<(String, String, Option<String>)> {
+    let mut tree = std::mem::replace(project, Directory::new(project.name.clone(), project.path.clone()));
+    let root = root.to_path_buf();
+    let (tree, files) = run_blocking(move || {
+        let files = tree.collect_files_to_summarize(&root, &filtered_folders, &options, None);
+        (tree, files)
+    })
+    .await;
+    *project = tree;
+    files
+}
+
+// 以 UTF-8 解碼檔案內容，無效的位元組以 U+FFFD 取代；回傳 (內容, 是否有替換)
+fn decode_utf8_lossy(bytes: Vec<u8>) -> (String, bool) {
+    match String::from_utf8(bytes) {
+        Ok(content) => (content, false),
+        Err(e) => (String::from_utf8_lossy(e.as_bytes()).into_owned(), true),
+    }
+}
+
+// 更新進度中以替換字元讀取的檔案清單
+fn set_encoding_lossy(progress: &mut Progress, file_path: &str, encoding_lossy: bool) {
+    progress.encoding_lossy_files.retain(|path| path != file_path);
+    if encoding_lossy {
+        progress.encoding_lossy_files.push(file_path.to_string());
+    }
+}
+
+// 依序為檔案生成摘要：最多同時處理 concurrency + max_queue_depth 個檔案，
+// 其中只有 concurrency 個能取得 semaphore 呼叫 API，其餘已讀取內容並排隊等待
+async fn run_summary_pipeline(state: &AppState, files: Vec<(String, Option<String>)>) {
+    let concurrency = state.config.backend_concurrency() as usize;
+    let in_flight = concurrency + state.config.max_queue_depth.unwrap_or(concurrency);
+    if let Some(progress_bar) = &state.progress_bar {
+        progress_bar.start(files.len());
+    }
+    stream::iter(files)
+        .map(|(file_path, prompt)| async move {
+            let task = summarize_file_task(state.clone(), file_path.clone(), prompt);
+            match &state.progress_bar {
+                Some(progress_bar) => {
+                    progress_bar.scope(file_path.clone(), task).await;
+                    progress_bar.file_done(&file_path);
+                }
+                None => task.await,
+            }
+        })
+        .buffer_unordered(in_flight)
+        .collect::<()>()
+        .await;
+    if let Some(progress_bar) = &state.progress_bar {
+        progress_bar.finish();
+    }
+
+    if let Some(budget) = state.config.token_budget {
+        let progress = state.progress.read().await;
+        let not_summarized = progress.budget_exceeded_files.len();
+        if not_summarized > 0 {
+            tracing::warn!(
+                "Token budget of {} exhausted after {} files. {} files were not summarized.",
+                budget,
+                progress.completed_files.saturating_sub(not_summarized),
+                not_summarized
+            );
+        }
+    }
+}
+
+// 攤平目錄樹並套用 /api/file-tree/flat 的篩選與排序
+fn flat_file_list(project: &Directory, summaries: &BTreeMap<String, String>, params: &FlatTreeParams) -> Result<Vec<FileMetadata>, QprError> {
+    let mut files = Vec::new();
+    project.with_summaries(summaries).collect_all_files_with_metadata(&mut files);
+
+    if let Some(ext) = &params.ext {
+        let ext = ext.trim_start_matches('.').to_lowercase();
+        files.retain(|file| file.extension == ext);
+    }
+    if let Some(has_summary) = params.has_summary {
+        files.retain(|file| file.summary.is_some() == has_summary);
+    }
+    if params.changed_only == Some(true) {
+        files.retain(|file| {
+            matches!(
+                file.change_status,
+                Some(ChangeStatus::New | ChangeStatus::Modified | ChangeStatus::Deleted)
+            )
+        });
+    }
+    match params.sort.as_deref().unwrap_or("path") {
+        "path" => files.sort_by(|a, b| a.path.cmp(&b.path)),
+        "name" => files.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()).then_with(|| a.path.cmp(&b.path))),
+        "size" => files.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes).then_with(|| a.path.cmp(&b.path))),
+        // 最近修改的在前，沒有修改時間的檔案排在最後
+        "mtime" => files.sort_by(|a, b| b.modified_at.cmp(&a.modified_at).then_with(|| a.path.cmp(&b.path))),
+        other => {
+            return Err(QprError::InvalidRequest(format!("不支援的排序方式：{}（可用：name、path、size、mtime）", other)));
+        }
+    }
+    Ok(files)
+}
+
+// 列出某個副檔名的所有檔案（/api/search/by-extension），格式與 /api/file-tree/flat 相同，
+// 未指定 with_summaries=true 時不附上摘要
+fn files_by_extension(
+    project: &Directory,
+    summaries: &BTreeMap<String, String>,
+    ext: &str,
+    params: ExtensionSearchParams,
+) -> Result<Vec<FileMetadata>, QprError> {
+    let flat_params = FlatTreeParams {
+        ext: Some(ext.to_string()),
+        has_summary: None,
+        sort: params.sort,
+        changed_only: None,
+    };
+    let mut files = flat_file_list(project, summaries, &flat_params)?;
+    if params.with_summaries != Some(true) {
+        for file in &mut files {
+            file.summary = None;
+        }
+    }
+    Ok(files)
+}
+
+// 記錄超過大小上限的檔案，摘要以 `[FILE TOO LARGE — N bytes]` 標記
+async fn skip_large_file(state: &AppState, file_path: String, file_size: u64) {
+    let summary = format!("[FILE TOO LARGE — {} bytes]", file_size);
+    state.metrics.record_file(false);
+    let event = {
+        let mut progress = state.progress.write().await;
+        progress.completed_files += 1;
+        progress.failed_files.retain(|path| path != &file_path);
+        if !progress.skipped_large_files.contains(&file_path) {
+            progress.skipped_large_files.push(file_path.clone());
+        }
+        progress.content_hashes.remove(&file_path);
+        progress.project_stats = None;
+        progress.summaries.insert(file_path.clone(), summary.clone());
+        SummaryEvent {
+            file_path: file_path.clone(),
+            summary: summary.clone(),
+            completed: progress.completed_files,
+            total: progress.total_files,
+            total_tokens_used: progress.total_tokens_used,
+        }
+    };
+    let _ = state.events.send(ServerEvent::Summary(event));
+    state.project.write().await.update_file_summary(&file_path, summary);
+    tracing::warn!("檔案超過大小上限（{} bytes），略過摘要：{}", file_size, file_path);
+}
+
+// 記錄無法讀取（例如摘要前已被刪除或沒有讀取權限）的檔案，不送出摘要請求，標記為失敗
+async fn fail_unreadable_file(state: &AppState, file_path: String, err: io::Error) {
+    tracing::error!("無法讀取檔案，略過摘要：{}（{}）", file_path, err);
+    let summary = "檔案讀取失敗".to_string();
+    state.metrics.record_file(true);
+    let event = {
+        let mut progress = state.progress.write().await;
+        progress.completed_files += 1;
+        if !progress.failed_files.contains(&file_path) {
+            progress.failed_files.push(file_path.clone());
+        }
+        progress.content_hashes.remove(&file_path);
+        progress.project_stats = None;
+        progress.summaries.insert(file_path.clone(), summary.clone());
+        SummaryEvent {
+            file_path: file_path.clone(),
+            summary: summary.clone(),
+            completed: progress.completed_files,
+            total: progress.total_files,
+            total_tokens_used: progress.total_tokens_used,
+        }
+    };
+    let _ = state.events.send(ServerEvent::Summary(event));
+    state.project.write().await.update_file_summary(&file_path, summary);
+}
+
+// 記錄用完 token_budget 而未摘要的檔案，摘要以 [BUDGET EXCEEDED] 標記
+async fn skip_budget_exceeded_file(state: &AppState, file_path: String) {
+    let summary = BUDGET_EXCEEDED_MARKER.to_string();
+    let event = {
+        let mut progress = state.progress.write().await;
+        progress.completed_files += 1;
+        progress.failed_files.retain(|path| path != &file_path);
+        if !progress.budget_exceeded_files.contains(&file_path) {
+            progress.budget_exceeded_files.push(file_path.clone());
+        }
+        progress.content_hashes.remove(&file_path);
+        progress.project_stats = None;
+        progress.summaries.insert(file_path.clone(), summary.clone());
+        SummaryEvent {
+            file_path: file_path.clone(),
+            summary: summary.clone(),
+            completed: progress.completed_files,
+            total: progress.total_files,
+            total_tokens_used: progress.total_tokens_used,
+        }
+    };
+    let _ = state.events.send(ServerEvent::Summary(event));
+    state.project.write().await.update_file_summary(&file_path, summary);
+    tracing::debug!("已用完 token 預算，略過摘要：{}", file_path);
+}
+
+// 取得專案統計，尚未計算過（或摘要已變動）時重新計算並快取
+async fn cached_project_stats(state: &AppState) -> ProjectStats {
+    if let Some(stats) = &state.progress.read().await.project_stats {
+        return stats.clone();
+    }
+    let project = state.project.read().await;
+    let mut progress = state.progress.write().await;
+    let stats = ProjectStats::compute(&project, &progress.summaries, &progress.line_counts);
+    progress.project_stats = Some(stats.clone());
+    stats
+}
+
+// 以 LLM 重新分析資料夾，hints 為使用者補充要考慮的資料夾
+async fn refine_folders(state: &AppState, hints: &[String]) -> Result<Vec<String>, QprError> {
+    if *state.phase.read().await != AnalysisPhase::GatheringFolders {
+        return Err(QprError::PhaseConflict("摘要已開始，無法再調整資料夾".to_string()));
+    }
+
+    let folders = state.project.read().await.to_string();
+
+    // 持有鎖直到分析完成，讓多次分析依序進行
+    let mut selection = state.folder_selection.write().await;
+    if !hints.is_empty() {
+        selection
+            .extra_prompt
+            .push_str(&format!(", please also consider {}", hints.join(", ")));
+    }
+    let response = state.backend.analyze_folders(&folders, &selection.extra_prompt).await?;
+    tracing::info!("重新過濾後的結果：\n{}", response);
+    selection.filtered_folders = llm::parse_folder_analysis(&response)?;
+    Ok(selection.filtered_folders.clone())
+}
+
+// --skip-folders-analysis：不呼叫 LLM，選定 include_dirs（未設定時為整個專案），
+// 並排除 exclude_dirs 與隱藏資料夾
+async fn select_folders_without_analysis(state: &AppState) {
+    let root = Path::new(&state.config.project_path);
+    let folders = state.project.read().await.to_string();
+    let folders: Vec<String> = folders.lines().map(str::to_lowercase).collect();
+
+    let mut selection = state.folder_selection.write().await;
+    selection.filtered_folders = if state.config.include_dirs.is_empty() {
+        vec![".".to_string()]
+    } else {
+        state
+            .config
+            .include_dirs
+            .iter()
+            .map(|folder| normalize_folder_key(root, folder))
+            .collect()
+    };
+    for folder in &selection.filtered_folders {
+        if !folders.contains(folder) {
+            tracing::warn!("include_dirs 中的資料夾不在目錄樹中（不存在或已被忽略）：{}", folder);
+        }
+    }
+    selection.excluded_folders = state
+        .config
+        .exclude_dirs
+        .iter()
+        .map(|folder| normalize_folder_key(root, folder))
+        .chain(
+            folders
+                .into_iter()
+                .filter(|folder| folder.split('/').any(|name| name.starts_with('.') && name != ".")),
+        )
+        .collect();
+    tracing::info!("略過 LLM 資料夾分析，選定的資料夾：{}", selection.filtered_folders.join(", "));
+}
+
+// relative（正規化的相對路徑）是否為 folder 或位於其中
+fn folder_contains(folder: &str, relative: &str) -> bool {
+    folder == "." || relative == folder || relative.starts_with(&format!("{}/", folder))
+}
+
+// 以 LLM 分析資料夾並回傳建議的清單，不修改目前的資料夾選擇
+async fn suggest_folders(state: &AppState, request: SuggestFoldersRequest) -> Result<Vec<String>, QprError> {
+    let folders = match request.folders_text {
+        Some(folders_text) if !folders_text.trim().is_empty() => folders_text,
+        _ => state.project.read().await.to_string(),
+    };
+    let extra_hint = request.extra_hint.trim();
+    let extra_prompt = if extra_hint.is_empty() {
+        String::new()
+    } else {
+        format!(", please also consider {}", extra_hint)
+    };
+    let response = state.backend.analyze_folders(&folders, &extra_prompt).await?;
+    llm::parse_folder_analysis(&response)
+}
+
+// 命令列互動式資料夾選擇；輸入 ok 時回傳 true，標準輸入結束或已從網頁開始摘要時回傳 false
+async fn select_folders_interactively(state: &AppState) -> bool {
+    let mut lines = spawn_stdin_reader();
+    loop {
+        if *state.phase.read().await != AnalysisPhase::GatheringFolders {
+            return false;
+        }
+        println!("請輸入要保留的資料夾路徑（相對於專案目錄，以逗號分隔，或輸入 'ok' 表示完成）：");
+        let Some(input) = lines.recv().await else {
+            return false;
+        };
+        if *state.phase.read().await != AnalysisPhase::GatheringFolders {
+            return false;
+        }
+
+        let input = input.trim();
+        if input.eq_ignore_ascii_case("ok") {
+            return true;
+        }
+        let hints: Vec<String> = input
+            .split(',')
+            .map(|hint| hint.trim().to_string())
+            .filter(|hint| !hint.is_empty())
+            .collect();
+        if hints.is_empty() {
+            continue;
+        }
+        if let Err(e) = refine_folders(state, &hints).await {
+            tracing::error!("資料夾分析失敗：{}", e);
+        }
+    }
+}
+
+// 結束資料夾選擇並收集要摘要的檔案，每次執行只能開始一次
+async fn begin_summarization(state: &AppState) -> Result<Vec<(String, Option<String>)>, QprError> {
+    let mut phase = state.phase.write().await;
+    if *phase != AnalysisPhase::GatheringFolders {
+        return Err(QprError::PhaseConflict("摘要已開始".to_string()));
+    }
+
+    let selection = state.folder_selection.read().await;
+
+    let root = Path::new(&state.config.project_path);
+    let mut project = state.project.write().await;
+    let mut files: Vec<(String, Option<String>)> =
+        collect_files_blocking(&mut project, root, selection.filtered_folders.clone(), state.scan_options.clone())
+            .await
+            .into_iter()
+            .map(|(file_path, _, prompt)| (file_path, prompt))
+            .collect();
+    // 選定的資料夾會連同所有子目錄一起收集，排除的資料夾需另外移除
+    if !selection.excluded_folders.is_empty() {
+        project.remove_folders(root, &selection.excluded_folders);
+        files.retain(|(file_path, _)| {
+            let relative = relative_path(root, Path::new(file_path)).to_lowercase();
+            !selection.excluded_folders.iter().any(|folder| folder_contains(folder, &relative))
+        });
+    }
+    drop(selection);
+    if let Some(baseline) = &state.baseline {
+        project.apply_baseline(root, baseline);
+    }
+    tracing::info!("最終選定的資料夾為：\n{}", project.to_tree_string(false));
+    drop(project);
+    state.progress.write().await.total_files = files.len();
+    *phase = AnalysisPhase::Summarizing;
+    Ok(files)
+}
+
+// 在背景為選定的資料夾生成摘要，全部完成後生成總覽並寫入快取
+async fn spawn_summarization(state: AppState) -> Result<(), QprError> {
+    let files = begin_summarization(&state).await?;
+    let tasks = state.shutdown.tasks.clone();
+    tasks.spawn(async move {
+        run_summary_pipeline(&state, files).await;
+        // 中途收到結束訊號時由 run() 寫入快取，不生成總覽
+        if state.shutdown.is_cancelled() {
+            return;
+        }
+        finish_summarization(&state).await;
+
+        // --watch：摘要完成後持續監看選定的資料夾
+        if state.config.watch {
+            if let Err(e) = watch::watch_project(state).await {
+                tracing::error!("{}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+// 將未選定資料夾中的檔案加入目錄樹並在背景生成摘要，與初次摘要共用 semaphore 與 SSE 事件
+async fn batch_summarize(state: AppState, request: BatchSummarizeRequest) -> Result<BatchSummarizeResponse, QprError> {
+    if *state.phase.read().await == AnalysisPhase::GatheringFolders {
+        return Err(QprError::PhaseConflict("尚未開始摘要，請先選定資料夾".to_string()));
+    }
+
+    let root = Path::new(&state.config.project_path);
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+    for requested in request.paths {
+        let file_path = root.join(&requested).to_string_lossy().to_string();
+        let path = Path::new(&file_path);
+        // 路徑檢查、是否為程式碼檔案（exclude_tests 時會讀取內容）與 metadata 都需要存取檔案系統
+        let checked = run_blocking({
+            let project_path = state.config.project_path.clone();
+            let file_path = file_path.clone();
+            let scan_options = state.scan_options.clone();
+            move || {
+                let path = Path::new(&file_path);
+                resolve_project_path(&project_path, &file_path).map_err(|e| e.to_string())?;
+                if !path.is_file() {
+                    return Err("不是檔案".to_string());
+                }
+                if Directory::is_code_file(path, &scan_options).is_none() {
+                    return Err("不是程式碼檔案".to_string());
+                }
+                let metadata = fs::metadata(path).ok();
+                Ok((
+                    metadata.as_ref().map(|m| m.len()).unwrap_or(0),
+                    metadata.and_then(|m| m.modified().ok()),
+                ))
+            }
+        })
+        .await;
+        let reason = match checked {
+            Err(reason) => Some(reason),
+            Ok(_) if state.progress.read().await.summaries.contains_key(&file_path) => Some("已有摘要".to_string()),
+            Ok((size_bytes, modified_at)) => {
+                // 所在目錄不在樹中（被忽略或超過 max_depth）或檔案已在摘要清單中時不加入
+                let mut project = state.project.write().await;
+                if project.parent_dir_mut(path).is_none() {
+                    Some("所在目錄不在目錄樹中".to_string())
+                } else if !project.add_file(&file_path, size_bytes, modified_at) {
+                    Some("已在摘要清單中".to_string())
+                } else {
+                    None
+                }
+            }
+        };
+        match reason {
+            Some(reason) => skipped.push(SkippedPath { path: requested, reason }),
+            None => {
+                let prompt = run_blocking({
+                    let root = root.to_path_buf();
+                    let file_path = file_path.clone();
+                    move || find_prompt_override(&root, Path::new(&file_path))
+                })
+                .await;
+                files.push((file_path, prompt));
+            }
+        }
+    }
+
+    let queued = files.len();
+    if queued > 0 {
+        state.progress.write().await.total_files += queued;
+        tracing::info!("已加入 {} 個檔案到摘要佇列", queued);
+        let tasks = state.shutdown.tasks.clone();
+        tasks.spawn(async move {
+            run_summary_pipeline(&state, files).await;
+            // 初次摘要已完成時快取不會再寫入，這裡補寫；尚未完成時由 finish_summarization 寫入
+            if !state.shutdown.is_cancelled() && *state.phase.read().await == AnalysisPhase::Done {
+                if let Err(e) = save_summary_cache(&state).await {
+                    tracing::error!("無法寫入快取檔案 {}：{}", state.config.cache_file, e);
+                }
+                refresh_similarity_index(&state).await;
+            }
+        });
+    }
+    Ok(BatchSummarizeResponse { queued, skipped })
+}
+
+// 所有檔案摘要完成後生成專案總覽，並將成功的摘要寫入快取檔案
+async fn finish_summarization(state: &AppState) {
+    // 已用完 token_budget 時不再為總覽送出請求，可之後以 /api/regenerate-rollup 生成
+    let budget_exceeded = !state.progress.read().await.budget_exceeded_files.is_empty();
+    if budget_exceeded {
+        tracing::warn!("已用完 token 預算，略過專案總覽");
+    } else {
+        match generate_rollup_summary(state).await {
+            Ok(_) => tracing::info!("已生成專案總覽"),
+            Err(e) => tracing::error!("專案總覽生成失敗：{}", e),
+        }
+    }
+
+    if let Err(e) = save_summary_cache(state).await {
+        tracing::error!("無法寫入快取檔案 {}：{}", state.config.cache_file, e);
+    }
+
+    if state.config.print_tree {
+        let summaries = state.progress.read().await.summaries.clone();
+        println!("{}", state.project.read().await.with_summaries(&summaries).to_tree_string(true));
+    }
+
+    *state.phase.write().await = AnalysisPhase::Done;
+    refresh_similarity_index(state).await;
+}
+
+// 以目前的摘要（含使用者註記，不含失敗與略過的檔案）重建相似度索引；摘要尚未全部完成時不建立
+async fn refresh_similarity_index(state: &AppState) {
+    if *state.phase.read().await != AnalysisPhase::Done {
+        return;
+    }
+    let summaries = {
+        let progress = state.progress.read().await;
+        let mut summaries = progress.annotated_summaries();
+        summaries.retain(|path, _| !progress.is_placeholder(path));
+        summaries
+    };
+    let index = SimilarityIndex::build(&summaries);
+    *state.similarity.write().await = Some(index);
+}
+
+// 將成功的摘要與內容雜湊寫入快取檔案，下次執行時未變動的檔案可直接沿用；
+// 只在複製資料時持有讀取鎖，序列化與寫檔在 blocking 執行緒池中進行
+async fn save_summary_cache(state: &AppState) -> Result<(), QprError> {
+    let cache = {
+        let progress = state.progress.read().await;
+        let mut summaries = progress.summaries.clone();
+        summaries.retain(|path, _| !progress.is_placeholder(path));
+        let mut hashes = progress.content_hashes.clone();
+        hashes.retain(|path, _| summaries.contains_key(path));
+        let mut full_summaries = progress.full_summaries.clone();
+        full_summaries.retain(|path, _| summaries.contains_key(path));
+        let annotations = progress.annotations.clone();
+        SummaryCache { summaries, hashes, annotations, full_summaries }
+    };
+    let cache_path = PathBuf::from(&state.config.cache_file);
+    run_blocking(move || cache.save(&cache_path)).await
+}
+
+// 以 /api/export/json 匯出的報告取代目前的目錄樹與摘要，例如檢視在其他機器上完成的分析；
+// 報告中的路徑改寫到本機的專案目錄之下，使用者註記保留，不寫入快取
+async fn import_report(state: &AppState, mut report: Directory) -> Result<ImportResult, QprError> {
+    report
+        .validate()
+        .map_err(|e| QprError::InvalidRequest(format!("報告的目錄樹無效：{}", e)))?;
+    let mut phase = state.phase.write().await;
+    if *phase == AnalysisPhase::Summarizing {
+        return Err(QprError::PhaseConflict("摘要進行中，無法匯入報告".to_string()));
+    }
+
+    let from = PathBuf::from(&report.path);
+    report.rebase(&from, Path::new(&state.config.project_path));
+    // 舊版報告沒有品質欄位，匯入時一律重新評估
+    report.assess_summary_quality();
+    let mut files = Vec::new();
+    report.collect_all_files_with_metadata(&mut files);
+    let result = ImportResult {
+        imported_files: report.file_count(),
+        imported_dirs: report.dir_count(),
+    };
+
+    let mut project = state.project.write().await;
+    let mut progress = state.progress.write().await;
+    progress.summaries = files
+        .into_iter()
+        .filter_map(|file| Some((file.path, file.summary?)))
+        .collect();
+    progress.full_summaries.clear();
+    progress.total_files = result.imported_files;
+    progress.completed_files = result.imported_files;
+    progress.failed_files.clear();
+    progress.skipped_large_files.clear();
+    progress.budget_exceeded_files.clear();
+    progress.encoding_lossy_files.clear();
+    progress.rollup_summary = None;
+    progress.directory_summaries.clear();
+    progress.content_hashes.clear();
+    progress.line_counts.clear();
+    progress.latency_ms.clear();
+    progress.change_stats = ChangeStats::default();
+    progress.project_stats = None;
+    *project = report;
+    *phase = AnalysisPhase::Done;
+    drop((progress, project));
+    *state.similarity.write().await = None;
+    tracing::info!(
+        "已匯入報告：{} 個檔案、{} 個目錄",
+        result.imported_files,
+        result.imported_dirs
+    );
+    Ok(result)
+}
+
+// 清除摘要快取（記憶體中與快取檔案）以及目前所有的摘要，下次摘要時全部重新生成；使用者註記保留。
+// 依 project → progress → cache 的順序持有寫入鎖清除記憶體中的資料，摘要任務無法在清除途中寫入結果；
+// 釋放鎖之後才在 blocking 執行緒池中寫入快取檔案
+async fn clear_summary_cache(state: &AppState) -> Result<usize, QprError> {
+    let mut project = state.project.write().await;
+    let mut progress = state.progress.write().await;
+    let mut cache = state.cache.write().await;
+
+    let mut deleted: HashSet<String> = cache.summaries.keys().cloned().collect();
+    deleted.extend(progress.summaries.keys().cloned());
+    cache.summaries.clear();
+    cache.hashes.clear();
+    cache.full_summaries.clear();
+
+    progress.summaries.clear();
+    progress.full_summaries.clear();
+    progress.completed_files = 0;
+    progress.failed_files.clear();
+    progress.skipped_large_files.clear();
+    progress.budget_exceeded_files.clear();
+    progress.encoding_lossy_files.clear();
+    progress.rollup_summary = None;
+    progress.directory_summaries.clear();
+    progress.content_hashes.clear();
+    progress.line_counts.clear();
+    progress.latency_ms.clear();
+    progress.change_stats = ChangeStats::default();
+    progress.project_stats = None;
+    project.clear_summaries();
+    if let Some(index) = state.similarity.write().await.as_mut() {
+        *index = SimilarityIndex::default();
+    }
+
+    let cleared = SummaryCache {
+        annotations: progress.annotations.clone(),
+        ..SummaryCache::default()
+    };
+    drop((cache, progress, project));
+
+    let cache_path = PathBuf::from(&state.config.cache_file);
+    run_blocking(move || cleared.save(&cache_path)).await?;
+    tracing::info!("已清除摘要快取：{} 筆", deleted.len());
+    Ok(deleted.len())
+}
+
+// 只清除單一檔案的快取摘要，relative 為相對於專案目錄的路徑；回傳清除的筆數（0 或 1）
+async fn invalidate_cached_summary(state: &AppState, relative: &str) -> Result<usize, QprError> {
+    let file_path = Path::new(&state.config.project_path)
+        .join(relative.trim_matches('/'))
+        .to_string_lossy()
+        .to_string();
+    let mut project = state.project.write().await;
+    let mut progress = state.progress.write().await;
+    let mut removed = state.cache.write().await.remove(&file_path);
+
+    progress.full_summaries.remove(&file_path);
+    if progress.summaries.remove(&file_path).is_some() {
+        progress.completed_files = progress.completed_files.saturating_sub(1);
+        removed = true;
+    }
+    progress.failed_files.retain(|path| path != &file_path);
+    progress.skipped_large_files.retain(|path| path != &file_path);
+    progress.budget_exceeded_files.retain(|path| path != &file_path);
+    progress.encoding_lossy_files.retain(|path| path != &file_path);
+    progress.content_hashes.remove(&file_path);
+    progress.line_counts.remove(&file_path);
+    progress.latency_ms.remove(&file_path);
+    progress.project_stats = None;
+    if let Some(file) = project.find_file_by_path_mut(&file_path) {
+        file.summary = None;
+    }
+
+    let cache_path = PathBuf::from(&state.config.cache_file);
+    let removed_from_file = run_blocking(move || {
+        let mut cache_file = SummaryCache::load(&cache_path);
+        if !cache_file.remove(&file_path) {
+            return Ok(false);
+        }
+        cache_file.save(&cache_path).map(|_| true)
+    })
+    .await?;
+    Ok(usize::from(removed || removed_from_file))
+}
+
+// 收到結束訊號後等待背景摘要任務結束（最多 SHUTDOWN_DRAIN_SECS 秒），再寫入各專案的快取
+async fn save_progress_on_shutdown(registry: &ProjectRegistry, shutdown: &Shutdown) {
+    shutdown.tasks.close();
+    if tokio::time::timeout(std::time::Duration::from_secs(SHUTDOWN_DRAIN_SECS), shutdown.tasks.wait())
+        .await
+        .is_err()
+    {
+        tracing::warn!("等待進行中的摘要請求逾時，未完成的檔案將在下次執行時重新生成");
+    }
+    for entry in &registry.projects {
+        // 尚未開始摘要的專案沒有需要保存的進度
+        if entry.state.progress.read().await.summaries.is_empty() {
+            continue;
+        }
+        eprintln!("Saving progress to {} before exit...", entry.state.config.cache_file);
+        if let Err(e) = save_summary_cache(&entry.state).await {
+            tracing::error!("無法寫入快取檔案 {}：{}", entry.state.config.cache_file, e);
+        }
+    }
+}
+
+// 網頁上的資料夾選擇：補充提示後重新分析，finalize 時開始生成摘要
+#[tracing::instrument(skip(state))]
+async fn manual_select_folders(state: AppState, request: ManualSelectRequest) -> Result<Vec<String>, QprError> {
+    if !request.add.is_empty() || !request.finalize {
+        refine_folders(&state, &request.add).await?;
+    }
+    let filtered_folders = state.folder_selection.read().await.filtered_folders.clone();
+    if request.finalize {
+        spawn_summarization(state).await?;
+    }
+    Ok(filtered_folders)
+}
+
+// 以網頁指定的資料夾取代 LLM 的過濾結果，並開始生成摘要
+#[tracing::instrument(skip(state))]
+async fn confirm_folders(state: AppState, request: ConfirmFoldersRequest) -> Result<Vec<String>, QprError> {
+    let folders: Vec<String> = request
+        .folders
+        .iter()
+        .map(|folder| folder.trim().to_string())
+        .filter(|folder| !folder.is_empty())
+        .collect();
+    if folders.is_empty() {
+        return Err(QprError::InvalidRequest("至少需要選擇一個資料夾".to_string()));
+    }
+    if *state.phase.read().await != AnalysisPhase::GatheringFolders {
+        return Err(QprError::PhaseConflict("摘要已開始，無法再調整資料夾".to_string()));
+    }
+
+    state.folder_selection.write().await.filtered_folders = folders.clone();
+    spawn_summarization(state).await?;
+    Ok(folders)
+}
+
+// 目前的分析階段與進度
+// 依 Progress 的檔案數與時間軸上最近完成的檔案估計剩餘時間（/api/completion-estimate 與 SSE 的 progress_estimate）
+async fn completion_estimate(state: &AppState) -> CompletionEstimate {
+    let (completed, total) = {
+        let progress = state.progress.read().await;
+        (progress.completed_files, progress.total_files)
+    };
+    state.timeline.completion_estimate(completed, total).await
+}
+
+async fn current_status(state: &AppState) -> StatusResponse {
+    let phase = *state.phase.read().await;
+    // 資料夾分析期間 folder_selection 被寫入鎖持有，不等待以免阻塞輪詢
+    let (analyzing, filtered_folders) = match state.folder_selection.try_read() {
+        Ok(selection) => (false, selection.filtered_folders.clone()),
+        Err(_) => (true, Vec::new()),
+    };
+    let progress = state.progress.read().await;
+    StatusResponse {
+        phase,
+        analyzing,
+        filtered_folders,
+        total_files: progress.total_files,
+        completed_files: progress.completed_files,
+    }
+}
+
+// 重新掃描專案目錄，保留未變動檔案的摘要，並為新檔案排入摘要任務
+#[tracing::instrument(skip_all)]
+async fn rescan_project(state: AppState) -> Result<RescanResult, QprError> {
+    if *state.phase.read().await == AnalysisPhase::GatheringFolders {
+        return Err(QprError::PhaseConflict("尚未選定資料夾，無法重新掃描".to_string()));
+    }
+    let filtered_folders = state.folder_selection.read().await.filtered_folders.clone();
+
+    // 掃描期間不持有鎖，其他請求照常讀取目前的目錄樹
+    let root = Path::new(&state.config.project_path);
+    let mut new_project = run_blocking({
+        let root = root.to_path_buf();
+        let scan_options = state.scan_options.clone();
+        move || Directory::from_path(&root, &root, false, &scan_options)
+    })
+    .await;
+    let new_files: HashMap<String, Option<String>> =
+        collect_files_blocking(&mut new_project, root, filtered_folders, state.scan_options.clone())
+            .await
+            .into_iter()
+            .map(|(path, _, prompt)| (path, prompt))
+            .collect();
+    let new_paths: HashSet<String> = new_files.keys().cloned().collect();
+    let mut new_hashes = BTreeMap::new();
+    new_project.collect_content_hashes(&mut new_hashes);
+
+    // 只在比對與替換目錄樹時持有寫入鎖；舊的檔案清單在取得鎖之後才讀取，不會漏掉掃描期間的變動
+    let mut project = state.project.write().await;
+    let mut old_files = Vec::new();
+    project.collect_all_files_with_metadata(&mut old_files);
+    let old_paths: HashSet<String> = old_files
+        .into_iter()
+        .filter(|file| file.change_status != Some(ChangeStatus::Deleted))
+        .map(|file| file.path)
+        .collect();
+
+    let added: Vec<String> = new_paths.difference(&old_paths).cloned().collect();
+    let removed_files = old_paths.difference(&new_paths).count();
+
+    let changed: Vec<String> = {
+        let mut progress = state.progress.write().await;
+        progress.summaries.retain(|path, _| new_paths.contains(path));
+        progress.failed_files.retain(|path| new_paths.contains(path));
+        progress.skipped_large_files.retain(|path| new_paths.contains(path));
+        progress.budget_exceeded_files.retain(|path| new_paths.contains(path));
+        progress.encoding_lossy_files.retain(|path| new_paths.contains(path));
+
+        // 內容雜湊與上次摘要時不同的檔案需要重新生成摘要
+        let changed: Vec<String> = new_paths
+            .intersection(&old_paths)
+            .filter(|path| {
+                matches!(
+                    (progress.content_hashes.get(*path), new_hashes.get(*path)),
+                    (Some(old), Some(new)) if old != new
+                )
+            })
+            .cloned()
+            .collect();
+        for path in &changed {
+            progress.summaries.remove(path);
+        }
+        progress.content_hashes.retain(|path, _| new_paths.contains(path));
+        progress.line_counts.retain(|path, _| new_paths.contains(path));
+        progress.latency_ms.retain(|path, _| new_paths.contains(path));
+        progress.project_stats = None;
+
+        // 將既有摘要套用到新的目錄結構
+        for (path, summary) in &progress.summaries {
+            new_project.update_file_summary(path, summary.clone());
+        }
+
+        progress.total_files = new_paths.len();
+        progress.completed_files = progress.summaries.len();
+        changed
+    };
+
+    if let Some(baseline) = &state.baseline {
+        new_project.apply_baseline(root, baseline);
+    }
+    *project = new_project;
+    drop(project);
+
+    let files: Vec<(String, Option<String>)> = added
+        .iter()
+        .chain(&changed)
+        .map(|file_path| (file_path.clone(), new_files.get(file_path).cloned().flatten()))
+        .collect();
+    state.shutdown.tasks.spawn({
+        let state = state.clone();
+        async move { run_summary_pipeline(&state, files).await }
+    });
+
+    tracing::info!(
+        "重新掃描完成：新增 {} 個檔案，變動 {} 個檔案，移除 {} 個檔案",
+        added.len(),
+        changed.len(),
+        removed_files
+    );
+
+    Ok(RescanResult {
+        new_files: added.len(),
+        changed_files: changed.len(),
+        removed_files,
+    })
+}
+
+// 重新為單一檔案生成摘要，只在寫入結果時持有鎖
+// stream 不為 None 時以串流請求生成，回覆的片段依序送到 stream（WebSocket 的 summary_chunk）
+#[tracing::instrument(skip(state))]
+async fn regenerate_summary(
+    state: AppState,
+    file_path: String,
+    stream: Option<mpsc::Sender<String>>,
+) -> Result<String, QprError> {
+    resolve_project_path(&state.config.project_path, &file_path)?;
+    let bytes = read_file_blocking(&file_path).await?;
+    let content_hash = cache::content_hash(&bytes);
+    let (file_content, encoding_lossy) = decode_utf8_lossy(bytes);
+    if encoding_lossy {
+        tracing::warn!("檔案不是有效的 UTF-8，以替換字元讀取：{}", file_path);
+    }
+    let prompt = state.config.file_summary_template(
+        &Language::from_path(Path::new(&file_path)),
+        find_prompt_override(Path::new(&state.config.project_path), Path::new(&file_path)),
+    );
+
+    let (summary, latency_ms) = {
+        let _permit = state.semaphore.acquire().await.expect("semaphore 已關閉");
+        let extension = file_extension(Path::new(&file_path));
+        state.timeline.record(TimelineEventKind::SummarizationStarted { path: file_path.clone() });
+        let started = Instant::now();
+        let summarize = async {
+            match stream {
+                Some(tx) => {
+                    state
+                        .backend
+                        .summarize_file_streaming(&file_content, &extension, prompt.as_deref(), tx)
+                        .await
+                }
+                None => state.backend.summarize_file(&file_content, &extension, prompt.as_deref()).await,
+            }
+        };
+        let summarize = debug_log::scope(state.debug_log.clone(), file_path.clone(), summarize);
+        let result = timeline::scope(state.timeline.clone(), file_path.clone(), summarize).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        state.timeline.record(TimelineEventKind::SummarizationCompleted {
+            path: file_path.clone(),
+            duration_ms: latency_ms,
+        });
+        (result?, latency_ms)
+    };
+    let (summary, full_summary) = limit_summary_length(summary, state.config.summary_max_length);
+
+    let event = {
+        let mut progress = state.progress.write().await;
+        progress.summaries.insert(file_path.clone(), summary.clone());
+        set_full_summary(&mut progress, &file_path, full_summary);
+        progress.failed_files.retain(|path| path != &file_path);
+        progress.skipped_large_files.retain(|path| path != &file_path);
+        progress.budget_exceeded_files.retain(|path| path != &file_path);
+        if state.scan_options.hash_files {
+            progress.content_hashes.insert(file_path.clone(), content_hash);
+        }
+        progress.line_counts.insert(file_path.clone(), stats::count_lines(&file_content));
+        set_encoding_lossy(&mut progress, &file_path, encoding_lossy);
+        progress.latency_ms.insert(file_path.clone(), latency_ms);
+        progress.project_stats = None;
+        progress.total_tokens_used = state.backend.total_tokens_used();
+        SummaryEvent {
+            file_path: file_path.clone(),
+            summary: summary.clone(),
+            completed: progress.completed_files,
+            total: progress.total_files,
+            total_tokens_used: progress.total_tokens_used,
+        }
+    };
+    {
+        let mut project = state.project.write().await;
+        project.update_file_summary(&file_path, summary.clone());
+        project.set_encoding_lossy(&file_path, encoding_lossy);
+    }
+    write_summary_sidecar(&state.config, &file_path, &summary).await;
+    let _ = state.events.send(ServerEvent::Summary(event));
+    refresh_similarity_index(&state).await;
+
+    tracing::info!("已重新生成摘要：{}", file_path);
+    Ok(summary)
+}
+
+// 取摘要的第一句，用於縮短總覽的輸入
+fn first_sentence(summary: &str) -> &str {
+    let summary = summary.trim();
+    let end = summary
+        .char_indices()
+        .find(|&(i, c)| matches!(c, '。' | '！' | '？' | '\n') || (c == '.' && summary[i + 1..].starts_with(' ')))
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(summary.len());
+    &summary[..end]
+}
+
+// 將各檔案摘要組成總覽的輸入，並依 token 上限切成多個片段
+fn build_rollup_chunks(summaries: &[(String, String)], max_tokens: usize, model: &str) -> Vec<String> {
+    let full: Vec<String> = summaries
+        .iter()
+        .map(|(path, summary)| format!("- {}：{}", path, summary.trim()))
+        .collect();
+    let entries = if llm::count_tokens(&full.join("\n"), model) > max_tokens {
+        summaries
+            .iter()
+            .map(|(path, summary)| format!("- {}：{}", path, first_sentence(summary)))
+            .collect()
+    } else {
+        full
+    };
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for entry in entries {
+        if !current.is_empty() && llm::count_tokens(&current, model) + llm::count_tokens(&entry, model) > max_tokens {
+            chunks.push(std::mem::take(&mut current));
+        }
+        current.push_str(&entry);
+        current.push('\n');
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+// 彙整所有檔案摘要，呼叫 LLM 生成專案架構總覽並存入 Progress
+#[tracing::instrument(skip_all)]
+async fn generate_rollup_summary(state: &AppState) -> Result<String, QprError> {
+    let mut summaries: Vec<(String, String)> = {
+        let progress = state.progress.read().await;
+        progress
+            .summaries
+            .iter()
+            .filter(|(path, _)| !progress.is_placeholder(path))
+            .map(|(path, summary)| (path.clone(), summary.clone()))
+            .collect()
+    };
+    if summaries.is_empty() {
+        return Err(QprError::NoSummaries);
+    }
+    summaries.sort();
+
+    let prompt = PROJECT_ROLLUP_PROMPT.replace("{language}", state.config.language.prompt_name());
+    let rollup = combine_summaries(state, &summaries, &prompt).await?;
+
+    let mut progress = state.progress.write().await;
+    progress.rollup_summary = Some(rollup.clone());
+    progress.total_tokens_used = state.backend.total_tokens_used();
+    Ok(rollup)
+}
+
+// 以提示語（{} 為摘要清單）將多份摘要合併為一段；輸入過大時先分段總結，再合併各段的結果
+async fn combine_summaries(state: &AppState, summaries: &[(String, String)], prompt: &str) -> Result<String, QprError> {
+    let chunks = build_rollup_chunks(summaries, state.config.rollup_max_tokens, &state.config.model_name());
+    let mut partials = Vec::new();
+    for chunk in &chunks {
+        let _permit = state.semaphore.acquire().await.expect("semaphore 已關閉");
+        partials.push(state.backend.complete(&prompt.replace("{}", chunk)).await?);
+    }
+    if partials.len() == 1 {
+        return Ok(partials.remove(0));
+    }
+    let _permit = state.semaphore.acquire().await.expect("semaphore 已關閉");
+    state.backend.complete(&prompt.replace("{}", &partials.join("\n"))).await
+}
+
+// 儲存或刪除檔案的手動註記，並立即寫入快取檔案（保留快取中的其他內容）
+#[tracing::instrument(skip(state))]
+async fn annotate_file(state: &AppState, request: AnnotateRequest) -> Result<Option<Annotation>, QprError> {
+    resolve_project_path(&state.config.project_path, &request.file_path)?;
+    if state.project.read().await.find_file_by_path(&request.file_path).is_none() {
+        return Err(QprError::InvalidRequest(format!("檔案不在目錄樹中：{}", request.file_path)));
+    }
+    let annotation = request.annotation.trim();
+    let annotation = (!annotation.is_empty()).then(|| Annotation {
+        annotation: annotation.to_string(),
+        mode: request.mode,
+    });
+
+    let annotations = {
+        let mut progress = state.progress.write().await;
+        match &annotation {
+            Some(annotation) => progress.annotations.insert(request.file_path.clone(), annotation.clone()),
+            None => progress.annotations.remove(&request.file_path),
+        };
+        progress.annotations.clone()
+    };
+
+    let cache_path = PathBuf::from(&state.config.cache_file);
+    run_blocking(move || {
+        let mut cache = SummaryCache::load(&cache_path);
+        cache.annotations = annotations;
+        cache.save(&cache_path)
+    })
+    .await?;
+    refresh_similarity_index(state).await;
+    Ok(annotation)
+}
+
+// 讀取專案目錄內的檔案（/api/file-content），relative 為相對於專案目錄的路徑；
+// 與 /get-file 相同，跳出專案目錄的路徑回傳 403。先檢查路徑再檢查檔案是否存在，
+// 專案目錄外的路徑不論檔案存在與否都回傳 403，不透露專案外的檔案是否存在
+async fn file_content(state: &AppState, relative: &str, highlight: bool) -> Result<warp::reply::Response, QprError> {
+    let project_path = state.config.project_path.clone();
+    let relative_path = PathBuf::from(relative.trim_matches('/'));
+    let not_found = format!("檔案不存在：{}", relative);
+    let (path, content) = run_blocking(move || {
+        if relative_path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(QprError::PathNotAllowed(relative_path.to_string_lossy().to_string()));
+        }
+        let file_path = Path::new(&project_path).join(&relative_path);
+        let path = match resolve_project_path(&project_path, &file_path.to_string_lossy()) {
+            Err(QprError::IoError(e)) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(QprError::NotFound(not_found));
+            }
+            result => result?,
+        };
+        if !path.is_file() {
+            return Err(QprError::NotFound(not_found));
+        }
+        let content = fs::read_to_string(&path)?;
+        Ok((path, content))
+    })
+    .await?;
+    if highlight {
+        return Ok(warp::reply::html(highlighted_file_page(relative, &content)).into_response());
+    }
+    Ok(warp::reply::with_header(content, "Content-Type", Language::from_path(&path).content_type()).into_response())
+}
+
+// 單獨檢視檔案用的 HTML 頁面，以 Prism.js 上色
+fn highlighted_file_page(relative: &str, content: &str) -> String {
+    let language = match export::fence_language(relative) {
+        "" => "none",
+        language => language,
+    };
+    format!(
+        concat!(
+            "<!DOCTYPE html>\n<html lang=\"zh-TW\">\n<head>\n<meta charset=\"UTF-8\">\n<title>{title}</title>\n",
+            "<link rel=\"stylesheet\" href=\"https://cdn.jsdelivr.net/npm/prismjs@1.28.0/themes/prism-okaidia.min.css\">\n",
+            "</head>\n<body>\n<pre><code class=\"language-{language}\">{content}</code></pre>\n",
+            "<script src=\"https://cdn.jsdelivr.net/npm/prismjs@1.28.0/prism.min.js\"></script>\n",
+            "<script src=\"https://cdn.jsdelivr.net/npm/prismjs@1.28.0/plugins/autoloader/prism-autoloader.min.js\"></script>\n",
+            "</body>\n</html>\n",
+        ),
+        title = export::escape_html(relative),
+        language = language,
+        content = export::escape_html(content),
+    )
+}
+
+// 檔案最近一次摘要的 LLM 原始回應（/api/raw-llm-response），relative 為相對於專案目錄的路徑；
+// 未以 --debug-llm 啟動時回傳 404
+fn raw_llm_response(state: &AppState, relative: &str) -> Result<serde_json::Value, QprError> {
+    let Some(debug_log) = &state.debug_log else {
+        return Err(QprError::NotFound("未啟用 LLM 原始回應紀錄，請以 --debug-llm 啟動".to_string()));
+    };
+    let file_path = Path::new(&state.config.project_path)
+        .join(relative.trim_matches('/'))
+        .to_string_lossy()
+        .to_string();
+    let raw_response = DebugLog::raw_response(debug_log, &file_path)
+        .ok_or_else(|| QprError::NotFound(format!("沒有此檔案的 LLM 原始回應：{}", relative)))?;
+    Ok(serde_json::json!({ "path": relative, "raw_response": raw_response }))
+}
+
+// 單一檔案的摘要、註記與程式碼統計（/api/file-summary），relative 為相對於專案目錄的路徑
+async fn file_summary(state: &AppState, relative: &str) -> Result<FileSummary, QprError> {
+    let file_path = Path::new(&state.config.project_path)
+        .join(relative.trim_matches('/'))
+        .to_string_lossy()
+        .to_string();
+    if state.project.read().await.find_file_by_path(&file_path).is_none() {
+        return Err(QprError::NotFound(format!("檔案不在目錄樹中：{}", relative)));
+    }
+    let (path, metadata, content) = run_blocking({
+        let project_path = state.config.project_path.clone();
+        let file_path = file_path.clone();
+        move || -> Result<_, QprError> {
+            let path = resolve_project_path(&project_path, &file_path)?;
+            let metadata = fs::metadata(&path)?;
+            let content = fs::read(&path)?;
+            Ok((path, metadata, content))
+        }
+    })
+    .await?;
+    let last_modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| time::OffsetDateTime::from(modified).format(&Rfc3339).ok());
+
+    let progress = state.progress.read().await;
+    Ok(FileSummary {
+        summary: progress.summaries.get(&file_path).cloned(),
+        summary_full: progress
+            .full_summaries
+            .get(&file_path)
+            .or_else(|| progress.summaries.get(&file_path))
+            .cloned(),
+        annotation: progress
+            .annotations
+            .get(&file_path)
+            .map(|annotation| annotation.annotation.clone()),
+        size_bytes: metadata.len(),
+        // 摘要時已計算過行數則沿用，否則以目前內容計算
+        line_count: progress
+            .line_counts
+            .get(&file_path)
+            .copied()
+            .unwrap_or_else(|| stats::count_lines(&String::from_utf8_lossy(&content))),
+        extension: file_extension(&path),
+        content_hash: cache::content_hash(&content),
+        last_modified,
+        latency_ms: progress.latency_ms.get(&file_path).copied(),
+        path: file_path,
+    })
+}
+
+// 組出摘要檔案時會送出的提示語但不呼叫 LLM（/api/prompt-preview），含目錄的 .qpr_prompt
+async fn prompt_preview(state: &AppState, params: &PromptPreviewParams) -> Result<PromptPreview, QprError> {
+    let file_path = Path::new(&state.config.project_path)
+        .join(&params.file_path)
+        .to_string_lossy()
+        .to_string();
+    if state.project.read().await.find_file_by_path(&file_path).is_none() {
+        return Err(QprError::NotFound(format!("檔案不在目錄樹中：{}", params.file_path)));
+    }
+    let (bytes, prompt_override) = run_blocking({
+        let project_path = state.config.project_path.clone();
+        let file_path = file_path.clone();
+        move || -> Result<_, QprError> {
+            let path = resolve_project_path(&project_path, &file_path)?;
+            let bytes = fs::read(path)?;
+            Ok((bytes, find_prompt_override(Path::new(&project_path), Path::new(&file_path))))
+        }
+    })
+    .await?;
+    let (content, _) = decode_utf8_lossy(bytes);
+
+    let extension = file_extension(Path::new(&file_path));
+    let mut prompts = llm::build_summary_prompts(&content, &extension, prompt_override.as_deref(), &state.config);
+    let chunk_count = prompts.len();
+    if params.chunk_index >= chunk_count {
+        return Err(QprError::InvalidRequest(format!(
+            "chunk_index {} 超出範圍（共 {} 段）",
+            params.chunk_index, chunk_count
+        )));
+    }
+    let prompt = prompts.swap_remove(params.chunk_index);
+    Ok(PromptPreview {
+        estimated_tokens: llm::count_tokens(&prompt, &state.config.model_name()),
+        prompt,
+        would_chunk: chunk_count > 1,
+        chunk_count,
+    })
+}
+
+// 在目錄樹的所有檔案內容中尋找 name 出現的行（/api/cross-references），每次重新從磁碟讀取
+async fn cross_references(state: &AppState, params: &CrossReferenceParams) -> Result<search::CrossReferences, QprError> {
+    if params.name.is_empty() {
+        return Err(QprError::InvalidRequest("name 不可為空".to_string()));
+    }
+    let mut files = Vec::new();
+    state.project.read().await.collect_all_files_with_metadata(&mut files);
+
+    let name = params.name.clone();
+    let case_sensitive = params.case_sensitive.unwrap_or(true);
+    let project_path = state.config.project_path.clone();
+    let references = run_blocking(move || {
+        let mut references = search::CrossReferences::default();
+        for file in files.iter().filter(|file| file.change_status != Some(ChangeStatus::Deleted)) {
+            // 不在專案目錄內（例如指向外部的符號連結）或讀取失敗（例如已被刪除）的檔案略過
+            let Ok(bytes) = resolve_project_path(&project_path, &file.path).and_then(|path| Ok(fs::read(path)?)) else {
+                continue;
+            };
+            let (content, _) = decode_utf8_lossy(bytes);
+            if !references.scan(&file.path, &content, &name, case_sensitive) {
+                break;
+            }
+        }
+        references
+    })
+    .await;
+    Ok(references)
+}
+
+// 摘要與 path 最相似的檔案（/api/similar）
+async fn similar_files(state: &AppState, params: &SimilarParams) -> Result<Vec<similarity::SimilarFile>, QprError> {
+    let file_path = Path::new(&state.config.project_path)
+        .join(&params.path)
+        .to_string_lossy()
+        .to_string();
+    let similarity = state.similarity.read().await;
+    let index = similarity
+        .as_ref()
+        .ok_or_else(|| QprError::PhaseConflict("摘要尚未完成，無法比較相似度".to_string()))?;
+    index
+        .similar(&file_path, params.top_k.unwrap_or(similarity::DEFAULT_TOP_K))
+        .ok_or_else(|| QprError::NotFound(format!("檔案沒有摘要：{}", params.path)))
+}
+
+// 解析專案檔案的匯入語句，建立檔案相依圖（/api/dependency-graph）
+async fn dependency_graph(state: &AppState) -> dependencies::DependencyGraph {
+    let mut files = Vec::new();
+    state.project.read().await.collect_all_files_with_metadata(&mut files);
+
+    let project_path = state.config.project_path.clone();
+    run_blocking(move || {
+        let mut imports = HashMap::new();
+        for file in files.iter().filter(|file| file.change_status != Some(ChangeStatus::Deleted)) {
+            // 不在專案目錄內（例如指向外部的符號連結）或讀取失敗（例如已被刪除）的檔案略過
+            let Ok(bytes) = resolve_project_path(&project_path, &file.path).and_then(|path| Ok(fs::read(path)?)) else {
+                continue;
+            };
+            let (content, _) = decode_utf8_lossy(bytes);
+            let language = Language::from_path(Path::new(&file.path));
+            imports.insert(file.path.clone(), dependencies::extract_imports(&content, &language));
+        }
+        dependencies::build_graph(Path::new(&project_path), &imports)
+    })
+    .await
+}
+
+// 摘要品質不佳的檔案（/api/quality-report），依嚴重程度由高到低、再依路徑排序
+async fn quality_report(state: &AppState) -> Vec<FileMetadata> {
+    let mut files = Vec::new();
+    state.project.read().await.collect_all_files_with_metadata(&mut files);
+    files.retain(|file| !file.quality.is_ok());
+    files.sort_by(|a, b| b.quality.cmp(&a.quality).then_with(|| a.path.cmp(&b.path)));
+    files
+}
+
+// 列出目錄的直接子項目（/api/browse），relative 為相對於專案目錄的路徑，空字串為專案根目錄
+async fn browse_directory(state: &AppState, relative: &str) -> Result<BrowseResponse, QprError> {
+    let relative = relative.trim_matches('/');
+    let dir_path = if relative.is_empty() || relative == "." {
+        state.config.project_path.clone()
+    } else {
+        Path::new(&state.config.project_path).join(relative).to_string_lossy().to_string()
+    };
+    // 與 /get-file 相同的檢查，拒絕跳出專案目錄的路徑
+    resolve_project_path(&state.config.project_path, &dir_path)?;
+
+    let mut response = {
+        let project = state.project.read().await;
+        let dir = project
+            .find_dir_by_path(&dir_path)
+            .ok_or_else(|| QprError::InvalidRequest(format!("目錄不在目錄樹中：{}", relative)))?;
+        BrowseResponse {
+            name: dir.name.clone(),
+            path: dir.path.clone(),
+            dirs: dir
+                .subdirs
+                .iter()
+                .map(|subdir| BrowseEntry {
+                    name: subdir.name.clone(),
+                    path: subdir.path.clone(),
+                    size_bytes: subdir.total_size_bytes(),
+                    summary: None,
+                    change_status: None,
+                })
+                .collect(),
+            files: dir
+                .files
+                .iter()
+                .map(|file| BrowseEntry {
+                    name: file.name.clone(),
+                    path: Path::new(&dir.path).join(&file.name).to_string_lossy().to_string(),
+                    size_bytes: file.size_bytes,
+                    summary: file.summary.clone(),
+                    change_status: file.change_status,
+                })
+                .collect(),
+        }
+    };
+
+    let progress = state.progress.read().await;
+    for entry in &mut response.dirs {
+        entry.summary = progress.directory_summaries.get(&entry.path).cloned();
+    }
+    Ok(response)
+}
+
+// 彙整目錄（含子目錄）內的檔案摘要，生成該目錄的整體摘要；已生成過時直接回傳快取
+#[tracing::instrument(skip(state))]
+async fn generate_directory_summary(state: &AppState, relative: &str) -> Result<String, QprError> {
+    let relative = relative.trim_matches('/');
+    let relative = if relative == "." { "" } else { relative };
+    if Path::new(relative)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return Err(QprError::PathNotAllowed(relative.to_string()));
+    }
+    // 與目錄樹中 Directory.path 相同的寫法，前端可直接以節點路徑查詢
+    let dir_path = if relative.is_empty() {
+        state.config.project_path.clone()
+    } else {
+        Path::new(&state.config.project_path).join(relative).to_string_lossy().to_string()
+    };
+    if state.project.read().await.find_dir_by_path(&dir_path).is_none() {
+        return Err(QprError::InvalidRequest(format!("目錄不在目錄樹中：{}", relative)));
+    }
+
+    let mut summaries: Vec<(String, String)> = {
+        let progress = state.progress.read().await;
+        if let Some(summary) = progress.directory_summaries.get(&dir_path) {
+            return Ok(summary.clone());
+        }
+        progress
+            .summaries
+            .iter()
+            .filter(|(path, _)| Path::new(path).starts_with(&dir_path))
+            .filter(|(path, _)| !progress.is_placeholder(path))
+            .map(|(path, summary)| (path.clone(), summary.clone()))
+            .collect()
+    };
+    if summaries.is_empty() {
+        return Err(QprError::NoSummaries);
+    }
+    summaries.sort();
+
+    let prompt = DIRECTORY_SUMMARY_PROMPT
+        .replace("{directory}", if relative.is_empty() { "." } else { relative })
+        .replace("{language}", state.config.language.prompt_name());
+    let summary = combine_summaries(state, &summaries, &prompt).await?;
+
+    let mut progress = state.progress.write().await;
+    progress.directory_summaries.insert(dir_path, summary.clone());
+    progress.total_tokens_used = state.backend.total_tokens_used();
+    Ok(summary)
+}
+
+// 掃描專案目錄並建立該專案的共享狀態
+fn load_project(
+    config: QprConfig,
+    scan_options: Arc<ScanOptions>,
+    backend: Arc<dyn LlmBackend>,
+    semaphore: Arc<Semaphore>,
+    metrics: Arc<Metrics>,
+    baseline: Option<Arc<BaselineHashes>>,
+    shutdown: Shutdown,
+) -> AppState {
+    let path = Path::new(&config.project_path);
+    let project = Directory::from_path(path, path, false, &scan_options); // 初次僅收集目錄
+
+    // 另外掃描一次檔案（不計算雜湊）以顯示專案規模
+    let counting_options = ScanOptions {
+        extensions: scan_options.extensions.clone(),
+        hash_files: false,
+        max_depth: scan_options.max_depth,
+        exclude_tests: scan_options.exclude_tests,
+        sort_by: scan_options.sort_by,
+    };
+    let full_tree = Directory::from_path(path, path, true, &counting_options);
+    tracing::info!(
+        "Found {} files across {} directories, max depth {}.",
+        full_tree.file_count(),
+        full_tree.dir_count(),
+        full_tree.max_depth()
+    );
+    let truncated = full_tree.max_depth_reached_count();
+    if truncated > 0 {
+        tracing::warn!(
+            "{} 個目錄超過最大深度 {}，其子目錄未被掃描（可調整 --max-depth）",
+            truncated,
+            scan_options.max_depth
+        );
+    }
+
+    // 1. 初始收集資料夾
+    tracing::debug!("收集的資料夾：\n{}", project);
+
+    let cache = SummaryCache::load(Path::new(&config.cache_file));
+
+    // 定義進度狀態（檔案總數在選定資料夾後才確定）
+    let progress = Arc::new(RwLock::new(Progress {
+        total_files: 0,
+        completed_files: 0,
+        summaries: BTreeMap::new(),
+        failed_files: Vec::new(),
+        skipped_large_files: Vec::new(),
+        budget_exceeded_files: Vec::new(),
+        encoding_lossy_files: Vec::new(),
+        rollup_summary: None,
+        directory_summaries: BTreeMap::new(),
+        annotations: cache.annotations.clone(),
+        total_tokens_used: 0,
+        content_hashes: BTreeMap::new(),
+        change_stats: ChangeStats::default(),
+        line_counts: HashMap::new(),
+        latency_ms: BTreeMap::new(),
+        project_stats: None,
+        full_summaries: BTreeMap::new(),
+    }));
+    let debug_log = config.debug_llm.then(Arc::default);
+
+    AppState {
+        project: Arc::new(RwLock::new(project)),
+        progress,
+        folder_selection: Arc::new(RwLock::new(FolderSelection::default())),
+        phase: Arc::new(RwLock::new(AnalysisPhase::GatheringFolders)),
+        scan_options,
+        semaphore,
+        config: Arc::new(config),
+        backend,
+        cache: Arc::new(RwLock::new(cache)),
+        events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+        metrics,
+        baseline,
+        shutdown,
+        similarity: Arc::default(),
+        timeline: Timeline::spawn(),
+        progress_bar: None,
+        debug_log,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("錯誤：{}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run() -> Result<(), QprError> {
+    let cli = Cli::parse();
+    let _log_guard = logging::init(cli.verbose, cli.log_file.as_deref())?;
+
+    // `qpr init`：產生預設設定檔後結束
+    if let Some(Command::Init { force }) = &cli.command {
+        let config_path = Path::new(&cli.config);
+        if config_path.exists() && !force {
+            return Err(QprError::ConfigError(format!(
+                "設定檔 {} 已存在，使用 --force 覆寫",
+                config_path.display()
+            )));
+        }
+        fs::write(config_path, config::default_config_template())?;
+        println!("已產生設定檔：{}", config_path.display());
+        return Ok(());
+    }
+
+    // 讀取 qpr.toml，再以命令列參數覆寫
+    let mut config = QprConfig::load(Path::new(&cli.config))?;
+    config.apply_cli(&cli);
+    if let Some(path) = &cli.extra_context {
+        config.project_context = config::read_project_context(Path::new(path))?;
+    }
+    config.validate()?;
+    config.warn_unknown_models();
+
+    // 指定 HTTPS 憑證時先檢查檔案，避免分析完才發現無法啟動伺服器
+    let tls_files = tls::TlsFiles::from_paths(cli.tls_cert.as_deref(), cli.tls_key.as_deref())?;
+
+    // 讀取 .env 等檔案中的 API 金鑰與 GIT_TOKEN；多個專案共用同一個後端，只讀取第一個專案目錄的 .env
+    let project_paths = config.project_paths();
+    config::load_env_chain(Path::new(&project_paths[0]), cli.env_file.as_deref().map(Path::new))?;
+
+    // 指定 --git-url 時先複製到暫存目錄，結束時（checkout 被 drop）自動刪除
+    let _git_checkout = match &cli.git_url {
+        Some(url) => {
+            let token = cli.git_token.clone().or_else(|| env::var("GIT_TOKEN").ok());
+            let source = git::GitSource::new(url, token)?;
+            tracing::info!("正在複製 Git 儲存庫：{}", url);
+            let checkout = source.clone_to_temp().await?;
+            config.project_path = checkout.path().to_string_lossy().to_string();
+            config.projects.clear();
+            tracing::info!("已複製到暫存目錄：{}", config.project_path);
+            Some(checkout)
+        }
+        None => None,
+    };
+
+    // --dry-run 時改用不呼叫 API 的後端
+    let dry_run = cli.dry_run.then(|| Arc::new(llm::DryRunBackend::new(&config)));
+    let metrics = Arc::new(Metrics::default());
+    // --backend mock：保留具體型別，結束時輸出呼叫紀錄
+    let mock = (!cli.dry_run && config.backend == BackendKind::Mock)
+        .then(|| Arc::new(llm::MockLlmBackend::new(&config, metrics.clone())));
+    let backend: Arc<dyn LlmBackend> = match (&dry_run, &mock) {
+        (Some(dry_run), _) => dry_run.clone(),
+        (None, Some(mock)) => mock.clone(),
+        (None, None) => llm::create_backend(&config, metrics.clone())?,
+    };
+    tracing::info!("使用 LLM 後端：{}", backend.name());
+
+    // 先確認後端可連線，避免掃描完目錄後每個檔案的摘要都失敗；--dry-run 不會呼叫 API，不需檢查
+    if dry_run.is_none() && !cli.skip_health_check {
+        match llm::check_health(backend.as_ref()).await {
+            llm::HealthReport::Ok { latency_ms, .. } => {
+                tracing::info!("LLM 後端連線正常（{} ms）", latency_ms);
+            }
+            llm::HealthReport::Error { message, .. } => {
+                return Err(QprError::ConfigError(format!(
+                    "無法連線至 LLM 後端 {}：{}（可使用 --skip-health-check 略過檢查）",
+                    backend.name(),
+                    message
+                )));
+            }
+        }
+    }
+
+    // --baseline：讀取上次匯出的 JSON 報告，比對時需要計算目前檔案的雜湊
+    let baseline = match &cli.baseline {
+        Some(path) => Some(Arc::new(baseline::load_baseline(Path::new(path))?)),
+        None => None,
+    };
+
+    // 載入所有專案；各專案有自己的目錄樹與進度，但共用 LLM 後端與並行數上限
+    let scan_options = Arc::new(ScanOptions {
+        extensions: ExtensionSet::new(CODE_FILE_EXTENSIONS, &config.extensions)?,
+        hash_files: config.hash_files || baseline.is_some(),
+        max_depth: config.max_depth,
+        exclude_tests: config.exclude_tests,
+        sort_by: config.sort_by,
+    });
+    // 並行數上限由後端決定（見 concurrency_per_backend）
+    let semaphore = backend.semaphore();
+    // Ctrl+C / SIGTERM：停止開始新的摘要，等待進行中的請求後保存進度
+    let shutdown = Shutdown::default();
+    shutdown.listen();
+    let project_paths = config.project_paths();
+    let multiple_projects = project_paths.len() > 1;
+    if multiple_projects && baseline.is_some() {
+        return Err(QprError::ConfigError("--baseline 一次只能比對一個專案".to_string()));
+    }
+    // 所有專案的進度條畫在同一個 MultiProgress 上
+    let progress_bars = (!cli.no_progress_bar && std::io::stderr().is_terminal()).then(SummaryProgress::multi);
+    let mut projects = Vec::new();
+    for (id, project_path) in registry::project_ids(&project_paths).into_iter().zip(project_paths) {
+        let mut project_config = config.clone();
+        project_config.project_path = project_path;
+        if multiple_projects {
+            project_config.cache_file = registry::project_cache_file(&config.cache_file, &id);
+            // 各專案的摘要檔案放在輸出目錄下以專案 ID 命名的子目錄，避免相同的相對路徑互相覆寫
+            if let Some(output_dir) = &config.output_dir {
+                project_config.output_dir = Some(Path::new(output_dir).join(&id).to_string_lossy().to_string());
+            }
+            tracing::info!("專案 {}：{}", id, project_config.project_path);
+        }
+        let progress_bar = progress_bars
+            .as_ref()
+            .map(|multi| SummaryProgress::new(multi, &project_config.project_path));
+        let mut state = load_project(
+            project_config,
+            scan_options.clone(),
+            backend.clone(),
+            semaphore.clone(),
+            metrics.clone(),
+            baseline.clone(),
+            shutdown.clone(),
+        );
+        state.progress_bar = progress_bar;
+        projects.push(ProjectEntry { id, state });
+    }
+    let registry = ProjectRegistry { projects };
+    let state = registry.first().state.clone();
+
+    // --no-server，或標準輸出不是終端機（例如 CI）且未指定 --server 時，不啟動網頁伺服器
+    let no_server = cli.no_server || (!cli.server && !io::stdout().is_terminal());
+    if no_server && !cli.no_server {
+        tracing::info!("標準輸出不是終端機，不啟動網頁伺服器（可使用 --server 強制啟動）");
+    }
+
+    // --dry-run、--export-markdown、--export-format 與 --no-server 只在命令列中完成，不啟動網頁伺服器
+    if dry_run.is_some() || cli.export_markdown.is_some() || cli.export_format.is_some() || no_server {
+        if multiple_projects {
+            return Err(QprError::ConfigError(
+                "--dry-run、--export-markdown、--export-format 與 --no-server 一次只能處理一個專案（標準輸出不是終端機時可使用 --server 啟動網頁伺服器）".to_string(),
+            ));
+        }
+        // 2. 呼叫 GPT 進行資料夾過濾，再以命令列互動補充；--skip-folders-analysis 時直接選定
+        if state.config.skip_folders_analysis {
+            select_folders_without_analysis(&state).await;
+        } else {
+            refine_folders(&state, &[]).await?;
+            select_folders_interactively(&state).await;
+        }
+
+        // 3. 為選定的資料夾收集檔案並生成摘要
+        let files_to_summarize = begin_summarization(&state).await?;
+        run_summary_pipeline(&state, files_to_summarize).await;
+        if shutdown.is_cancelled() {
+            if dry_run.is_none() {
+                save_progress_on_shutdown(&registry, &shutdown).await;
+            }
+            return Ok(());
+        }
+
+        // --dry-run：輸出估算結果後結束，不寫入快取
+        if let Some(dry_run) = &dry_run {
+            let tokens = dry_run.total_tokens_used();
+            let cost = match state.config.price_per_1k_tokens() {
+                Some(price) => tokens as f64 / 1000.0 * price,
+                None => {
+                    tracing::warn!("pricing 表中沒有模型 {} 的價格，費用以 0 計算", state.config.model_name());
+                    0.0
+                }
+            };
+            println!(
+                "Would send {} API requests, estimated {} tokens, estimated cost ${:.2}",
+                dry_run.request_count(),
+                tokens,
+                cost
+            );
+            return Ok(());
+        }
+
+        finish_summarization(&state).await;
+        if let Some(mock) = &mock {
+            tracing::info!("mock 後端呼叫：{}", mock.call_summary());
+        }
+
+        if let Some(export_path) = &cli.export_markdown {
+            let summaries = state.progress.read().await.annotated_summaries();
+            let markdown = state
+                .project
+                .read()
+                .await
+                .with_summaries(&summaries)
+                .to_markdown(&state.config.project_path, 0);
+            fs::write(export_path, markdown)?;
+            tracing::info!("已匯出 Markdown 報告：{}", export_path);
+        }
+
+        // --export-format：只在標準輸出印出報告路徑，方便腳本取用
+        if let Some(format) = cli.export_format {
+            let export_path = cli
+                .export_output
+                .clone()
+                .unwrap_or_else(|| format.default_filename().to_string());
+            let report = {
+                let project = state.project.read().await;
+                let progress = state.progress.read().await;
+                export::render_report(format, &progress, &project, &state.config.project_path)?
+            };
+            fs::write(&export_path, report)?;
+            println!("{}", export_path);
+        }
+        return Ok(());
+    }
+
+    // 2. 先啟動網頁伺服器，之後的資料夾選擇可在網頁或命令列進行
+    let server_addr = SocketAddr::new(state.config.bind_addr, state.config.server_port);
+    let bind_error = |e: warp::Error| QprError::ConfigError(format!("無法在 {} 啟動網頁伺服器：{}", server_addr, e));
+    // 綁定所有介面時，網路上的任何人都能讀取專案檔案內容
+    if server_addr.ip().is_unspecified() && state.config.api_token.is_none() {
+        tracing::warn!("網頁伺服器綁定於 {} 且未設定 --api-token，專案檔案內容將對網路公開", server_addr.ip());
+    }
+    // 收到結束訊號時正常結束，讓暫存目錄等資源得以清除
+    let server_shutdown = shutdown.token.clone().cancelled_owned();
+    let server = match &tls_files {
+        Some(tls_files) => {
+            let (_, server) = warp::serve(build_routes(&registry))
+                .tls()
+                .cert_path(&tls_files.cert_path)
+                .key_path(&tls_files.key_path)
+                .try_bind_with_graceful_shutdown(server_addr, server_shutdown)
+                .map_err(bind_error)?;
+            tracing::info!("啟動網頁伺服器，請訪問 https://{}", server_addr);
+            tokio::spawn(server)
+        }
+        None => {
+            let (_, server) = warp::serve(build_routes(&registry))
+                .try_bind_with_graceful_shutdown(server_addr, server_shutdown)
+                .map_err(bind_error)?;
+            tracing::info!("啟動網頁伺服器，請訪問 http://{}", server_addr);
+            tokio::spawn(server)
+        }
+    };
+
+    // 3. 各專案同時進行初次資料夾過濾，失敗時仍可從網頁重新分析；--skip-folders-analysis 時直接開始摘要
+    let initial_analysis = futures::future::join_all(registry.projects.iter().map(|entry| async move {
+        if entry.state.config.skip_folders_analysis {
+            select_folders_without_analysis(&entry.state).await;
+            if let Err(e) = spawn_summarization(entry.state.clone()).await {
+                tracing::error!("無法開始生成摘要（{}）：{}", entry.id, e);
+            }
+        } else if let Err(e) = refine_folders(&entry.state, &[]).await {
+            tracing::error!("資料夾分析失敗（{}）：{}", entry.id, e);
+        }
+    }));
+    tokio::select! {
+        _ = initial_analysis => {}
+        _ = shutdown.token.cancelled() => {}
+    }
+
+    // 4. 命令列互動式資料夾選擇，輸入 ok 後開始生成摘要；多個專案時只能在網頁上選擇
+    if config.skip_folders_analysis {
+        // 已在上一步開始摘要
+    } else if multiple_projects {
+        tracing::info!("已載入 {} 個專案，請在網頁上確認各專案的資料夾", registry.projects.len());
+    } else {
+        tokio::spawn({
+            let state = state.clone();
+            async move {
+                if select_folders_interactively(&state).await {
+                    if let Err(e) = spawn_summarization(state).await {
+                        tracing::error!("無法開始生成摘要：{}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    if let Err(e) = server.await {
+        tracing::error!("網頁伺服器異常結束：{}", e);
+    }
+    tracing::info!("網頁伺服器已關閉");
+    if let Some(mock) = &mock {
+        tracing::info!("mock 後端呼叫：{}", mock.call_summary());
+    }
+    if shutdown.is_cancelled() {
+        save_progress_on_shutdown(&registry, &shutdown).await;
+    }
+
+    Ok(())
+}
+
+// 建立單一專案的 API 路由（不含驗證），掛載於 /projects/<id>/ 之下
+fn build_project_routes(
+    state: &AppState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static {
+    let project_arc = Arc::clone(&state.project);
+    let progress_arc = Arc::clone(&state.progress);
+
+    // 定義 /filtered-tree 端點
+    let project_clone = Arc::clone(&project_arc);
+
+    let filtered_tree_route = warp::path("filtered-tree")
+        .and(warp::get())
+        .and_then({
+            let project_clone = Arc::clone(&project_clone);
+            move || {
+                let project_clone = Arc::clone(&project_clone);
+                async move {
+                    let project = project_clone.read().await;
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&*project))
+                }
+            }
+        });
+
+    // 定義 /api/directory-tree/depth/<n> 端點，只回傳前 n 層，更深的目錄由 /api/browse 載入
+    let directory_tree_depth_route = warp::path!("api" / "directory-tree" / "depth" / usize)
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move |depth: usize| {
+                let state = state.clone();
+                async move {
+                    let tree = state.project.read().await.prune_to_depth(depth);
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&tree))
+                }
+            }
+        });
+
+    // 定義 /progress 端點
+    let progress_route = warp::path("progress")
+        .and(warp::get())
+        .and_then({
+            let progress_arc = Arc::clone(&progress_arc);
+            move || {
+                let progress_arc = Arc::clone(&progress_arc);
+                async move {
+                    let progress = progress_arc.read().await;
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&*progress))
+                }
+            }
+        });
+        
+        
+
+    // 添加新的路由來處理檔案內容請求
+    // 僅允許讀取專案目錄內的檔案：目錄外回傳 403，讀取失敗回傳 JSON 錯誤
+    // 已由 /api/file-content/<path> 取代，保留給舊的客戶端，回應帶有 Deprecation 標頭
+    let get_file_route = warp::path("get-file")
+        .and(warp::get())
+        .and(warp::query::<FileParams>())
+        .and_then({
+            let state = state.clone();
+            move |params: FileParams| {
+                let state = state.clone();
+                async move {
+                    let project_path = state.config.project_path.clone();
+                    let content = run_blocking(move || {
+                        resolve_project_path(&project_path, &params.path).and_then(|path| Ok(fs::read_to_string(path)?))
+                    })
+                    .await;
+                    let response = match content {
+                        Ok(content) => warp::reply::with_header(
+                            warp::reply::html(content),
+                            "Deprecation",
+                            "true",
+                        )
+                        .into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/rescan 端點，重新掃描專案目錄並更新目錄樹
+    let rescan_route = warp::path!("api" / "rescan")
+        .and(warp::post())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let response = match rescan_project(state).await {
+                        Ok(result) => warp::reply::json(&result).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/folders 端點，回傳目前的分析階段與選定的資料夾
+    let folders_route = warp::path!("api" / "folders")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let phase = *state.phase.read().await;
+                    let selection = state.folder_selection.read().await;
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&serde_json::json!({
+                        "phase": phase,
+                        "filtered_folders": selection.filtered_folders,
+                    })))
+                }
+            }
+        });
+
+    // 定義 /api/folders/filtered 端點，回傳目前選定要分析的資料夾；確認資料夾並開始摘要後 analysis_complete 為 true
+    let filtered_folders_route = warp::path!("api" / "folders" / "filtered")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let analysis_complete = *state.phase.read().await != AnalysisPhase::GatheringFolders;
+                    let selection = state.folder_selection.read().await;
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&serde_json::json!({
+                        "selected_folders": selection.filtered_folders,
+                        "analysis_complete": analysis_complete,
+                    })))
+                }
+            }
+        });
+
+    // 定義 /api/folders/all 端點，回傳目錄樹中所有資料夾的相對路徑（與 /api/folders/tree-text 相同，以陣列表示）
+    let all_folders_route = warp::path!("api" / "folders" / "all")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let folders = state.project.read().await.to_string();
+                    let folders: Vec<&str> = folders.lines().collect();
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&serde_json::json!({ "folders": folders })))
+                }
+            }
+        });
+
+    // 定義 /api/folders/tree-text 端點，回傳送給 LLM 分析的資料夾清單（純文字），用於除錯資料夾的選擇結果
+    let folders_tree_text_route = warp::path!("api" / "folders" / "tree-text")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let folders = state.project.read().await.to_string();
+                    Ok::<_, std::convert::Infallible>(folders)
+                }
+            }
+        });
+
+    // 定義 /api/folders/tree-with-files 端點，同上但另外列出各資料夾中已收集的檔案
+    let folders_tree_with_files_route = warp::path!("api" / "folders" / "tree-with-files")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let folders = DisplayWithFiles(&*state.project.read().await).to_string();
+                    Ok::<_, std::convert::Infallible>(folders)
+                }
+            }
+        });
+
+    // 定義 /api/folders/manual-select 端點，從網頁補充資料夾並重新分析，finalize 時開始生成摘要
+    let manual_select_route = warp::path!("api" / "folders" / "manual-select")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then({
+            let state = state.clone();
+            move |request: ManualSelectRequest| {
+                let state = state.clone();
+                async move {
+                    let response = match manual_select_folders(state, request).await {
+                        Ok(filtered_folders) => {
+                            warp::reply::json(&serde_json::json!({ "filtered_folders": filtered_folders })).into_response()
+                        }
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/file-tree/flat 端點，以一維陣列回傳所有檔案，可依副檔名、摘要與排序方式篩選
+    let flat_tree_route = warp::path!("api" / "file-tree" / "flat")
+        .and(warp::get())
+        .and(warp::query::<FlatTreeParams>())
+        .and_then({
+            let state = state.clone();
+            move |params: FlatTreeParams| {
+                let state = state.clone();
+                async move {
+                    let summaries = state.progress.read().await.summaries.clone();
+                    let project = state.project.read().await;
+                    let response = match flat_file_list(&project, &summaries, &params) {
+                        Ok(files) => warp::reply::json(&files).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/search/by-extension/<ext> 端點，列出某個副檔名的所有檔案，不論位於目錄樹的哪一層
+    let search_by_extension_route = warp::path!("api" / "search" / "by-extension" / String)
+        .and(warp::get())
+        .and(warp::query::<ExtensionSearchParams>())
+        .and_then({
+            let state = state.clone();
+            move |ext: String, params: ExtensionSearchParams| {
+                let state = state.clone();
+                async move {
+                    let ext = percent_encoding::percent_decode_str(&ext).decode_utf8_lossy().to_string();
+                    let summaries = state.progress.read().await.summaries.clone();
+                    let project = state.project.read().await;
+                    let response = match files_by_extension(&project, &summaries, &ext, params) {
+                        Ok(files) => warp::reply::json(&files).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/folders/analyze 端點，以 LLM 重新過濾資料夾並回傳結果
+    let analyze_folders_route = warp::path!("api" / "folders" / "analyze")
+        .and(warp::post())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let response = match refine_folders(&state, &[]).await {
+                        Ok(filtered_folders) => {
+                            warp::reply::json(&serde_json::json!({ "filtered_folders": filtered_folders })).into_response()
+                        }
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/folders/suggest 端點，回傳 LLM 建議的資料夾但不套用，由前端勾選後以 /api/folders/confirm 送出
+    let suggest_folders_route = warp::path!("api" / "folders" / "suggest")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then({
+            let state = state.clone();
+            move |request: SuggestFoldersRequest| {
+                let state = state.clone();
+                async move {
+                    let response = match suggest_folders(&state, request).await {
+                        Ok(suggested) => warp::reply::json(&serde_json::json!({ "suggested": suggested })).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/folders/confirm 端點，直接指定資料夾並開始生成摘要
+    let confirm_folders_route = warp::path!("api" / "folders" / "confirm")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then({
+            let state = state.clone();
+            move |request: ConfirmFoldersRequest| {
+                let state = state.clone();
+                async move {
+                    let response = match confirm_folders(state, request).await {
+                        Ok(folders) => warp::reply::json(&serde_json::json!({ "filtered_folders": folders })).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/status 端點，回傳目前的分析階段
+    let status_route = warp::path!("api" / "status")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let status = current_status(&state).await;
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&status))
+                }
+            }
+        });
+
+    // 定義 /api/events 端點，以 SSE 即時推送摘要進度，並每 10 秒推送一次 progress_estimate
+    let events_route = warp::path!("api" / "events")
+        .and(warp::get())
+        .map({
+            let state = state.clone();
+            move || {
+                let rx = state.events.subscribe();
+                let events = futures::stream::unfold(rx, |mut rx| async move {
+                    loop {
+                        match rx.recv().await {
+                            Ok(ServerEvent::Summary(event)) => {
+                                return Some((warp::sse::Event::default().json_data(&event), rx))
+                            }
+                            Ok(ServerEvent::Delete(event)) => {
+                                return Some((warp::sse::Event::default().event("delete").json_data(&event), rx))
+                            }
+                            // 客戶端處理太慢時略過遺失的事件
+                            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(broadcast::error::RecvError::Closed) => return None,
+                        }
+                    }
+                });
+                // 連線後立即送出第一筆，之後每隔固定時間送出；收到結束訊號後停止，不拖延伺服器關閉
+                let interval = tokio::time::interval(std::time::Duration::from_secs(PROGRESS_ESTIMATE_INTERVAL_SECS));
+                let estimates = futures::stream::unfold((state.clone(), interval), |(state, mut interval)| async move {
+                    interval.tick().await;
+                    if state.shutdown.is_cancelled() {
+                        return None;
+                    }
+                    let estimate = completion_estimate(&state).await;
+                    let event = warp::sse::Event::default().event("progress_estimate").json_data(&estimate);
+                    Some((event, (state, interval)))
+                });
+                warp::sse::reply(warp::sse::keep_alive().stream(futures::stream::select(events, estimates)))
+            }
+        });
+
+    // 定義 /api/ws 端點，以 WebSocket 雙向傳送事件與請求（與 SSE 共用事件來源）
+    let ws_route = warp::path!("api" / "ws")
+        .and(warp::ws())
+        .map({
+            let state = state.clone();
+            move |ws: warp::ws::Ws| {
+                let state = state.clone();
+                ws.on_upgrade(move |socket| ws::handle_socket(state, socket))
+            }
+        });
+
+    // 定義 /api/regenerate 端點，重新生成單一檔案的摘要
+    let regenerate_route = warp::path!("api" / "regenerate")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then({
+            let state = state.clone();
+            move |request: RegenerateRequest| {
+                let state = state.clone();
+                async move {
+                    let response = match regenerate_summary(state, request.file_path, None).await {
+                        Ok(summary) => warp::reply::json(&serde_json::json!({ "summary": summary })).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/batch-summarize 端點，啟動後追加未選定資料夾中的檔案進行摘要
+    let batch_summarize_route = warp::path!("api" / "batch-summarize")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then({
+            let state = state.clone();
+            move |request: BatchSummarizeRequest| {
+                let state = state.clone();
+                async move {
+                    let response = match batch_summarize(state, request).await {
+                        Ok(result) => warp::reply::json(&result).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/annotate 端點，為檔案摘要加上手動註記（replace、prepend 或 append）
+    let annotate_route = warp::path!("api" / "annotate")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then({
+            let state = state.clone();
+            move |request: AnnotateRequest| {
+                let state = state.clone();
+                async move {
+                    let response = match annotate_file(&state, request).await {
+                        Ok(annotation) => warp::reply::json(&serde_json::json!({ "annotation": annotation })).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/search 端點，以關鍵字搜尋檔名與摘要（GET 使用預設選項，POST 可指定選項）
+    let search_get_route = warp::path!("api" / "search")
+        .and(warp::get())
+        .and(warp::query::<SearchParams>())
+        .map(|params: SearchParams| search::SearchQuery::from_keyword(params.q));
+    let search_post_route = warp::path!("api" / "search")
+        .and(warp::post())
+        .and(warp::body::json::<search::SearchQuery>());
+    let search_route = search_get_route
+        .or(search_post_route)
+        .unify()
+        .and_then({
+            let state = state.clone();
+            move |query: search::SearchQuery| {
+                let state = state.clone();
+                async move {
+                    let project = state.project.read().await;
+                    let progress = state.progress.read().await;
+                    let results = search::search(&project, &progress.summaries, &query);
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&results))
+                }
+            }
+        });
+
+    // 定義 /api/cross-references 端點，以純文字比對找出引用某個名稱的檔案與行號
+    let cross_references_route = warp::path!("api" / "cross-references")
+        .and(warp::get())
+        .and(warp::query::<CrossReferenceParams>())
+        .and_then({
+            let state = state.clone();
+            move |params: CrossReferenceParams| {
+                let state = state.clone();
+                async move {
+                    let response = match cross_references(&state, &params).await {
+                        Ok(references) => warp::reply::json(&references).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/health 端點，對 LLM 後端送出最小的請求確認可連線；失敗時回傳 503
+    let health_route = warp::path!("api" / "health")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let report = llm::check_health(state.backend.as_ref()).await;
+                    let status = if report.is_ok() {
+                        warp::http::StatusCode::OK
+                    } else {
+                        warp::http::StatusCode::SERVICE_UNAVAILABLE
+                    };
+                    Ok::<_, std::convert::Infallible>(warp::reply::with_status(warp::reply::json(&report), status))
+                }
+            }
+        });
+
+    // 定義 /api/timeline 端點，回傳摘要事件的時間軸；客戶端以上次最後一筆的 timestamp 作為 since 增量輪詢
+    let timeline_route = warp::path!("api" / "timeline")
+        .and(warp::get())
+        .and(warp::query::<TimelineParams>())
+        .and_then({
+            let state = state.clone();
+            move |params: TimelineParams| {
+                let state = state.clone();
+                async move {
+                    let events = state.timeline.since(params.since).await;
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&serde_json::json!({ "events": events })))
+                }
+            }
+        });
+
+    // 定義 /api/completion-estimate 端點，依最近 20 個完成的檔案估計剩餘時間與預計完成時間
+    let completion_estimate_route = warp::path!("api" / "completion-estimate")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let estimate = completion_estimate(&state).await;
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&estimate))
+                }
+            }
+        });
+
+    // 定義 /api/similar 端點，以 TF-IDF 比較摘要，找出功能相近的檔案
+    let similar_route = warp::path!("api" / "similar")
+        .and(warp::get())
+        .and(warp::query::<SimilarParams>())
+        .and_then({
+            let state = state.clone();
+            move |params: SimilarParams| {
+                let state = state.clone();
+                async move {
+                    let response = match similar_files(&state, &params).await {
+                        Ok(similar) => warp::reply::json(&serde_json::json!({ "similar": similar })).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/dependency-graph 端點，回傳由匯入語句建立的檔案相依圖（nodes 與 edges）
+    let dependency_graph_route = warp::path!("api" / "dependency-graph")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let graph = dependency_graph(&state).await;
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&graph))
+                }
+            }
+        });
+
+    // 定義 /api/quality-report 端點，列出過短、空泛或疑似錯誤訊息的摘要，方便重新生成或手動註記
+    let quality_report_route = warp::path!("api" / "quality-report")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let files = quality_report(&state).await;
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&serde_json::json!({ "files": files })))
+                }
+            }
+        });
+
+    // 定義 /api/stats 端點，回傳與快取比對後的檔案變動統計；摘要完成後另附專案統計
+    let stats_route = warp::path!("api" / "stats")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let project_stats = if *state.phase.read().await == AnalysisPhase::Done {
+                        Some(cached_project_stats(&state).await)
+                    } else {
+                        None
+                    };
+                    let project = state.project.read().await;
+                    let progress = state.progress.read().await;
+                    let stats = Stats {
+                        changes: &progress.change_stats,
+                        skipped_large_files: &progress.skipped_large_files,
+                        budget_exceeded_files: &progress.budget_exceeded_files,
+                        encoding_lossy_files: &progress.encoding_lossy_files,
+                        file_count: project.file_count(),
+                        dir_count: project.dir_count(),
+                        max_depth: project.max_depth(),
+                        slowest_files: progress.slowest_files(),
+                        project: project_stats,
+                    };
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&stats))
+                }
+            }
+        });
 
-    let filtered_tree_route = warp::path("filtered-tree")
+    // 定義 /api/export/markdown 端點，下載 Markdown 格式的完整報告
+    let export_markdown_route = warp::path!("api" / "export" / "markdown")
         .and(warp::get())
         .and_then({
-            let project_clone = Arc::clone(&project_clone);
+            let state = state.clone();
             move || {
-                let project_clone = Arc::clone(&project_clone);
+                let state = state.clone();
                 async move {
-                    let project = project_clone.read().await;
-                    Ok::<_, std::convert::Infallible>(warp::reply::json(&*project))
+                    let summaries = state.progress.read().await.annotated_summaries();
+                    let report = state.project.read().await.with_summaries(&summaries);
+                    let project_path = state.config.project_path.clone();
+                    let markdown = run_blocking(move || report.to_markdown(&project_path, 0)).await;
+                    let response = warp::reply::with_header(
+                        warp::reply::with_header(markdown, "Content-Type", "text/markdown; charset=utf-8"),
+                        "Content-Disposition",
+                        format!("attachment; filename=\"{}\"", export::MARKDOWN_EXPORT_FILENAME),
+                    );
+                    Ok::<_, std::convert::Infallible>(response)
                 }
             }
         });
 
-    // 定義 /progress 端點
-    let progress_route = warp::path("progress")
+    // 定義 /api/export/json 端點，回傳合併所有摘要後的完整目錄樹
+    let export_json_route = warp::path!("api" / "export" / "json")
         .and(warp::get())
         .and_then({
-            let progress_arc = Arc::clone(&progress_arc);
+            let state = state.clone();
             move || {
-                let progress_arc = Arc::clone(&progress_arc);
+                let state = state.clone();
                 async move {
-                    let progress = progress_arc.read().await;
-                    Ok::<_, std::convert::Infallible>(warp::reply::json(&*progress))
+                    let summaries = state.progress.read().await.annotated_summaries();
+                    let tree = state.project.read().await.with_summaries(&summaries);
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&tree))
+                }
+            }
+        });
+
+    // 定義 /api/export/csv 端點，下載可用試算表開啟的 CSV（每個檔案一列）
+    let export_csv_route = warp::path!("api" / "export" / "csv")
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let csv = {
+                        let project = state.project.read().await;
+                        let progress = state.progress.read().await;
+                        export::progress_to_csv(&progress, &project)
+                    };
+                    let response = warp::reply::with_header(
+                        warp::reply::with_header(csv, "Content-Type", "text/csv; charset=utf-8"),
+                        "Content-Disposition",
+                        format!("attachment; filename=\"{}\"", export::CSV_EXPORT_FILENAME),
+                    );
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/regenerate-rollup 端點，重新生成專案總覽
+    let regenerate_rollup_route = warp::path!("api" / "regenerate-rollup")
+        .and(warp::post())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let response = match generate_rollup_summary(&state).await {
+                        Ok(summary) => warp::reply::json(&serde_json::json!({ "rollup_summary": summary })).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/directory-summary/<path> 端點，path 為相對於專案目錄的目錄路徑（省略時為專案根目錄）；
+    // /api/folder-summary/<path> 為相同端點的別名
+    let directory_summary_route = warp::path!("api" / "directory-summary" / ..)
+        .or(warp::path!("api" / "folder-summary" / ..))
+        .unify()
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move |tail: warp::path::Tail| {
+                let state = state.clone();
+                async move {
+                    let relative = percent_encoding::percent_decode_str(tail.as_str()).decode_utf8_lossy().to_string();
+                    let response = match generate_directory_summary(&state, &relative).await {
+                        Ok(summary) => warp::reply::json(&serde_json::json!({
+                            "path": relative.trim_matches('/'),
+                            "summary": summary,
+                        }))
+                        .into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 DELETE /api/cache 端點，清除摘要快取並重設進度；/api/cache/<path> 只清除單一檔案
+    let clear_cache_route = warp::path!("api" / "cache")
+        .and(warp::delete())
+        .and_then({
+            let state = state.clone();
+            move || {
+                let state = state.clone();
+                async move {
+                    let response = match clear_summary_cache(&state).await {
+                        Ok(deleted) => warp::reply::json(&serde_json::json!({ "deleted_entries": deleted })).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    let invalidate_cache_route = warp::path!("api" / "cache" / ..)
+        .and(warp::path::tail())
+        .and(warp::delete())
+        .and_then({
+            let state = state.clone();
+            move |tail: warp::path::Tail| {
+                let state = state.clone();
+                async move {
+                    let relative = percent_encoding::percent_decode_str(tail.as_str()).decode_utf8_lossy().to_string();
+                    let response = match invalidate_cached_summary(&state, &relative).await {
+                        Ok(deleted) => warp::reply::json(&serde_json::json!({ "deleted_entries": deleted })).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/file-summary/<path> 端點，path 為相對於專案目錄的檔案路徑
+    let file_summary_route = warp::path!("api" / "file-summary" / ..)
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move |tail: warp::path::Tail| {
+                let state = state.clone();
+                async move {
+                    let relative = percent_encoding::percent_decode_str(tail.as_str()).decode_utf8_lossy().to_string();
+                    let response = match file_summary(&state, &relative).await {
+                        Ok(summary) => warp::reply::json(&summary).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/file-content/<path> 端點，path 為相對於專案目錄的檔案路徑，回傳檔案原始內容
+    let file_content_route = warp::path!("api" / "file-content" / ..)
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and(warp::query::<FileContentParams>())
+        .and_then({
+            let state = state.clone();
+            move |tail: warp::path::Tail, params: FileContentParams| {
+                let state = state.clone();
+                async move {
+                    let relative = percent_encoding::percent_decode_str(tail.as_str()).decode_utf8_lossy().to_string();
+                    let response = match file_content(&state, &relative, params.highlight.unwrap_or(false)).await {
+                        Ok(response) => response,
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/raw-llm-response/<path> 端點，回傳檔案最近一次摘要的 LLM 原始回應（需要 --debug-llm）
+    let raw_llm_response_route = warp::path!("api" / "raw-llm-response" / ..)
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move |tail: warp::path::Tail| {
+                let state = state.clone();
+                async move {
+                    let relative = percent_encoding::percent_decode_str(tail.as_str()).decode_utf8_lossy().to_string();
+                    let response = match raw_llm_response(&state, &relative) {
+                        Ok(body) => warp::reply::json(&body).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 POST /api/import 端點，載入 /api/export/json 匯出的報告
+    let import_route = warp::path!("api" / "import")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then({
+            let state = state.clone();
+            move |report: Directory| {
+                let state = state.clone();
+                async move {
+                    let response = match import_report(&state, report).await {
+                        Ok(result) => warp::reply::json(&result).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
                 }
             }
         });
+
+    // 定義 /api/prompt-preview 端點，回傳摘要檔案時會送出的提示語，不呼叫 LLM
+    let prompt_preview_route = warp::path!("api" / "prompt-preview")
+        .and(warp::get())
+        .and(warp::query::<PromptPreviewParams>())
+        .and_then({
+            let state = state.clone();
+            move |params: PromptPreviewParams| {
+                let state = state.clone();
+                async move {
+                    let response = match prompt_preview(&state, &params).await {
+                        Ok(preview) => warp::reply::json(&preview).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 定義 /api/browse/<path> 端點，只回傳目錄的直接子項目，供目錄樹展開節點時載入
+    let browse_route = warp::path!("api" / "browse" / ..)
+        .and(warp::path::tail())
+        .and(warp::get())
+        .and_then({
+            let state = state.clone();
+            move |tail: warp::path::Tail| {
+                let state = state.clone();
+                async move {
+                    let relative = percent_encoding::percent_decode_str(tail.as_str()).decode_utf8_lossy().to_string();
+                    let response = match browse_directory(&state, &relative).await {
+                        Ok(listing) => warp::reply::json(&listing).into_response(),
+                        Err(e) => e.into_response(),
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            }
+        });
+
+    // 資料夾相關的路由先合併成一組，避免單一 or 鏈過長超過編譯器的遞迴上限
+    let folder_routes = folders_route
+        .or(filtered_folders_route)
+        .or(all_folders_route)
+        .or(folders_tree_text_route)
+        .or(folders_tree_with_files_route)
+        .or(manual_select_route)
+        .or(analyze_folders_route)
+        .or(suggest_folders_route)
+        .or(confirm_folders_route);
+
+    filtered_tree_route
+        .or(browse_route)
+        .or(file_summary_route)
+        .or(prompt_preview_route)
+        .or(cross_references_route)
+        .or(similar_route)
+        .or(quality_report_route)
+        .or(dependency_graph_route)
+        .or(raw_llm_response_route)
+        .or(directory_tree_depth_route)
+        .or(health_route)
+        .or(timeline_route)
+        .or(completion_estimate_route)
+        .or(clear_cache_route)
+        .or(invalidate_cache_route)
+        .or(progress_route)
+        .or(get_file_route)
+        .or(file_content_route)
+        .or(rescan_route)
+        .or(events_route)
+        .or(ws_route)
+        .or(regenerate_route)
+        .or(batch_summarize_route)
+        .or(export_markdown_route)
+        .or(export_json_route)
+        .or(export_csv_route)
+        .or(import_route)
+        .or(regenerate_rollup_route)
+        .or(search_route)
+        .or(stats_route)
+        .or(folder_routes)
+        .or(status_route)
+        .or(flat_tree_route)
+        .or(search_by_extension_route)
+        .or(directory_summary_route)
+        .or(annotate_route)
+}
+
+// 建立網頁伺服器的所有路由：首頁、/metrics、專案清單與各專案的 API
+fn build_routes(
+    registry: &ProjectRegistry,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone + Send + Sync + 'static {
         let index_html = warp::path::end().map(|| {
             warp::reply::html(
                 r#"
@@ -482,6 +4222,49 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             text-align: center;
                             color: #d4d4d4;
                         }
+                        #folder-selection {
+                            text-align: center;
+                            margin-bottom: 20px;
+                        }
+                        #folder-selection input {
+                            padding: 10px;
+                            font-size: 16px;
+                            width: 300px;
+                            background-color: #252526;
+                            color: #d4d4d4;
+                            border: 1px solid #3c3c3c;
+                        }
+                        #search {
+                            text-align: center;
+                            margin-bottom: 20px;
+                        }
+                        #search input {
+                            padding: 10px;
+                            font-size: 16px;
+                            width: 300px;
+                            background-color: #252526;
+                            color: #d4d4d4;
+                            border: 1px solid #3c3c3c;
+                        }
+                        #search-results {
+                            text-align: left;
+                            list-style: none;
+                            padding: 0;
+                        }
+                        #search-results a {
+                            color: #4fc1ff;
+                        }
+                        /* 相對於 --baseline 報告的檔案狀態 */
+                        .jstree-anchor.change-new {
+                            color: #89d185;
+                        }
+                        .jstree-anchor.change-modified {
+                            color: #e2c08d;
+                        }
+                        .jstree-anchor.change-deleted {
+                            color: #f48771;
+                            text-decoration: line-through;
+                        }
                         #controls {
                             text-align: center;
                             margin-bottom: 20px;
@@ -558,6 +4341,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 </head>
                 <body>
                     <h1>Quick Project Report </h1>
+                    <div id="project-selector" style="display: none; text-align: center; margin-bottom: 20px;">
+                        專案：<select id="project-select" onchange="switchProject(this.value)"></select>
+                    </div>
         
                     <!-- Tabs -->
                     <div class="tab-container">
@@ -570,6 +4356,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         <div id="controls">
                             <button onclick="fetchTree()">顯示目錄樹</button>
                             <button onclick="fetchProgress()">查看摘要進度</button>
+                            <button onclick="rescanProject()">重新掃描</button>
+                            <button onclick="exportMarkdown()">匯出 Markdown</button>
+                        </div>
+                        <div id="folder-selection">
+                            <div id="phase"></div>
+                            <div id="filtered-folders"></div>
+                            <div id="folder-controls">
+                                <ul id="folder-checklist"></ul>
+                                <input type="text" id="folder-hints" placeholder="補充資料夾路徑（以逗號分隔）">
+                                <button onclick="manualSelect(false)">重新分析資料夾</button>
+                                <button onclick="suggestFolders()">取得 LLM 建議</button>
+                                <button onclick="confirmFolders()">以勾選的資料夾開始生成摘要</button>
+                            </div>
+                        </div>
+                        <div id="search">
+                            <input type="text" id="search-input" placeholder="搜尋檔名或摘要..." onkeydown="if (event.key === 'Enter') searchFiles()">
+                            <button onclick="searchFiles()">搜尋</button>
+                            <ul id="search-results"></ul>
                         </div>
                         <div id="main">
                             <div id="jstree"></div>
@@ -583,11 +4387,210 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     <!-- Content: Total Summary -->
                     <div id="summary-tab" class="content-container">
                         <h2>總摘要</h2>
+                        <div id="controls">
+                            <button onclick="regenerateRollup()">重新生成專案總覽</button>
+                        </div>
+                        <div id="rollup"></div>
                         <div id="progress"></div>
                     </div>
         
                     <script>
                         let progressData = null;
+                        let tokenPrompt = null;
+                        // 目前檢視的專案 id，null 時使用未加前綴的路由（第一個專案）
+                        let currentProject = null;
+                        let eventsController = null;
+                        // 摘要事件中出現目錄樹尚未顯示的新檔案時，合併多個事件後再重新載入目錄樹
+                        let treeRefreshTimer = null;
+                        const TREE_REFRESH_DELAY_MS = 1000;
+
+                        function projectUrl(path) {
+                            return currentProject ? '/projects/' + encodeURIComponent(currentProject) + path : path;
+                        }
+
+                        // 讀取專案清單，有多個專案時顯示專案選單
+                        async function loadProjects() {
+                            try {
+                                const response = await authFetch('/projects');
+                                const projects = await response.json();
+                                const select = $('#project-select').empty();
+                                for (const project of projects) {
+                                    select.append($('<option>').val(project.id).text(`${project.id}（${project.path}）`));
+                                }
+                                if (projects.length > 0) {
+                                    currentProject = projects[0].id;
+                                }
+                                $('#project-selector').toggle(projects.length > 1);
+                            } catch (error) {
+                                console.error('抓取專案清單時出錯:', error);
+                            }
+                        }
+
+                        // 切換專案後重新載入目錄樹、進度與狀態，並改為訂閱該專案的事件
+                        function switchProject(projectId) {
+                            currentProject = projectId;
+                            progressData = null;
+                            lastStatus = null;
+                            $('#rollup').empty();
+                            $('#progress').empty();
+                            $('#filtered-folders').empty();
+                            fetchTree();
+                            fetchProgress();
+                            fetchFolders();
+                            subscribeEvents();
+                        }
+
+                        // 伺服器啟用 --api-token 時，於第一次收到 401 後詢問 token 並存於 localStorage
+                        function askForToken() {
+                            if (!tokenPrompt) {
+                                tokenPrompt = new Promise(resolve => {
+                                    const token = prompt('請輸入 API token：');
+                                    if (token) {
+                                        localStorage.setItem('qprApiToken', token);
+                                    } else {
+                                        localStorage.removeItem('qprApiToken');
+                                    }
+                                    tokenPrompt = null;
+                                    resolve(token);
+                                });
+                            }
+                            return tokenPrompt;
+                        }
+
+                        // 帶上 Authorization 標頭的 fetch，token 錯誤時重新詢問一次
+                        async function authFetch(url, options = {}, retried = false) {
+                            const token = localStorage.getItem('qprApiToken');
+                            const headers = Object.assign({}, options.headers || {});
+                            if (token) {
+                                headers['Authorization'] = 'Bearer ' + token;
+                            }
+                            const response = await fetch(url, Object.assign({}, options, { headers }));
+                            if (response.status === 401 && !retried && await askForToken()) {
+                                return authFetch(url, options, true);
+                            }
+                            return response;
+                        }
+
+                        // --watch 模式下檔案被刪除時移除其摘要
+                        function handleDeleteEvent(data) {
+                            if (progressData) {
+                                delete progressData.summaries[data.file_path];
+                                displayProgress(progressData, document.getElementById('progress'));
+                            }
+                            fetchTree();
+                        }
+
+                        function handleSummaryEvent(data) {
+                            if (!progressData) {
+                                progressData = { total_files: 0, completed_files: 0, summaries: {} };
+                            }
+                            progressData.summaries[data.file_path] = data.summary;
+                            progressData.completed_files = data.completed;
+                            progressData.total_files = data.total;
+                            progressData.total_tokens_used = data.total_tokens_used;
+                            displayProgress(progressData, document.getElementById('progress'));
+                            updateTreeFileSummary(data.file_path, data.summary);
+                        }
+
+                        // 只更新目錄樹中對應的檔案節點，不重新載入整棵樹（保留展開狀態）；
+                        // 所在目錄尚未展開時不需處理，展開時會載入最新的摘要
+                        function updateTreeFileSummary(filePath, summary) {
+                            const tree = $('#jstree').jstree(true);
+                            if (!tree) {
+                                return;
+                            }
+                            const dirPath = filePath.slice(0, filePath.lastIndexOf('/'));
+                            let dirNode = null;
+                            for (const { id } of tree.get_json('#', { flat: true, no_state: true, no_data: true, no_li_attr: true, no_a_attr: true })) {
+                                const node = tree.get_node(id);
+                                if (!node.original) {
+                                    continue;
+                                }
+                                if (node.original.type === 'file' && node.original.path === filePath) {
+                                    node.original.summary = summary || '無摘要';
+                                    return;
+                                }
+                                if (node.original.type === 'folder' && node.original.path === dirPath) {
+                                    dirNode = node;
+                                }
+                            }
+                            // 已載入的目錄中出現新檔案（例如 --watch 或批次摘要加入的檔案）
+                            if (dirNode && tree.is_loaded(dirNode)) {
+                                scheduleTreeRefresh();
+                            }
+                        }
+
+                        function scheduleTreeRefresh() {
+                            clearTimeout(treeRefreshTimer);
+                            treeRefreshTimer = setTimeout(() => {
+                                treeRefreshTimer = null;
+                                fetchTree();
+                            }, TREE_REFRESH_DELAY_MS);
+                        }
+
+                        // 透過 SSE 即時接收摘要完成事件；EventSource 無法帶標頭，因此以 fetch 讀取串流
+                        async function subscribeEvents() {
+                            // 同時只保留一條連線，切換專案時中斷舊的連線
+                            if (eventsController) {
+                                eventsController.abort();
+                            }
+                            const controller = new AbortController();
+                            eventsController = controller;
+                            try {
+                                const response = await authFetch(projectUrl('/api/events'), { signal: controller.signal });
+                                if (!response.ok) {
+                                    throw new Error('HTTP ' + response.status);
+                                }
+                                const reader = response.body.getReader();
+                                const decoder = new TextDecoder();
+                                let buffer = '';
+                                while (true) {
+                                    const { value, done } = await reader.read();
+                                    if (done) {
+                                        break;
+                                    }
+                                    buffer += decoder.decode(value, { stream: true });
+                                    const messages = buffer.split('\n\n');
+                                    buffer = messages.pop();
+                                    for (const message of messages) {
+                                        const lines = message.split('\n');
+                                        const eventLine = lines.find(line => line.startsWith('event:'));
+                                        const eventType = eventLine ? eventLine.slice(6).trim() : 'message';
+                                        const data = lines
+                                            .filter(line => line.startsWith('data:'))
+                                            .map(line => line.slice(5).trimStart())
+                                            .join('\n');
+                                        if (!data) {
+                                            continue;
+                                        }
+                                        if (eventType === 'delete') {
+                                            handleDeleteEvent(JSON.parse(data));
+                                        } else {
+                                            handleSummaryEvent(JSON.parse(data));
+                                        }
+                                    }
+                                }
+                            } catch (error) {
+                                if (controller.signal.aborted) {
+                                    return;
+                                }
+                                console.error('SSE 連線出錯:', error);
+                            }
+                            // 連線中斷後稍候重新連線（已切換專案時不重連）
+                            setTimeout(() => {
+                                if (eventsController === controller) {
+                                    subscribeEvents();
+                                }
+                            }, 3000);
+                        }
+        
+                        window.addEventListener('load', async () => {
+                            await loadProjects();
+                            pollStatus();
+                            fetchTree();
+                            fetchProgress();
+                            subscribeEvents();
+                        });
         
                         function showTab(tabId) {
                             // Hide all content containers
@@ -605,19 +4608,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             document.querySelector(`[onclick="showTab('${tabId}')"]`).classList.add('active');
                         }
         
-                        async function fetchTree() {
-                            try {
-                                const response = await fetch('/filtered-tree');
-                                const data = await response.json();
-                                displayTree(data);
-                            } catch (error) {
-                                console.error('抓取目錄樹時出錯:', error);
+                        // 重新建立目錄樹，節點在展開時才向 /api/browse/ 載入子項目
+                        function fetchTree() {
+                            displayTree();
+                        }
+
+                        // 取得目錄的直接子項目，relative 為相對於專案目錄的路徑
+                        async function fetchTreeToDepth(depth) {
+                            const response = await authFetch(projectUrl('/api/directory-tree/depth/' + depth));
+                            if (!response.ok) {
+                                throw new Error('HTTP ' + response.status);
+                            }
+                            return response.json();
+                        }
+
+                        async function browseDirectory(relative) {
+                            const url = '/api/browse/' + relative.split('/').map(encodeURIComponent).join('/');
+                            const response = await authFetch(projectUrl(url));
+                            const data = await response.json();
+                            if (!response.ok) {
+                                throw new Error(data.error);
                             }
+                            return data;
                         }
         
                         async function fetchProgress() {
                             try {
-                                const response = await fetch('/progress');
+                                const response = await authFetch(projectUrl('/progress'));
                                 const data = await response.json();
                                 progressData = data;
                                 displayProgress(data, document.getElementById('progress'));
@@ -626,12 +4643,214 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             }
                         }
         
+                        const phaseNames = {
+                            gathering_folders: '選擇資料夾中',
+                            summarizing: '生成摘要中',
+                            done: '已完成'
+                        };
+        
+                        let lastStatus = null;
+
+                        // 定期查詢 /api/status，依目前階段更新畫面
+                        async function pollStatus() {
+                            await fetchFolders();
+                            const phase = lastStatus && lastStatus.phase;
+                            setTimeout(pollStatus, phase === 'done' ? 10000 : 2000);
+                        }
+
+                        async function fetchFolders() {
+                            try {
+                                const response = await authFetch(projectUrl('/api/status'));
+                                const status = await response.json();
+                                const previous = lastStatus;
+                                lastStatus = status;
+                                displayStatus(status, previous);
+                            } catch (error) {
+                                console.error('抓取目前狀態時出錯:', error);
+                            }
+                        }
+
+                        function displayStatus(status, previous) {
+                            if (status.analyzing) {
+                                $('#phase').text('目前階段：LLM 正在分析資料夾...');
+                            } else if (status.phase === 'summarizing') {
+                                $('#phase').text(`目前階段：${phaseNames[status.phase]}（${status.completed_files} / ${status.total_files}）`);
+                            } else {
+                                $('#phase').text(`目前階段：${phaseNames[status.phase] || status.phase}`);
+                            }
+                            $('#folder-controls').toggle(status.phase === 'gathering_folders');
+
+                            const folders = status.filtered_folders;
+                            const unchanged = previous && !previous.analyzing && previous.phase === status.phase &&
+                                previous.filtered_folders.join('\n') === folders.join('\n');
+                            if (status.analyzing || unchanged) {
+                                return;
+                            }
+                            $('#filtered-folders').text(`選定的資料夾：${folders.join(', ') || '（無）'}`);
+
+                            // 選擇資料夾階段：列出可勾選的資料夾
+                            const checklist = $('#folder-checklist').empty();
+                            for (const folder of folders) {
+                                const checkbox = $('<input type="checkbox" class="folder-option" checked>').val(folder);
+                                checklist.append($('<li>').append($('<label>').append(checkbox, ' ', folder)));
+                            }
+
+                            // 摘要完成時重新載入進度與目錄樹
+                            if (previous && previous.phase !== status.phase) {
+                                fetchTree();
+                                fetchProgress();
+                            }
+                        }
+
+                        async function confirmFolders() {
+                            const checked = $('.folder-option:checked').map((_, el) => el.value).get();
+                            const extra = $('#folder-hints').val().split(',').map(s => s.trim()).filter(s => s);
+                            const folders = checked.concat(extra);
+                            if (folders.length === 0) {
+                                alert('請至少選擇一個資料夾');
+                                return;
+                            }
+                            try {
+                                const response = await authFetch(projectUrl('/api/folders/confirm'), {
+                                    method: 'POST',
+                                    headers: { 'Content-Type': 'application/json' },
+                                    body: JSON.stringify({ folders })
+                                });
+                                const data = await response.json();
+                                if (!response.ok) {
+                                    alert(data.error);
+                                }
+                                $('#folder-hints').val('');
+                                fetchFolders();
+                            } catch (error) {
+                                console.error('確認資料夾時出錯:', error);
+                            }
+                        }
+        
+                        // 取得 LLM 建議的資料夾並預先勾選，伺服器上的選擇不變，按下開始生成摘要時才送出
+                        async function suggestFolders() {
+                            const extraHint = $('#folder-hints').val().trim();
+                            $('#phase').text('LLM 建議資料夾中...');
+                            try {
+                                const response = await authFetch(projectUrl('/api/folders/suggest'), {
+                                    method: 'POST',
+                                    headers: { 'Content-Type': 'application/json' },
+                                    body: JSON.stringify({ extra_hint: extraHint })
+                                });
+                                const data = await response.json();
+                                if (!response.ok) {
+                                    alert(data.error);
+                                    return;
+                                }
+                                const current = $('.folder-option').map((_, el) => el.value).get();
+                                const checklist = $('#folder-checklist').empty();
+                                for (const folder of new Set(data.suggested.concat(current))) {
+                                    const checkbox = $('<input type="checkbox" class="folder-option">').val(folder).prop('checked', data.suggested.includes(folder));
+                                    checklist.append($('<li>').append($('<label>').append(checkbox, ' ', folder)));
+                                }
+                            } catch (error) {
+                                console.error('取得資料夾建議時出錯:', error);
+                            }
+                        }
+
+                        async function manualSelect(finalize) {
+                            const add = $('#folder-hints').val().split(',').map(s => s.trim()).filter(s => s);
+                            $('#phase').text(finalize ? '開始生成摘要...' : '重新分析資料夾中...');
+                            try {
+                                const response = await authFetch(projectUrl('/api/folders/manual-select'), {
+                                    method: 'POST',
+                                    headers: { 'Content-Type': 'application/json' },
+                                    body: JSON.stringify({ add, finalize })
+                                });
+                                const data = await response.json();
+                                if (!response.ok) {
+                                    alert(data.error);
+                                }
+                                $('#folder-hints').val('');
+                                fetchFolders();
+                                fetchProgress();
+                            } catch (error) {
+                                console.error('選擇資料夾時出錯:', error);
+                            }
+                        }
+        
+                        async function rescanProject() {
+                            try {
+                                const response = await authFetch(projectUrl('/api/rescan'), { method: 'POST' });
+                                const data = await response.json();
+                                if (!response.ok) {
+                                    alert(data.error);
+                                    return;
+                                }
+                                alert(`重新掃描完成：新增 ${data.new_files} 個檔案，變動 ${data.changed_files} 個檔案，移除 ${data.removed_files} 個檔案`);
+                                fetchTree();
+                            } catch (error) {
+                                console.error('重新掃描時出錯:', error);
+                            }
+                        }
+        
+                        // 以 authFetch 下載（直接導向網址不會帶 Authorization 標頭），再以 Blob 存成檔案
+                        async function exportMarkdown() {
+                            try {
+                                const response = await authFetch(projectUrl('/api/export/markdown'));
+                                if (!response.ok) {
+                                    const data = await response.json();
+                                    alert(data.error);
+                                    return;
+                                }
+                                const disposition = response.headers.get('Content-Disposition') || '';
+                                const match = disposition.match(/filename="([^"]+)"/);
+                                const url = URL.createObjectURL(await response.blob());
+                                const link = document.createElement('a');
+                                link.href = url;
+                                link.download = match ? match[1] : 'qpr_report.md';
+                                document.body.appendChild(link);
+                                link.click();
+                                link.remove();
+                                URL.revokeObjectURL(url);
+                            } catch (error) {
+                                console.error('匯出 Markdown 時出錯:', error);
+                            }
+                        }
+        
+                        async function regenerateRollup() {
+                            $('#rollup').text('重新生成中...');
+                            try {
+                                const response = await authFetch(projectUrl('/api/regenerate-rollup'), { method: 'POST' });
+                                const data = await response.json();
+                                if (!response.ok) {
+                                    $('#rollup').text(data.error);
+                                    return;
+                                }
+                                if (progressData) {
+                                    progressData.rollup_summary = data.rollup_summary;
+                                }
+                                displayRollup(data.rollup_summary);
+                            } catch (error) {
+                                console.error('重新生成專案總覽時出錯:', error);
+                            }
+                        }
+        
+                        function displayRollup(rollupSummary) {
+                            const rollup = $('#rollup').empty();
+                            rollup.append($('<h3>').text('專案總覽'));
+                            rollup.append($('<p>').css('white-space', 'pre-wrap').text(rollupSummary || '尚未生成專案總覽。'));
+                        }
+        
                         function displayProgress(progress, parentElement) {
+                            displayRollup(progress.rollup_summary);
                             parentElement.innerHTML = '';
-                            const progressText = `已完成 ${progress.completed_files} / ${progress.total_files} 個摘要`;
+                            const progressText = `已完成 ${progress.completed_files} / ${progress.total_files} 個摘要，已使用 ${progress.total_tokens_used || 0} tokens`;
                             const progressDiv = document.createElement('div');
                             progressDiv.innerText = progressText;
                             parentElement.appendChild(progressDiv);
+
+                            const skipped = progress.skipped_large_files || [];
+                            if (skipped.length > 0) {
+                                const skippedDiv = document.createElement('div');
+                                skippedDiv.innerText = `因檔案過大而略過 ${skipped.length} 個檔案：${skipped.join(', ')}`;
+                                parentElement.appendChild(skippedDiv);
+                            }
         
                             const summariesUl = document.createElement('ul');
                             for (const [filePath, summary] of Object.entries(progress.summaries)) {
@@ -642,13 +4861,37 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             parentElement.appendChild(summariesUl);
                         }
         
-                        function displayTree(directory) {
-                            const treeData = [convertToJsTreeFormat(directory)];
-        
+                        // 專案根目錄的路徑，用於換算目錄摘要 API 的相對路徑
+                        let treeRootPath = '';
+                        // 初次載入的目錄層數，更深的目錄展開時才載入
+                        const INITIAL_TREE_DEPTH = 2;
+
+                        function displayTree() {
                             $('#jstree').jstree('destroy'); // 重置 jstree
                             $('#jstree').jstree({
                                 'core': {
-                                    'data': treeData,
+                                    'data': function (node, callback) {
+                                        // 初次載入前幾層，更深的目錄展開時再以 /api/browse 載入
+                                        if (node.id === '#') {
+                                            fetchTreeToDepth(INITIAL_TREE_DEPTH).then(tree => {
+                                                treeRootPath = tree.path;
+                                                const root = convertTreeToJsTreeNode(tree);
+                                                root.state = { opened: true };
+                                                callback([root]);
+                                            }).catch(error => {
+                                                console.error('抓取目錄樹時出錯:', error);
+                                                callback([]);
+                                            });
+                                            return;
+                                        }
+                                        const relative = node.original.relative;
+                                        browseDirectory(relative).then(listing => {
+                                            callback(convertToJsTreeNodes(listing, relative));
+                                        }).catch(error => {
+                                            console.error('抓取目錄樹時出錯:', error);
+                                            callback([]);
+                                        });
+                                    },
                                     'themes': {
                                         'variant': 'large',
                                         'dots': true,
@@ -665,41 +4908,100 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     const filePath = node.original.path;
                                     displayFileSummaryAndCode(filePath);
                                     showTab('file-tab');  // 點擊檔案後顯示檔案目錄和程式碼頁
-                                } else {
-                                    $('#file-summary').html('請選擇一個檔案以查看摘要和程式碼。');
+                                } else if (node.original) {
+                                    displayDirectorySummary(node.original.path, treeRootPath);
+                                }
+                            });
+                        }
+
+                        // 顯示目錄的整體摘要，尚未生成時提供生成按鈕
+                        function displayDirectorySummary(dirPath, rootPath) {
+                            const summaries = (progressData && progressData.directory_summaries) || {};
+                            const summary = $('<p>').text(summaries[dirPath] || '此目錄尚未生成摘要。');
+                            const button = $('<button>').text('生成目錄摘要').on('click', async () => {
+                                summary.text('生成中...');
+                                const relative = dirPath === rootPath ? '' : dirPath.slice(rootPath.length + 1);
+                                const url = '/api/directory-summary/' + relative.split('/').map(encodeURIComponent).join('/');
+                                try {
+                                    const response = await authFetch(projectUrl(url));
+                                    const data = await response.json();
+                                    if (!response.ok) {
+                                        summary.text(data.error);
+                                        return;
+                                    }
+                                    summary.text(data.summary);
+                                    if (progressData) {
+                                        progressData.directory_summaries = Object.assign({}, summaries, { [dirPath]: data.summary });
+                                    }
+                                } catch (error) {
+                                    summary.text('生成目錄摘要時出錯。');
                                 }
                             });
+                            $('#file-summary').empty().append($('<h3>').text(`目錄摘要：${dirPath}`), summary, button);
                         }
         
-                        function convertToJsTreeFormat(directory) {
+                        function formatSize(bytes) {
+                            if (bytes < 1024) {
+                                return `${bytes} B`;
+                            }
+                            if (bytes < 1024 * 1024) {
+                                return `${(bytes / 1024).toFixed(1)} KB`;
+                            }
+                            return `${(bytes / 1024 / 1024).toFixed(1)} MB`;
+                        }
+
+                        // 將 /api/browse/ 的回應轉成 jsTree 節點，子目錄設為 children: true 以便展開時再載入
+                        // 將 /api/directory-tree/depth/<n> 的目錄轉成 jsTree 節點；truncated 的目錄展開時才載入
+                        function convertTreeToJsTreeNode(dir) {
+                            const relative = dir.path === treeRootPath ? '' : dir.path.slice(treeRootPath.length + 1);
                             const node = {
-                                text: directory.name,
-                                children: [],
-                                state: {
-                                    opened: true
-                                },
+                                text: dir.name,
                                 type: 'folder',
-                                path: directory.path
+                                path: dir.path,
+                                relative: relative
                             };
-        
-                            directory.files.sort((a, b) => a.name.localeCompare(b.name));
-                            for (const file of directory.files) {
+                            if (dir.truncated) {
+                                node.children = true;
+                                return node;
+                            }
+                            node.children = (dir.subdirs || []).map(convertTreeToJsTreeNode);
+                            for (const file of dir.files || []) {
                                 node.children.push({
-                                    text: file.name,
+                                    text: `${file.name} (${formatSize(file.size_bytes || 0)})`,
+                                    children: false,
                                     type: 'file',
-                                    path: `${directory.path}/${file.name}`,
+                                    path: `${dir.path}/${file.name}`,
                                     summary: file.summary || '無摘要',
-                                    icon: 'jstree-file'
+                                    icon: 'jstree-file',
+                                    a_attr: file.change_status ? { class: `change-${file.change_status}` } : {}
                                 });
                             }
-        
-                            directory.subdirs.sort((a, b) => a.name.localeCompare(b.name));
-                            for (const subdir of directory.subdirs) {
-                                node.children.push(convertToJsTreeFormat(subdir));
-                            }
-        
                             return node;
                         }
+
+                        function convertToJsTreeNodes(listing, relative) {
+                            const childRelative = name => relative ? `${relative}/${name}` : name;
+                            const nodes = listing.dirs.map(dir => ({
+                                text: dir.name,
+                                children: true,
+                                type: 'folder',
+                                path: dir.path,
+                                relative: childRelative(dir.name),
+                                a_attr: dir.summary ? { title: dir.summary } : {}
+                            }));
+                            for (const file of listing.files) {
+                                nodes.push({
+                                    text: `${file.name} (${formatSize(file.size_bytes || 0)})`,
+                                    children: false,
+                                    type: 'file',
+                                    path: file.path,
+                                    summary: file.summary || '無摘要',
+                                    icon: 'jstree-file',
+                                    a_attr: file.change_status ? { class: `change-${file.change_status}` } : {}
+                                });
+                            }
+                            return nodes;
+                        }
         
                         async function displayFileSummaryAndCode(filePath) {
                             if (!progressData) {
@@ -711,7 +5013,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             let codeContent = '';
         
                             try {
-                                const response = await fetch('/get-file?path=' + encodeURIComponent(filePath));
+                                const response = await authFetch(projectUrl('/get-file?path=' + encodeURIComponent(filePath)));
                                 if (response.ok) {
                                     codeContent = await response.text();
                                 } else {
@@ -732,6 +5034,104 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             } else {
                                 $('#file-summary').html(`<h3>摘要：</h3><p>此檔案沒有摘要。</p><h3>程式碼：</h3>${codeHtml}`);
                             }
+                            $('#file-summary').prepend($('<button>').text('重新生成摘要').on('click', () => regenerateSummary(filePath)));
+
+                            // 使用者的手動註記與編輯按鈕
+                            const annotation = progressData.annotations && progressData.annotations[filePath];
+                            const note = $('<p class="annotation">');
+                            if (annotation) {
+                                note.text(`註記（${annotationModeNames[annotation.mode]}）：${annotation.annotation}`);
+                            }
+                            const editButton = $('<button>').text('編輯註記').on('click', () => editAnnotation(filePath, annotation));
+                            $('#file-summary p').first().after(note, editButton);
+                        }
+
+                        const annotationModeNames = {
+                            replace: '取代摘要',
+                            prepend: '加在摘要前',
+                            append: '加在摘要後'
+                        };
+
+                        // 顯示註記編輯區，儲存空白內容時刪除註記
+                        function editAnnotation(filePath, annotation) {
+                            const text = $('<textarea rows="4" style="width: 100%;">').val(annotation ? annotation.annotation : '');
+                            const mode = $('<select>');
+                            for (const [value, label] of Object.entries(annotationModeNames)) {
+                                mode.append($('<option>').val(value).text(label));
+                            }
+                            mode.val(annotation ? annotation.mode : 'append');
+                            const save = $('<button>').text('儲存註記').on('click', async () => {
+                                try {
+                                    const response = await authFetch(projectUrl('/api/annotate'), {
+                                        method: 'POST',
+                                        headers: { 'Content-Type': 'application/json' },
+                                        body: JSON.stringify({ file_path: filePath, annotation: text.val(), mode: mode.val() })
+                                    });
+                                    const data = await response.json();
+                                    if (!response.ok) {
+                                        alert(data.error);
+                                        return;
+                                    }
+                                    progressData.annotations = progressData.annotations || {};
+                                    if (data.annotation) {
+                                        progressData.annotations[filePath] = data.annotation;
+                                    } else {
+                                        delete progressData.annotations[filePath];
+                                    }
+                                    displayFileSummaryAndCode(filePath);
+                                } catch (error) {
+                                    console.error('儲存註記時出錯:', error);
+                                }
+                            });
+                            $('#annotation-editor').remove();
+                            $('#file-summary p').first().after($('<div id="annotation-editor">').append(text, mode, save));
+                        }
+        
+                        async function regenerateSummary(filePath) {
+                            $('#file-summary p').first().text('重新生成中...');
+                            try {
+                                const response = await authFetch(projectUrl('/api/regenerate'), {
+                                    method: 'POST',
+                                    headers: { 'Content-Type': 'application/json' },
+                                    body: JSON.stringify({ file_path: filePath })
+                                });
+                                const data = await response.json();
+                                if (!response.ok) {
+                                    alert(`重新生成失敗：${data.error}`);
+                                    return;
+                                }
+                                if (progressData) {
+                                    progressData.summaries[filePath] = data.summary;
+                                }
+                                displayFileSummaryAndCode(filePath);
+                            } catch (error) {
+                                console.error('重新生成摘要時出錯:', error);
+                            }
+                        }
+        
+                        async function searchFiles() {
+                            const query = $('#search-input').val().trim();
+                            const resultsList = $('#search-results').empty();
+                            if (!query) {
+                                return;
+                            }
+                            try {
+                                const response = await authFetch(projectUrl(`/api/search?q=${encodeURIComponent(query)}`));
+                                const results = await response.json();
+                                if (results.length === 0) {
+                                    resultsList.append($('<li>').text('找不到符合的檔案。'));
+                                    return;
+                                }
+                                for (const result of results) {
+                                    const link = $('<a>').attr('href', '#').text(result.path).on('click', (event) => {
+                                        event.preventDefault();
+                                        displayFileSummaryAndCode(result.path);
+                                    });
+                                    resultsList.append($('<li>').append(link).append($('<div>').text(result.snippet)));
+                                }
+                            } catch (error) {
+                                console.error('搜尋時出錯:', error);
+                            }
                         }
         
                         function escapeHtml(text) {
@@ -766,47 +5166,923 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 "#
             )
         });
-        
-        
 
-    // 添加新的路由來處理檔案內容請求
-    let get_file_route = warp::path("get-file")
+    // 各專案的路由位於 /projects/<id>/...；為了相容單一專案的用法，未加前綴的路由對應第一個專案
+    let mut project_routes = build_project_routes(&registry.first().state)
+        .map(Reply::into_response)
+        .boxed();
+    for entry in &registry.projects {
+        let routes = warp::path("projects")
+            .and(warp::path(entry.id.clone()))
+            .and(build_project_routes(&entry.state))
+            .map(Reply::into_response);
+        project_routes = routes.or(project_routes).unify().boxed();
+    }
+
+    // 定義 /projects 端點，列出所有專案的 id 與路徑
+    let projects_route = warp::path("projects")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map({
+            let infos = Arc::new(registry.infos());
+            move || warp::reply::json(&*infos)
+        });
+
+    // 定義 /projects/merged-tree 端點，將所有專案的目錄樹（含摘要）合併成一棵，方便檢視分散在多個根目錄的 monorepo
+    let merged_tree_route = warp::path!("projects" / "merged-tree")
         .and(warp::get())
-        .and(warp::query::<HashMap<String, String>>())
         .and_then({
-            move |params: HashMap<String, String>| async move {
-                let response = if let Some(path) = params.get("path") {
-                    if let Ok(content) = fs::read_to_string(path) {
-                        warp::reply::html(content).into_response()
-                    } else {
-                        warp::reply::with_status(
-                            warp::reply::html("無法取得檔案內容。"),
-                            warp::http::StatusCode::NOT_FOUND,
-                        )
-                        .into_response()
+            let registry = registry.clone();
+            move || {
+                let registry = registry.clone();
+                async move {
+                    let mut merged: Option<Directory> = None;
+                    for entry in &registry.projects {
+                        let summaries = entry.state.progress.read().await.annotated_summaries();
+                        let tree = entry.state.project.read().await.with_summaries(&summaries);
+                        match &mut merged {
+                            Some(merged) => merged.merge(&tree),
+                            None => merged = Some(tree),
+                        }
                     }
-                } else {
-                    warp::reply::with_status(
-                        warp::reply::html("無法取得檔案內容。"),
-                        warp::http::StatusCode::NOT_FOUND,
-                    )
-                    .into_response()
-                };
-                Ok::<_, std::convert::Infallible>(response)
+                    Ok::<_, std::convert::Infallible>(warp::reply::json(&merged))
+                }
             }
         });
 
-    // 合併所有路由
-    let routes = filtered_tree_route
-        .or(progress_route)
-        .or(get_file_route)
-        .or(index_html);
+    // 定義 /metrics 端點（Prometheus 文字格式），不需驗證以便直接抓取
+    let metrics_route = warp::path("metrics")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and_then({
+            let registry = registry.clone();
+            move || {
+                let registry = registry.clone();
+                async move {
+                    let mut files_total = 0;
+                    for entry in &registry.projects {
+                        files_total += entry.state.progress.read().await.total_files;
+                    }
+                    let state = &registry.first().state;
+                    let body = state.metrics.render(state.backend.name(), state.backend.total_tokens_used(), files_total);
+                    Ok::<_, std::convert::Infallible>(warp::reply::with_header(
+                        body,
+                        "Content-Type",
+                        "text/plain; version=0.0.4",
+                    ))
+                }
+            }
+        });
 
-    // 啟動伺服器
-    println!("啟動網頁伺服器，請訪問 http://127.0.0.1:{}", SERVER_PORT);
-    warp::serve(routes)
-        .run(([127, 0, 0, 1], SERVER_PORT))
-        .await;
+    // 合併所有路由；設定 api_token 時除了首頁以外都需要驗證
+    let api_token: Option<Arc<str>> = registry.first().state.config.api_token.as_deref().map(Arc::from);
+    index_html
+        .or(metrics_route)
+        .or(auth::require_auth(api_token).and(projects_route.or(merged_tree_route).or(project_routes)))
+        .recover(auth::handle_rejection)
+        .with(warp::trace::request())
+}
 
-    Ok(())
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use llm::MockLlmBackend;
+    use tempfile::TempDir;
+
+    // 以 mock 後端載入的暫存專案；TempDir 在測試結束時刪除
+    pub(crate) struct TestProject {
+        root: TempDir,
+        _cache_dir: TempDir,
+        pub(crate) state: AppState,
+        pub(crate) mock: Arc<MockLlmBackend>,
+    }
+
+    impl TestProject {
+        pub(crate) fn path(&self, relative: &str) -> String {
+            self.root.path().join(relative).to_string_lossy().to_string()
+        }
+    }
+
+    // 寫入 (相對路徑, 內容) 的檔案，自動建立上層目錄
+    fn write_files(root: &Path, files: &[(&str, &str)]) {
+        for (relative, content) in files {
+            let path = root.join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+
+    fn scan_options(config: &QprConfig) -> ScanOptions {
+        ScanOptions {
+            extensions: ExtensionSet::new(CODE_FILE_EXTENSIONS, &config.extensions).unwrap(),
+            hash_files: config.hash_files,
+            max_depth: config.max_depth,
+            exclude_tests: config.exclude_tests,
+            sort_by: config.sort_by,
+        }
+    }
+
+    // 與 --backend mock --skip-folders-analysis 相同的設定，快取寫在專案以外的暫存目錄
+    pub(crate) fn test_project(
+        files: &[(&str, &str)],
+        configure: impl FnOnce(&mut QprConfig),
+        mock: impl FnOnce(MockLlmBackend) -> MockLlmBackend,
+    ) -> TestProject {
+        let root = TempDir::new().unwrap();
+        write_files(root.path(), files);
+        let cache_dir = TempDir::new().unwrap();
+        let mut config = QprConfig {
+            project_path: root.path().to_string_lossy().to_string(),
+            cache_file: cache_dir.path().join("cache.json").to_string_lossy().to_string(),
+            backend: BackendKind::Mock,
+            skip_folders_analysis: true,
+            ..QprConfig::default()
+        };
+        configure(&mut config);
+        let metrics = Arc::new(Metrics::default());
+        let mock = Arc::new(mock(MockLlmBackend::new(&config, metrics.clone())));
+        let scan_options = Arc::new(scan_options(&config));
+        let semaphore = mock.semaphore();
+        let state = load_project(config, scan_options, mock.clone(), semaphore, metrics, None, Shutdown::default());
+        TestProject {
+            root,
+            _cache_dir: cache_dir,
+            state,
+            mock,
+        }
+    }
+
+    // 以 JSON 描述的目錄樹（與 /filtered-tree 的格式相同），未列出的欄位使用預設值
+    fn directory(value: serde_json::Value) -> Directory {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn summary_of<'a>(tree: &'a Directory, path: &str) -> Option<&'a str> {
+        tree.find_file_by_path(path).unwrap().summary.as_deref()
+    }
+
+    // 選定整個專案並摘要所有檔案
+    pub(crate) async fn summarize_all(state: &AppState) {
+        select_folders_without_analysis(state).await;
+        let files = begin_summarization(state).await.unwrap();
+        run_summary_pipeline(state, files).await;
+    }
+
+    async fn get_json(state: &AppState, path: &str) -> serde_json::Value {
+        serde_json::from_slice(&get_body(state, path).await).unwrap()
+    }
+
+    async fn get_body(state: &AppState, path: &str) -> Vec<u8> {
+        let response = get(state, path).await;
+        assert_eq!(response.status(), 200, "GET {}", path);
+        response.body().to_vec()
+    }
+
+    async fn get(state: &AppState, path: &str) -> warp::http::Response<warp::hyper::body::Bytes> {
+        let registry = ProjectRegistry {
+            projects: vec![ProjectEntry {
+                id: "test".to_string(),
+                state: state.clone(),
+            }],
+        };
+        warp::test::request().path(path).reply(&build_routes(&registry)).await
+    }
+
+    // 目錄樹 JSON 中依名稱往下找子目錄，最後一段為檔案名稱
+    fn tree_file<'a>(tree: &'a serde_json::Value, names: &[&str]) -> &'a serde_json::Value {
+        let (file, dirs) = names.split_last().unwrap();
+        let dir = dirs.iter().fold(tree, |dir, name| {
+            dir["subdirs"].as_array().unwrap().iter().find(|d| d["name"] == *name).unwrap()
+        });
+        dir["files"].as_array().unwrap().iter().find(|f| f["name"] == *file).unwrap()
+    }
+
+    #[tokio::test]
+    async fn mock_backend_summarizes_project_through_routes() {
+        let project = test_project(
+            &[
+                ("src/main.rs", "fn main() { util::helper(); }"),
+                ("src/util/helper.rs", "pub fn helper() -> u32 { 42 }"),
+                ("assets/logo.png", "not code"),
+            ],
+            |_| {},
+            |mock| mock.with_response("src/main.rs", "程式進入點，呼叫 util::helper 後結束。"),
+        );
+        summarize_all(&project.state).await;
+
+        let progress = get_json(&project.state, "/progress").await;
+        assert_eq!(progress["completed_files"], 2);
+        assert_eq!(progress["total_files"], 2);
+        let main_path = project.path("src/main.rs");
+        let helper_path = project.path("src/util/helper.rs");
+        assert_eq!(progress["summaries"][&main_path], "程式進入點，呼叫 util::helper 後結束。");
+        let helper_summary = progress["summaries"][&helper_path].as_str().unwrap();
+        assert!(helper_summary.starts_with("[mock] "), "{}", helper_summary);
+
+        // 摘要寫入目錄樹中對應的檔案
+        let tree = get_json(&project.state, "/filtered-tree").await;
+        assert_eq!(tree_file(&tree, &["src", "main.rs"])["summary"], "程式進入點，呼叫 util::helper 後結束。");
+        assert_eq!(tree_file(&tree, &["src", "util", "helper.rs"])["summary"], helper_summary);
+
+        // 每個程式碼檔案各呼叫一次，提示語包含檔案內容；非程式碼檔案不送出
+        let calls = project.mock.calls();
+        assert_eq!(calls.len(), 2);
+        assert!(calls.iter().all(|call| call.kind == "summarize_file"));
+        assert!(calls.iter().any(|call| call.prompt.contains("pub fn helper() -> u32 { 42 }")));
+        assert!(calls.iter().all(|call| !call.prompt.contains("not code")));
+
+        let flat = get_json(&project.state, "/api/file-tree/flat").await;
+        let paths: Vec<&str> = flat.as_array().unwrap().iter().map(|f| f["path"].as_str().unwrap()).collect();
+        assert_eq!(paths, vec![main_path.as_str(), helper_path.as_str()]);
+    }
+
+    #[tokio::test]
+    async fn concurrency_limits_in_flight_requests() {
+        let files: Vec<(String, String)> = (0..12)
+            .map(|i| (format!("src/file{}.rs", i), format!("fn file{}() {{}}", i)))
+            .collect();
+        let files: Vec<(&str, &str)> = files.iter().map(|(path, content)| (path.as_str(), content.as_str())).collect();
+        for concurrency in [1, 3] {
+            let project = test_project(
+                &files,
+                |config| {
+                    config.concurrency = concurrency;
+                    config.mock_delay_ms = 50;
+                },
+                |mock| mock,
+            );
+            summarize_all(&project.state).await;
+
+            assert_eq!(project.mock.calls().len(), 12);
+            // 佇列中的檔案多於上限，仍只有 concurrency 個請求同時進行，且確實用滿上限
+            assert_eq!(project.mock.peak_in_flight(), concurrency as usize, "--concurrency {}", concurrency);
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_backend_is_deterministic() {
+        let project = test_project(
+            &[("a.rs", "fn same() {}"), ("b.rs", "fn same() {}"), ("c.rs", "fn other() {}")],
+            |_| {},
+            |mock| mock,
+        );
+        summarize_all(&project.state).await;
+        let summaries = project.state.progress.read().await.summaries.clone();
+        assert_eq!(summaries[&project.path("a.rs")], summaries[&project.path("b.rs")]);
+        assert_ne!(summaries[&project.path("a.rs")], summaries[&project.path("c.rs")]);
+    }
+
+    #[tokio::test]
+    async fn mock_backend_failure_is_retried() {
+        let project = test_project(
+            &[("src/lib.rs", "pub fn lib() {}")],
+            |config| config.mock_fail_on_call = Some(1),
+            |mock| mock,
+        );
+        summarize_all(&project.state).await;
+
+        // 第一次呼叫回傳 503，重試後成功
+        let calls = project.mock.calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].prompt, calls[1].prompt);
+        let progress = project.state.progress.read().await;
+        assert!(progress.failed_files.is_empty());
+        assert!(progress.summaries[&project.path("src/lib.rs")].starts_with("[mock] "));
+    }
+
+    #[tokio::test]
+    async fn mock_backend_failure_without_retries_marks_file_failed() {
+        let project = test_project(
+            &[("src/lib.rs", "pub fn lib() {}")],
+            |config| {
+                config.mock_fail_on_call = Some(1);
+                config.max_retries = 0;
+            },
+            |mock| mock,
+        );
+        summarize_all(&project.state).await;
+
+        assert_eq!(project.mock.calls().len(), 1);
+        let progress = get_json(&project.state, "/progress").await;
+        assert_eq!(progress["failed_files"], serde_json::json!([project.path("src/lib.rs")]));
+    }
+
+    #[tokio::test]
+    async fn latin1_file_is_marked_encoding_lossy() {
+        let project = test_project(&[("src/latin1.rs", ""), ("src/utf8.rs", "// café\nfn utf8() {}")], |_| {}, |mock| mock);
+        // 0xE9 為 Latin-1 的 é，不是有效的 UTF-8
+        fs::write(project.path("src/latin1.rs"), b"// caf\xe9\nfn latin1() {}").unwrap();
+        summarize_all(&project.state).await;
+
+        let latin1_path = project.path("src/latin1.rs");
+        let progress = get_json(&project.state, "/progress").await;
+        assert_eq!(progress["encoding_lossy_files"], serde_json::json!([latin1_path]));
+        assert!(project.mock.calls().iter().any(|call| call.prompt.contains("caf\u{FFFD}")));
+
+        let tree = get_json(&project.state, "/filtered-tree").await;
+        assert_eq!(tree_file(&tree, &["src", "latin1.rs"])["encoding_lossy"], true);
+        assert_ne!(tree_file(&tree, &["src", "utf8.rs"])["encoding_lossy"], true);
+    }
+
+    #[tokio::test]
+    async fn unreadable_file_is_marked_failed_without_summarizing() {
+        let project = test_project(&[("src/gone.rs", "fn gone() {}"), ("src/kept.rs", "fn kept() {}")], |_| {}, |mock| mock);
+        select_folders_without_analysis(&project.state).await;
+        let files = begin_summarization(&project.state).await.unwrap();
+        // 已加入摘要佇列、尚未讀取內容前被刪除
+        fs::remove_file(project.path("src/gone.rs")).unwrap();
+        run_summary_pipeline(&project.state, files).await;
+
+        let gone_path = project.path("src/gone.rs");
+        let calls = project.mock.calls();
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].prompt.contains("fn kept() {}"));
+        let progress = project.state.progress.read().await;
+        assert_eq!(progress.failed_files, vec![gone_path.clone()]);
+        assert_eq!(progress.completed_files, 2);
+        assert!(!progress.encoding_lossy_files.contains(&gone_path));
+        assert!(!progress.content_hashes.contains_key(&gone_path));
+    }
+
+    #[tokio::test]
+    async fn clear_summary_cache_empties_cache_file_and_keeps_annotations() {
+        let project = test_project(&[("src/lib.rs", "pub fn lib() {}")], |_| {}, |mock| mock);
+        summarize_all(&project.state).await;
+        let lib_path = project.path("src/lib.rs");
+        let note = Annotation {
+            annotation: "note".to_string(),
+            mode: AnnotationMode::Append,
+        };
+        project.state.progress.write().await.annotations.insert(lib_path.clone(), note);
+        save_summary_cache(&project.state).await.unwrap();
+        let cache_path = Path::new(&project.state.config.cache_file);
+        assert!(SummaryCache::load(cache_path).summaries.contains_key(&lib_path));
+
+        assert_eq!(clear_summary_cache(&project.state).await.unwrap(), 1);
+        let saved = SummaryCache::load(cache_path);
+        assert!(saved.summaries.is_empty());
+        assert!(saved.hashes.is_empty());
+        assert_eq!(saved.annotations[&lib_path].annotation, "note");
+        // 寫檔前已釋放鎖，之後可立即再次取得
+        let progress = project.state.progress.try_read().unwrap();
+        assert!(progress.summaries.is_empty());
+        assert_eq!(progress.completed_files, 0);
+    }
+
+    #[tokio::test]
+    async fn rescan_reports_added_changed_and_removed_files() {
+        let project = test_project(
+            &[("src/kept.rs", "fn kept() {}"), ("src/edited.rs", "fn edited() {}"), ("src/gone.rs", "fn gone() {}")],
+            |config| config.hash_files = true,
+            |mock| mock,
+        );
+        summarize_all(&project.state).await;
+        write_files(project.root.path(), &[("src/edited.rs", "fn edited() { 1 }"), ("src/added.rs", "fn added() {}")]);
+        fs::remove_file(project.path("src/gone.rs")).unwrap();
+
+        let result = rescan_project(project.state.clone()).await.unwrap();
+        assert_eq!((result.new_files, result.changed_files, result.removed_files), (1, 1, 1));
+        let tree = project.state.project.read().await;
+        assert!(summary_of(&tree, &project.path("src/kept.rs")).is_some());
+        assert!(tree.find_file_by_path(&project.path("src/added.rs")).is_some());
+        assert!(tree.find_file_by_path(&project.path("src/gone.rs")).is_none());
+        drop(tree);
+        assert_eq!(project.state.progress.read().await.total_files, 3);
+    }
+
+    // 五層深的目錄樹，各層都有名稱前綴相同的兄弟目錄（a 與 a_extra、ab 與 ab_extra ...）
+    fn deep_tree() -> Directory {
+        directory(serde_json::json!({
+            "name": "root",
+            "path": "/root",
+            "files": [{ "name": "a.rs" }],
+            "subdirs": [
+                { "name": "a", "path": "/root/a", "files": [{ "name": "a.rs" }], "subdirs": [
+                    { "name": "ab", "path": "/root/a/ab", "files": [{ "name": "ab.rs" }], "subdirs": [
+                        { "name": "abc", "path": "/root/a/ab/abc", "subdirs": [
+                            { "name": "abcd", "path": "/root/a/ab/abc/abcd", "subdirs": [
+                                { "name": "abcde", "path": "/root/a/ab/abc/abcd/abcde", "files": [{ "name": "deep.rs" }] }
+                            ] },
+                            { "name": "abcd_extra", "path": "/root/a/ab/abc/abcd_extra", "files": [{ "name": "deep.rs" }] }
+                        ] }
+                    ] },
+                    { "name": "ab_extra", "path": "/root/a/ab_extra", "files": [{ "name": "ab.rs" }] }
+                ] },
+                { "name": "a_extra", "path": "/root/a_extra", "files": [{ "name": "a.rs" }] }
+            ]
+        }))
+    }
+
+    #[test]
+    fn find_dir_by_path_descends_each_level() {
+        let tree = deep_tree();
+        for path in [
+            "/root",
+            "/root/a",
+            "/root/a/ab",
+            "/root/a/ab/abc",
+            "/root/a/ab/abc/abcd",
+            "/root/a/ab/abc/abcd/abcde",
+            "/root/a_extra",
+            "/root/a/ab_extra",
+            "/root/a/ab/abc/abcd_extra",
+        ] {
+            assert_eq!(tree.find_dir_by_path(path).map(|dir| dir.path.as_str()), Some(path));
+        }
+        // 多餘的分隔符號與 `.` 元件略過；系統原生分隔符號與 `/` 相同處理
+        let native = ["", "root", "a", "ab", "abc"].join(std::path::MAIN_SEPARATOR_STR);
+        assert_eq!(tree.find_dir_by_path(&native).unwrap().path, "/root/a/ab/abc");
+        assert_eq!(tree.find_dir_by_path("/root//a/./ab/").unwrap().path, "/root/a/ab");
+    }
+
+    #[test]
+    fn find_dir_by_path_does_not_match_shared_prefixes() {
+        let tree = deep_tree();
+        for path in ["/ro", "/root_extra", "/root/a_", "/root/a/abc", "/root/a/ab/abcd", "/root/a/ab/abc/abcde", "/root/a/ab.rs"] {
+            assert!(tree.find_dir_by_path(path).is_none(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn find_file_by_path_matches_full_path() {
+        let mut tree = deep_tree();
+        tree.update_file_summary("/root/a/ab/abc/abcd/abcde/deep.rs", "deepest".to_string());
+        tree.update_file_summary("/root/a/ab_extra/ab.rs", "extra".to_string());
+
+        assert_eq!(summary_of(&tree, "/root/a/ab/abc/abcd/abcde/deep.rs"), Some("deepest"));
+        assert_eq!(summary_of(&tree, "/root/a/ab/abc/abcd_extra/deep.rs"), None);
+        assert_eq!(summary_of(&tree, "/root/a/ab_extra/ab.rs"), Some("extra"));
+        assert_eq!(summary_of(&tree, "/root/a/ab/ab.rs"), None);
+        assert_eq!(tree.find_file_by_path("/root/a.rs").unwrap().name, "a.rs");
+
+        // 目錄、不存在的檔案與其他目錄下的同名檔案都找不到
+        for path in ["/root/a/ab", "/root/a/deep.rs", "/root/a/ab/abc/deep.rs", "/root_extra/a.rs", "/root", ""] {
+            assert!(tree.find_file_by_path(path).is_none(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn find_dir_by_path_mut_modifies_matching_dir() {
+        let mut tree = deep_tree();
+        tree.find_dir_by_path_mut("/root/a/ab/abc/abcd").unwrap().files.push(FileInfo {
+            name: "added.rs".to_string(),
+            summary: Some("added".to_string()),
+            content_hash: None,
+            size_bytes: 0,
+            change_status: None,
+            encoding_lossy: false,
+            language: None,
+            quality: SummaryQuality::Ok,
+            modified_at: None,
+        });
+        assert_eq!(summary_of(&tree, "/root/a/ab/abc/abcd/added.rs"), Some("added"));
+        assert!(tree.find_file_by_path("/root/a/ab/abc/abcd_extra/added.rs").is_none());
+    }
+
+    // levels 層深的暫存目錄 l1/l2/.../l<levels>，每層各有一個 fileN.rs
+    fn nested_levels(levels: usize) -> TempDir {
+        let root = TempDir::new().unwrap();
+        let mut dir = root.path().to_path_buf();
+        for level in 1..=levels {
+            dir.push(format!("l{}", level));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(format!("file{}.rs", level)), "fn f() {}").unwrap();
+        }
+        root
+    }
+
+    fn level_path(root: &Path, level: usize) -> String {
+        (1..=level)
+            .fold(root.to_path_buf(), |dir, level| dir.join(format!("l{}", level)))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn scan_stops_at_max_depth_with_sentinel() {
+        let root = nested_levels(25);
+        let options = scan_options(&QprConfig::default());
+        assert_eq!(options.max_depth, 20);
+        let tree = Directory::from_path(root.path(), root.path(), true, &options);
+
+        // 第 20 層的檔案仍會掃描，但不再往下遞迴，並標記還有未掃描的子目錄
+        let level20 = tree.find_dir_by_path(&level_path(root.path(), 20)).unwrap();
+        assert!(level20.max_depth_reached);
+        assert!(level20.subdirs.is_empty());
+        assert_eq!(level20.files.len(), 1);
+        assert!(tree.find_dir_by_path(&level_path(root.path(), 21)).is_none());
+        for level in 1..20 {
+            assert!(!tree.find_dir_by_path(&level_path(root.path(), level)).unwrap().max_depth_reached, "l{}", level);
+        }
+        assert_eq!(tree.max_depth(), 20);
+        assert_eq!(tree.file_count(), 20);
+        assert_eq!(tree.max_depth_reached_count(), 1);
+
+        // 標記只出現在目錄樹輸出中第 20 層的底下（第 21 層的縮排）
+        let printed = tree.to_tree_string(false);
+        let sentinel = format!("{}└── {}", " ".repeat(4 * 20), MAX_DEPTH_MARKER);
+        assert_eq!(printed.lines().filter(|line| line.contains(MAX_DEPTH_MARKER)).collect::<Vec<_>>(), vec![sentinel]);
+    }
+
+    #[test]
+    fn scan_within_max_depth_has_no_sentinel() {
+        let root = nested_levels(20);
+        let tree = Directory::from_path(root.path(), root.path(), true, &scan_options(&QprConfig::default()));
+        assert_eq!(tree.max_depth(), 20);
+        assert_eq!(tree.file_count(), 20);
+        assert_eq!(tree.max_depth_reached_count(), 0);
+        assert!(!tree.to_tree_string(false).contains(MAX_DEPTH_MARKER));
+    }
+
+    // 依 paths 的順序寫入摘要、資料夾摘要、註記與耗時（內容只由路徑決定）
+    fn fill_progress(progress: &mut Progress, paths: &[&str]) {
+        for path in paths {
+            progress.summaries.insert(path.to_string(), format!("summary of {}", path));
+            progress.directory_summaries.insert(format!("{}/dir", path), "dir".to_string());
+            progress.annotations.insert(
+                path.to_string(),
+                Annotation {
+                    annotation: "note".to_string(),
+                    mode: AnnotationMode::Append,
+                },
+            );
+            progress.latency_ms.insert(path.to_string(), path.len() as u64);
+        }
+    }
+
+    #[tokio::test]
+    async fn progress_serialization_is_byte_identical() {
+        let project = test_project(&[], |_| {}, |mock| mock);
+        let empty = project.state.progress.read().await.clone();
+        let paths = ["/p/src/b.rs", "/p/src/B.rs", "/p/a.rs", "/p/src/a.rs", "/p/Z.rs", "/p/src_extra/a.rs"];
+
+        let mut progress = empty.clone();
+        fill_progress(&mut progress, &paths);
+        let first = serde_json::to_string(&progress).unwrap();
+        let second = serde_json::to_string(&progress).unwrap();
+        assert_eq!(first, second);
+
+        // 以不同順序加入相同內容，輸出仍相同
+        let mut reversed = empty.clone();
+        let reversed_paths: Vec<&str> = paths.iter().rev().copied().collect();
+        fill_progress(&mut reversed, &reversed_paths);
+        assert_eq!(serde_json::to_string(&reversed).unwrap(), first);
+
+        // summaries 的鍵依字典順序（逐位元組）輸出
+        let value: serde_json::Value = serde_json::from_str(&first).unwrap();
+        let keys: Vec<&str> = value["summaries"].as_object().unwrap().keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["/p/Z.rs", "/p/a.rs", "/p/src/B.rs", "/p/src/a.rs", "/p/src/b.rs", "/p/src_extra/a.rs"]);
+        let first_positions: Vec<usize> = keys.iter().map(|key| first.find(&format!("\"{}\":\"summary", key)).unwrap()).collect();
+        assert!(first_positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn scanned_tree_order_is_case_sensitive_and_stable() {
+        let root = TempDir::new().unwrap();
+        write_files(root.path(), &[("b.rs", ""), ("B.rs", ""), ("a.rs", ""), ("src/x.rs", ""), ("Src/x.rs", "")]);
+        let options = scan_options(&QprConfig::default());
+        let scan = || Directory::from_path(root.path(), root.path(), true, &options);
+
+        let tree = scan();
+        let files: Vec<&str> = tree.files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(files, vec!["B.rs", "a.rs", "b.rs"]);
+        let subdirs: Vec<&str> = tree.subdirs.iter().map(|dir| dir.name.as_str()).collect();
+        assert_eq!(subdirs, vec!["Src", "src"]);
+        assert_eq!(serde_json::to_string(&tree).unwrap(), serde_json::to_string(&scan()).unwrap());
+    }
+
+    fn file_names(dir: &Directory) -> Vec<&str> {
+        dir.files.iter().map(|file| file.name.as_str()).collect()
+    }
+
+    fn subdir_names(dir: &Directory) -> Vec<&str> {
+        dir.subdirs.iter().map(|subdir| subdir.name.as_str()).collect()
+    }
+
+    #[test]
+    fn merge_overlapping_trees_keeps_summarized_files() {
+        let mut left = directory(serde_json::json!({
+            "name": "left", "path": "/left",
+            "files": [{ "name": "a.rs", "summary": "left a" }, { "name": "b.rs" }],
+            "subdirs": [{ "name": "src", "path": "/left/src", "files": [{ "name": "lib.rs" }] }]
+        }));
+        let right = directory(serde_json::json!({
+            "name": "right", "path": "/right",
+            "files": [{ "name": "a.rs", "summary": "right a" }, { "name": "b.rs", "summary": "right b" }],
+            "subdirs": [{ "name": "src", "path": "/right/src", "files": [{ "name": "lib.rs", "summary": "right lib" }] }]
+        }));
+        left.merge(&right);
+
+        assert_eq!(left.name, "left + right");
+        assert_eq!(left.path, "/left");
+        assert_eq!(file_names(&left), vec!["a.rs", "b.rs"]);
+        assert_eq!(subdir_names(&left), vec!["src"]);
+        // 兩邊都有摘要時保留原本的，只有 other 有摘要時改用 other 的
+        assert_eq!(summary_of(&left, "/left/a.rs"), Some("left a"));
+        assert_eq!(summary_of(&left, "/left/b.rs"), Some("right b"));
+        assert_eq!(summary_of(&left, "/left/src/lib.rs"), Some("right lib"));
+    }
+
+    #[test]
+    fn merge_disjoint_trees_appends_other_entries() {
+        let mut left = directory(serde_json::json!({
+            "name": "web", "path": "/mono/web",
+            "files": [{ "name": "index.ts" }],
+            "subdirs": [{ "name": "pages", "path": "/mono/web/pages", "files": [{ "name": "home.ts" }] }]
+        }));
+        let right = directory(serde_json::json!({
+            "name": "api", "path": "/mono/api",
+            "files": [{ "name": "main.go", "summary": "api main" }],
+            "subdirs": [{ "name": "handlers", "path": "/mono/api/handlers", "files": [{ "name": "user.go" }] }]
+        }));
+        left.merge(&right);
+
+        assert_eq!(left.name, "web + api");
+        assert_eq!(file_names(&left), vec!["index.ts", "main.go"]);
+        assert_eq!(subdir_names(&left), vec!["pages", "handlers"]);
+        assert_eq!(left.file_count(), 4);
+        assert_eq!(summary_of(&left, "/mono/web/main.go"), Some("api main"));
+        // other 獨有的子目錄原封不動附加
+        assert_eq!(left.subdirs[1].path, "/mono/api/handlers");
+        assert_eq!(file_names(&left.subdirs[1]), vec!["user.go"]);
+    }
+
+    #[test]
+    fn merge_partially_overlapping_trees_recurses_into_shared_dirs() {
+        let mut left = directory(serde_json::json!({
+            "name": "one", "path": "/one",
+            "subdirs": [
+                { "name": "src", "path": "/one/src", "files": [{ "name": "shared.rs" }, { "name": "one.rs" }], "subdirs": [
+                    { "name": "util", "path": "/one/src/util", "files": [{ "name": "fmt.rs", "summary": "one fmt" }] }
+                ] },
+                { "name": "docs", "path": "/one/docs", "files": [{ "name": "gen.py" }] }
+            ]
+        }));
+        let right = directory(serde_json::json!({
+            "name": "two", "path": "/two",
+            "subdirs": [
+                { "name": "src", "path": "/two/src", "files": [{ "name": "shared.rs", "summary": "two shared" }, { "name": "two.rs" }], "subdirs": [
+                    { "name": "util", "path": "/two/src/util", "files": [{ "name": "fmt.rs" }, { "name": "io.rs" }] },
+                    { "name": "net", "path": "/two/src/net", "max_depth_reached": true }
+                ] },
+                { "name": "tests", "path": "/two/tests", "files": [{ "name": "it.rs" }] }
+            ]
+        }));
+        left.merge(&right);
+
+        assert_eq!(left.name, "one + two");
+        assert_eq!(subdir_names(&left), vec!["src", "docs", "tests"]);
+        let src = left.find_dir_by_path("/one/src").unwrap();
+        assert_eq!(file_names(src), vec!["shared.rs", "one.rs", "two.rs"]);
+        assert_eq!(subdir_names(src), vec!["util", "net"]);
+        assert_eq!(file_names(&src.subdirs[0]), vec!["fmt.rs", "io.rs"]);
+        assert_eq!(summary_of(&left, "/one/src/shared.rs"), Some("two shared"));
+        assert_eq!(summary_of(&left, "/one/src/util/fmt.rs"), Some("one fmt"));
+        assert_eq!(left.max_depth_reached_count(), 1);
+        assert_eq!(left.file_count(), 7);
+    }
+
+    fn report_with_file(name: &str) -> Directory {
+        directory(serde_json::json!({
+            "name": "proj",
+            "path": "/x/proj",
+            "files": [{ "name": name }],
+            "subdirs": [{ "name": "src", "path": "/x/proj/src", "files": [{ "name": "lib.rs" }] }]
+        }))
+    }
+
+    #[test]
+    fn validate_accepts_scanned_tree() {
+        assert_eq!(report_with_file("main.rs").validate(), Ok(()));
+        assert_eq!(deep_tree().validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_file_names_that_leave_their_directory() {
+        for name in ["/tmp/qprt/outside.txt", "..", "../secret.txt", "a/b.rs", "a\\b.rs", "x..y", ".", ""] {
+            assert!(report_with_file(name).validate().is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn validate_rejects_dir_names_and_paths_that_leave_the_root() {
+        let tree = |root: &str, name: &str, path: &str| {
+            directory(serde_json::json!({
+                "name": "proj", "path": root,
+                "subdirs": [{ "name": name, "path": path }]
+            }))
+        };
+        assert!(tree("/x/proj", "src", "/x/proj/src").validate().is_ok());
+        for (root, name, path) in [
+            ("/x/proj", "..", "/x/proj/.."),
+            ("/x/proj", "src", "/x/proj/../src"),
+            ("/x/proj", "src", "/x/proj/a/src"),
+            ("/x/proj", "a/src", "/x/proj/a/src"),
+            ("/x/proj", "src", "/etc/src"),
+            ("/x/../proj", "src", "/x/../proj/src"),
+        ] {
+            assert!(tree(root, name, path).validate().is_err(), "{} {} {}", root, name, path);
+        }
+    }
+
+    #[tokio::test]
+    async fn import_rejects_report_with_absolute_file_name() {
+        let project = test_project(&[("src/lib.rs", "pub fn lib() {}")], |_| {}, |mock| mock);
+        let result = import_report(&project.state, report_with_file("/tmp/qprt/outside.txt")).await;
+        assert!(matches!(result, Err(QprError::InvalidRequest(_))));
+        // 目錄樹維持原本掃描的內容
+        assert!(project.state.project.read().await.find_file_by_path("/tmp/qprt/outside.txt").is_none());
+    }
+
+    // 專案內的 src/link.rs 是指向專案外檔案的符號連結，掃描時會被當成一般檔案加入目錄樹
+    #[cfg(unix)]
+    fn project_with_escaping_symlink() -> (TestProject, TempDir) {
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("secret.rs"), "SECRET_TOKEN=hunter2\nuse crate::inside;").unwrap();
+        let project = test_project(&[("src/inside.rs", "pub fn inside() {} // SECRET_TOKEN")], |_| {}, |mock| mock);
+        std::os::unix::fs::symlink(outside.path().join("secret.rs"), project.path("src/link.rs")).unwrap();
+        (project, outside)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cross_references_skip_files_outside_project() {
+        let (project, _outside) = project_with_escaping_symlink();
+        summarize_all(&project.state).await;
+        assert!(project.state.project.read().await.find_file_by_path(&project.path("src/link.rs")).is_some());
+
+        let references = get_json(&project.state, "/api/cross-references?name=SECRET_TOKEN").await;
+        let references = references["references"].as_array().unwrap();
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0]["file_path"], project.path("src/inside.rs"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn dependency_graph_skips_files_outside_project() {
+        let (project, _outside) = project_with_escaping_symlink();
+        summarize_all(&project.state).await;
+
+        let graph = get_json(&project.state, "/api/dependency-graph").await;
+        let nodes: Vec<&str> = graph["nodes"].as_array().unwrap().iter().map(|node| node["id"].as_str().unwrap()).collect();
+        assert_eq!(nodes, vec!["src/inside.rs"]);
+        assert_eq!(graph["edges"], serde_json::json!([]));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn markdown_export_skips_files_outside_project() {
+        let (project, _outside) = project_with_escaping_symlink();
+        summarize_all(&project.state).await;
+
+        let markdown = String::from_utf8(get_body(&project.state, "/api/export/markdown").await).unwrap();
+        assert!(markdown.contains("pub fn inside() {} // SECRET_TOKEN"), "{}", markdown);
+        assert!(markdown.contains("### link.rs"), "{}", markdown);
+        assert!(!markdown.contains("hunter2"), "{}", markdown);
+    }
+
+    #[tokio::test]
+    async fn file_content_rejects_outside_paths_before_checking_existence() {
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("secret.rs"), "SECRET_TOKEN=hunter2").unwrap();
+        let project = test_project(&[("src/main.rs", "fn main() {}")], |_| {}, |mock| mock);
+        let escape = |name: &str| {
+            format!(
+                "/api/file-content/..%2F{}%2F{}",
+                outside.path().file_name().unwrap().to_string_lossy(),
+                name
+            )
+        };
+
+        assert_eq!(get_body(&project.state, "/api/file-content/src/main.rs").await, b"fn main() {}");
+        assert_eq!(get(&project.state, "/api/file-content/src/missing.rs").await.status(), 404);
+        assert_eq!(get(&project.state, "/api/file-content/src").await.status(), 404);
+        // 專案外的檔案存在與否都是 403
+        assert_eq!(get(&project.state, &escape("secret.rs")).await.status(), 403);
+        assert_eq!(get(&project.state, &escape("missing.rs")).await.status(), 403);
+    }
+
+    // 在 dir 下建立 src、src2 兩個名稱前綴相同的目錄，回傳已正規化的 dir
+    fn sibling_prefix_dirs() -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        write_files(dir.path(), &[("src/lib.rs", "pub fn lib() {}"), ("src2/secret.rs", "SECRET_TOKEN")]);
+        let base = fs::canonicalize(dir.path()).unwrap();
+        (dir, base)
+    }
+
+    #[test]
+    fn resolve_project_path_rejects_sibling_with_shared_prefix() {
+        let (_dir, base) = sibling_prefix_dirs();
+        let root = base.join("src").to_string_lossy().to_string();
+        let resolve = |path: PathBuf| resolve_project_path(&root, &path.to_string_lossy());
+
+        assert_eq!(resolve(base.join("src/lib.rs")).unwrap(), base.join("src/lib.rs"));
+        assert!(matches!(resolve(base.join("src2/secret.rs")), Err(QprError::PathNotAllowed(_))));
+        assert!(matches!(resolve(base.join("src/../src2/secret.rs")), Err(QprError::PathNotAllowed(_))));
+        assert!(matches!(resolve(base.join("src2")), Err(QprError::PathNotAllowed(_))));
+        assert!(matches!(resolve(base.clone()), Err(QprError::PathNotAllowed(_))));
+        // 不存在的路徑無法正規化，回傳讀取錯誤
+        assert!(matches!(resolve(base.join("src/missing.rs")), Err(QprError::IoError(_))));
+    }
+
+    #[test]
+    fn resolve_project_path_ignores_trailing_separators() {
+        let (_dir, base) = sibling_prefix_dirs();
+        let root = format!("{}/", base.join("src").display());
+        let resolve = |path: String| resolve_project_path(&root, &path);
+
+        assert_eq!(resolve(format!("{}lib.rs", root)).unwrap(), base.join("src/lib.rs"));
+        assert_eq!(resolve(root.clone()).unwrap(), base.join("src"));
+        assert_eq!(resolve(format!("{}//", base.join("src").display())).unwrap(), base.join("src"));
+        let root_without_separator = base.join("src").to_string_lossy().to_string();
+        assert!(resolve_project_path(&root_without_separator, &root).is_ok());
+        // 根目錄結尾的分隔符號不會讓名稱前綴相同的兄弟目錄通過檢查
+        assert!(matches!(resolve(format!("{}/", base.join("src2").display())), Err(QprError::PathNotAllowed(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_project_path_rejects_symlinks_leaving_the_root() {
+        let (_dir, base) = sibling_prefix_dirs();
+        std::os::unix::fs::symlink(base.join("src2/secret.rs"), base.join("src/link.rs")).unwrap();
+        std::os::unix::fs::symlink(base.join("src2"), base.join("src/linked_dir")).unwrap();
+        std::os::unix::fs::symlink("lib.rs", base.join("src/alias.rs")).unwrap();
+        let root = base.join("src").to_string_lossy().to_string();
+        let resolve = |relative: &str| resolve_project_path(&root, &base.join(relative).to_string_lossy());
+
+        assert!(matches!(resolve("src/link.rs"), Err(QprError::PathNotAllowed(_))));
+        assert!(matches!(resolve("src/linked_dir/secret.rs"), Err(QprError::PathNotAllowed(_))));
+        // 指向專案內的符號連結解析為實際的檔案
+        assert_eq!(resolve("src/alias.rs").unwrap(), base.join("src/lib.rs"));
+    }
+
+    #[test]
+    fn update_file_summary_does_not_match_sibling_with_shared_prefix() {
+        // /root/src_extra 以 /root/src 開頭，兩個目錄中都有 b.rs
+        let mut tree = directory(serde_json::json!({
+            "name": "root",
+            "path": "/root",
+            "subdirs": [
+                { "name": "src", "path": "/root/src", "files": [{ "name": "a.rs" }, { "name": "b.rs" }] },
+                { "name": "src_extra", "path": "/root/src_extra", "files": [{ "name": "b.rs" }] }
+            ]
+        }));
+        tree.update_file_summary("/root/src/a.rs", "summary of src/a.rs".to_string());
+        tree.update_file_summary("/root/src_extra/b.rs", "summary of src_extra/b.rs".to_string());
+
+        assert_eq!(summary_of(&tree, "/root/src/a.rs"), Some("summary of src/a.rs"));
+        assert_eq!(summary_of(&tree, "/root/src_extra/b.rs"), Some("summary of src_extra/b.rs"));
+        assert_eq!(summary_of(&tree, "/root/src/b.rs"), None);
+    }
+
+    // 掃描 files 建立的暫存專案（不含檔案，與選定資料夾前相同），收集 folders 中的檔案，回傳排序後的相對路徑
+    fn collect_selected(files: &[(&str, &str)], folders: &[&str]) -> Vec<String> {
+        let root = TempDir::new().unwrap();
+        write_files(root.path(), files);
+        let options = scan_options(&QprConfig::default());
+        let mut tree = Directory::from_path(root.path(), root.path(), false, &options);
+        let folders: Vec<String> = folders.iter().map(|folder| folder.to_string()).collect();
+        let mut collected: Vec<String> = tree
+            .collect_files_to_summarize(root.path(), &folders, &options, None)
+            .into_iter()
+            .map(|(path, _, _)| relative_path(root.path(), Path::new(&path)))
+            .collect();
+        collected.sort();
+        collected
+    }
+
+    // src 同時出現在根目錄、其他資料夾之下與自身之下
+    const SAME_NAME_FIXTURE: &[(&str, &str)] = &[
+        ("src/main.rs", "fn main() {}"),
+        ("src/src/nested.rs", "fn nested() {}"),
+        ("tools/src/tool.rs", "fn tool() {}"),
+        ("third_party/lib/src/vendored.rs", "fn vendored() {}"),
+    ];
+
+    #[test]
+    fn collect_files_matches_top_level_folder_only() {
+        assert_eq!(collect_selected(SAME_NAME_FIXTURE, &["src"]), vec!["src/main.rs", "src/src/nested.rs"]);
+    }
+
+    #[test]
+    fn collect_files_matches_nested_folder_by_relative_path() {
+        assert_eq!(collect_selected(SAME_NAME_FIXTURE, &["third_party/lib/src"]), vec!["third_party/lib/src/vendored.rs"]);
+        assert_eq!(collect_selected(SAME_NAME_FIXTURE, &["src/src"]), vec!["src/src/nested.rs"]);
+        assert_eq!(
+            collect_selected(SAME_NAME_FIXTURE, &["tools/src", "third_party/lib/src"]),
+            vec!["third_party/lib/src/vendored.rs", "tools/src/tool.rs"]
+        );
+        // 只有名稱相同、路徑不符時不選入
+        assert!(collect_selected(SAME_NAME_FIXTURE, &["lib/src"]).is_empty());
+    }
+
+    #[test]
+    fn folder_keys_are_normalized_relative_paths() {
+        let root = Path::new("/proj");
+        assert_eq!(normalize_folder_key(root, "third_party/lib/src"), "third_party/lib/src");
+        assert_eq!(normalize_folder_key(root, "./Third_Party\\Lib\\src/"), "third_party/lib/src");
+        assert_eq!(normalize_folder_key(root, "/proj/tools/src"), "tools/src");
+        assert_eq!(normalize_folder_key(root, "/proj"), ".");
+        assert_eq!(normalize_folder_key(root, " ./ "), ".");
+    }
 }
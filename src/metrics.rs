@@ -0,0 +1,94 @@
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+// ===========================
+// Prometheus 監控指標
+// ===========================
+
+// API 延遲直方圖的上界（秒），最後另有 +Inf
+const LATENCY_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+// 以原子計數器記錄，摘要任務更新時不需要取得鎖
+#[derive(Debug, Default)]
+pub struct Metrics {
+    files_completed: AtomicU64,
+    files_failed: AtomicU64,
+    api_requests: AtomicU64,
+    // 各上界的請求數（非累積），輸出時再累加
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_sum_micros: AtomicU64,
+}
+
+impl Metrics {
+    // 記錄一個檔案的摘要任務完成，failed 表示摘要生成失敗
+    pub fn record_file(&self, failed: bool) {
+        self.files_completed.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.files_failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // 執行一次 API 請求並記錄次數與延遲（不論成功或失敗）
+    pub async fn time_request<T>(&self, request: impl Future<Output = T>) -> T {
+        let started = Instant::now();
+        let result = request.await;
+        let elapsed = started.elapsed();
+
+        self.api_requests.fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        let seconds = elapsed.as_secs_f64();
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.latency_buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    // 輸出 Prometheus 文字格式；files_total 為所有專案選定的檔案數合計
+    pub fn render(&self, backend: &str, tokens_used: u64, files_total: usize) -> String {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let mut output = String::new();
+
+        let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} {}", name, kind);
+            let _ = writeln!(output, "{} {}", name, value);
+        };
+        metric("qpr_files_total", "gauge", "Files selected for summarization.", files_total as u64);
+        metric("qpr_files_completed", "counter", "Summary tasks completed.", load(&self.files_completed));
+        metric("qpr_files_failed", "counter", "Summary tasks that failed.", load(&self.files_failed));
+        metric("qpr_tokens_used_total", "counter", "Tokens used by all LLM requests.", tokens_used);
+
+        let requests = load(&self.api_requests);
+        let _ = writeln!(output, "# HELP qpr_api_requests_total LLM API requests sent.");
+        let _ = writeln!(output, "# TYPE qpr_api_requests_total counter");
+        let _ = writeln!(output, "qpr_api_requests_total{{backend=\"{}\"}} {}", backend, requests);
+
+        let _ = writeln!(output, "# HELP qpr_api_latency_seconds LLM API request latency.");
+        let _ = writeln!(output, "# TYPE qpr_api_latency_seconds histogram");
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&self.latency_buckets) {
+            cumulative += load(count);
+            let _ = writeln!(
+                output,
+                "qpr_api_latency_seconds_bucket{{backend=\"{}\",le=\"{}\"}} {}",
+                backend, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            output,
+            "qpr_api_latency_seconds_bucket{{backend=\"{}\",le=\"+Inf\"}} {}",
+            backend, requests
+        );
+        let _ = writeln!(
+            output,
+            "qpr_api_latency_seconds_sum{{backend=\"{}\"}} {}",
+            backend,
+            load(&self.latency_sum_micros) as f64 / 1_000_000.0
+        );
+        let _ = writeln!(output, "qpr_api_latency_seconds_count{{backend=\"{}\"}} {}", backend, requests);
+        output
+    }
+}
@@ -0,0 +1,128 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+
+// ===========================
+// 終端機進度條（--no-progress-bar 停用）
+// ===========================
+
+// 例：[██████░░░░] 60/100 files • ETA: 2m15s • current: src/main.rs
+const FILES_TEMPLATE: &str = "[{bar:10}] {pos}/{len} files • ETA: {eta} • current: {msg}";
+// 分段摘要的大型檔案：每完成一段前進一格
+const CHUNKS_TEMPLATE: &str = "  [{bar:10}] {pos}/{len} chunks • {msg}";
+const PROGRESS_CHARS: &str = "█░";
+
+// 摘要進度：第一條為檔案數，分段摘要中的檔案各自再顯示一條段數
+#[derive(Clone)]
+pub struct SummaryProgress {
+    multi: MultiProgress,
+    files: ProgressBar,
+    // 訊息中的檔案路徑以專案目錄為起點
+    project_root: PathBuf,
+}
+
+impl SummaryProgress {
+    // 多個專案共用同一個 MultiProgress，進度條才不會互相覆蓋；
+    // 開始摘要前不顯示，避免干擾命令列的資料夾選擇
+    pub fn new(multi: &MultiProgress, project_root: &str) -> Self {
+        let files = ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::hidden()).with_style(style(FILES_TEMPLATE));
+        SummaryProgress {
+            multi: multi.clone(),
+            files,
+            project_root: PathBuf::from(project_root),
+        }
+    }
+
+    // 進度條繪製在標準錯誤輸出，不與標準輸出的日誌混在同一個串流
+    pub fn multi() -> MultiProgress {
+        MultiProgress::with_draw_target(ProgressDrawTarget::stderr())
+    }
+
+    // 開始一輪摘要（begin_summarization 或 /api/batch-summarize），重新計算檔案數與剩餘時間
+    pub fn start(&self, total: usize) {
+        self.files.reset();
+        self.files.set_length(total as u64);
+        self.files.set_message("");
+        if self.files.is_hidden() {
+            self.multi.add(self.files.clone());
+        }
+        self.files.tick();
+    }
+
+    // 一個檔案處理完成（包含快取命中與略過的檔案）
+    pub fn file_done(&self, file_path: &str) {
+        self.files.set_message(self.relative(file_path));
+        self.files.inc(1);
+    }
+
+    // 摘要結束後清除進度條，之後的日誌（例如專案總覽）才不會接在進度條後面
+    pub fn finish(&self) {
+        self.files.finish_and_clear();
+    }
+
+    // 在 future 執行期間，LLM 後端分段摘要時會為 file_path 顯示段數進度條
+    pub async fn scope<F: Future>(&self, file_path: String, future: F) -> F::Output {
+        CURRENT_FILE.scope((self.clone(), file_path), future).await
+    }
+
+    fn relative(&self, file_path: &str) -> String {
+        Path::new(file_path)
+            .strip_prefix(&self.project_root)
+            .unwrap_or(Path::new(file_path))
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+tokio::task_local! {
+    // 目前摘要中的檔案，讓 LLM 後端的分段迴圈不必知道進度條也能回報進度
+    static CURRENT_FILE: (SummaryProgress, String);
+}
+
+// 分段摘要的段數進度條，離開作用域時移除
+pub struct ChunkProgress(Option<(MultiProgress, ProgressBar)>);
+
+impl ChunkProgress {
+    pub fn inc(&self) {
+        if let Some((_, bar)) = &self.0 {
+            bar.inc(1);
+        }
+    }
+}
+
+impl Drop for ChunkProgress {
+    fn drop(&mut self) {
+        if let Some((multi, bar)) = self.0.take() {
+            bar.finish_and_clear();
+            multi.remove(&bar);
+        }
+    }
+}
+
+// 開始分段摘要；只有一段，或不在 scope 中（例如停用進度條或重新生成單一檔案）時不顯示
+pub fn start_chunks(total: usize) -> ChunkProgress {
+    if total <= 1 {
+        return ChunkProgress(None);
+    }
+    let bar = CURRENT_FILE
+        .try_with(|(progress, file_path)| {
+            let bar = progress
+                .multi
+                .add(ProgressBar::new(total as u64).with_style(style(CHUNKS_TEMPLATE)));
+            bar.set_message(progress.relative(file_path));
+            (progress.multi.clone(), bar)
+        })
+        .ok();
+    ChunkProgress(bar)
+}
+
+fn style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template)
+        .expect("進度條樣板無效")
+        .progress_chars(PROGRESS_CHARS)
+        .with_key("eta", |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+            let secs = state.eta().as_secs();
+            let _ = write!(w, "{}m{:02}s", secs / 60, secs % 60);
+        })
+}
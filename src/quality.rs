@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+// ===========================
+// 摘要品質檢查（/api/quality-report）
+// ===========================
+
+// 少於此字元數的摘要視為過短
+const MIN_SUMMARY_CHARS: usize = 20;
+
+// 整份摘要只有這些內容時視為空泛（比對前去除空白與句尾標點，英文不分大小寫）
+const GENERIC_SUMMARIES: &[&str] = &[
+    "這個檔案",
+    "此程式碼",
+    "這段程式碼",
+    "該檔案",
+    "程式碼",
+    "函式",
+    "this file",
+    "this code",
+];
+
+// 模型把提示語原樣輸出時會出現的片段
+const PROMPT_ECHO_PATTERNS: &[&str] = &["請為以下程式碼生成", "不超過100個字", "請用專業的軟體工程師風格"];
+
+// 模型回覆錯誤訊息或拒絕回答時會出現的片段（區分大小寫）
+const ERROR_PATTERNS: &[&str] = &["Error", "error:", "I cannot"];
+
+// 摘要的品質，依嚴重程度由低到高排列
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryQuality {
+    #[default]
+    Ok,
+    Generic,
+    TooShort,
+    LikelyError,
+}
+
+impl SummaryQuality {
+    pub fn is_ok(&self) -> bool {
+        *self == SummaryQuality::Ok
+    }
+}
+
+// 以簡單的規則找出可能需要重新生成或手動註記的摘要
+pub fn assess_summary_quality(summary: &str) -> SummaryQuality {
+    if ERROR_PATTERNS.iter().any(|pattern| summary.contains(pattern)) {
+        return SummaryQuality::LikelyError;
+    }
+    let normalized = summary
+        .trim()
+        .trim_end_matches(['。', '.', '!', '！'])
+        .trim()
+        .to_lowercase();
+    if GENERIC_SUMMARIES.contains(&normalized.as_str())
+        || PROMPT_ECHO_PATTERNS.iter().any(|pattern| summary.contains(pattern))
+    {
+        return SummaryQuality::Generic;
+    }
+    if summary.trim().chars().count() < MIN_SUMMARY_CHARS {
+        return SummaryQuality::TooShort;
+    }
+    SummaryQuality::Ok
+}
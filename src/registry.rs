@@ -0,0 +1,105 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::AppState;
+
+// ===========================
+// 多專案登錄表
+// ===========================
+
+// 一個已載入的專案，路由為 /projects/<id>/...
+#[derive(Clone)]
+pub struct ProjectEntry {
+    pub id: String,
+    pub state: AppState,
+}
+
+// GET /projects 回傳的專案資訊
+#[derive(Debug, Serialize)]
+pub struct ProjectInfo {
+    pub id: String,
+    pub path: String,
+}
+
+#[derive(Clone)]
+pub struct ProjectRegistry {
+    // 至少有一個專案，第一個專案同時對應未加前綴的舊路由
+    pub projects: Vec<ProjectEntry>,
+}
+
+impl ProjectRegistry {
+    pub fn first(&self) -> &ProjectEntry {
+        &self.projects[0]
+    }
+
+    pub fn infos(&self) -> Vec<ProjectInfo> {
+        self.projects
+            .iter()
+            .map(|entry| ProjectInfo {
+                id: entry.id.clone(),
+                path: entry.state.config.project_path.clone(),
+            })
+            .collect()
+    }
+}
+
+// 以目錄名稱產生專案 id（小寫英數字與 `-`），重複時加上 -2、-3…
+pub fn project_ids(paths: &[String]) -> Vec<String> {
+    let mut used = HashSet::new();
+    paths
+        .iter()
+        .map(|path| {
+            let base = slugify(&base_name(path));
+            let mut id = base.clone();
+            let mut suffix = 2;
+            while !used.insert(id.clone()) {
+                id = format!("{}-{}", base, suffix);
+                suffix += 1;
+            }
+            id
+        })
+        .collect()
+}
+
+// 目錄的名稱；`.` 之類沒有名稱的路徑先取絕對路徑
+fn base_name(path: &str) -> String {
+    let path = Path::new(path);
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .or_else(|| {
+            fs::canonicalize(path)
+                .ok()
+                .and_then(|path| path.file_name().map(|name| name.to_string_lossy().to_string()))
+        })
+        .unwrap_or_default()
+}
+
+fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "project".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+// 多個專案時各自使用一個快取檔案，例如 .qpr_cache.json -> .qpr_cache.backend.json
+pub fn project_cache_file(cache_file: &str, id: &str) -> String {
+    let path = Path::new(cache_file);
+    let file_name = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => format!("{}.{}.{}", stem.to_string_lossy(), id, ext.to_string_lossy()),
+        (Some(stem), None) => format!("{}.{}", stem.to_string_lossy(), id),
+        _ => return format!("{}.{}", cache_file, id),
+    };
+    path.with_file_name(file_name).to_string_lossy().to_string()
+}
@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+use crate::baseline::ChangeStatus;
+use crate::Directory;
+
+// ===========================
+// 摘要與檔名搜尋
+// ===========================
+
+// 摘要片段的長度（字元數）
+const SNIPPET_CHARS: usize = 80;
+// 檔名模糊比對允許的 Levenshtein 距離
+const FUZZY_MAX_DISTANCE: usize = 2;
+const DEFAULT_MAX_RESULTS: usize = 20;
+// /api/cross-references 最多回傳的符合行數
+const MAX_REFERENCES: usize = 1000;
+
+// POST /api/search 的請求內容
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub query: String,
+    #[serde(default = "default_fuzzy")]
+    pub fuzzy: bool,
+    #[serde(default = "default_max_results")]
+    pub max_results: usize,
+}
+
+fn default_fuzzy() -> bool {
+    true
+}
+
+fn default_max_results() -> usize {
+    DEFAULT_MAX_RESULTS
+}
+
+impl SearchQuery {
+    // GET /api/search?q= 使用預設選項
+    pub fn from_keyword(keyword: String) -> Self {
+        SearchQuery {
+            query: keyword,
+            fuzzy: default_fuzzy(),
+            max_results: default_max_results(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub path: String,
+    pub name: String,
+    pub snippet: String,
+}
+
+// 依符合程度排序：檔名 > 摘要 > 檔名模糊比對
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchKind {
+    Name,
+    Summary,
+    Fuzzy(usize),
+}
+
+// 在目錄樹的檔名與所有摘要中搜尋關鍵字（不分大小寫）
+pub fn search(project: &Directory, summaries: &BTreeMap<String, String>, query: &SearchQuery) -> Vec<SearchResult> {
+    let keyword = query.query.trim().to_lowercase();
+    if keyword.is_empty() {
+        return Vec::new();
+    }
+
+    // 目錄樹中的檔案，加上只存在於摘要中的路徑
+    let mut tree_files = Vec::new();
+    project.collect_all_files_with_metadata(&mut tree_files);
+    // 基準報告中已刪除的檔案不列入搜尋
+    let mut files: Vec<(String, String)> = tree_files
+        .into_iter()
+        .filter(|file| file.change_status != Some(ChangeStatus::Deleted))
+        .map(|file| (file.path, file.name))
+        .collect();
+    let known: HashSet<String> = files.iter().map(|(path, _)| path.clone()).collect();
+    for path in summaries.keys().filter(|path| !known.contains(*path)) {
+        let name = Path::new(path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.clone());
+        files.push((path.clone(), name));
+    }
+
+    let mut matches = Vec::new();
+    for (path, name) in files {
+        let summary = summaries.get(&path).map(String::as_str).unwrap_or("");
+        let lower_name = name.to_lowercase();
+        let summary_match = find_case_insensitive(summary, &keyword);
+
+        let kind = if lower_name.contains(&keyword) {
+            MatchKind::Name
+        } else if summary_match.is_some() {
+            MatchKind::Summary
+        } else if query.fuzzy {
+            // 同時與完整檔名及去掉副檔名的檔名比較
+            let stem = lower_name.rsplit_once('.').map_or(lower_name.as_str(), |(stem, _)| stem);
+            let distance = levenshtein(&keyword, &lower_name).min(levenshtein(&keyword, stem));
+            if distance > FUZZY_MAX_DISTANCE {
+                continue;
+            }
+            MatchKind::Fuzzy(distance)
+        } else {
+            continue;
+        };
+
+        let snippet = match summary_match {
+            Some((start, len)) => snippet_around(summary, start, len),
+            None => summary.chars().take(SNIPPET_CHARS).collect(),
+        };
+        matches.push((kind, SearchResult { path, name, snippet }));
+    }
+
+    matches.sort_by(|(a_kind, a), (b_kind, b)| a_kind.cmp(b_kind).then_with(|| a.path.cmp(&b.path)));
+    matches
+        .into_iter()
+        .take(query.max_results)
+        .map(|(_, result)| result)
+        .collect()
+}
+
+// GET /api/cross-references 回應中的一行符合結果，line 從 1 開始
+#[derive(Debug, Serialize)]
+pub struct Reference {
+    pub file_path: String,
+    pub line: usize,
+    pub context: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CrossReferences {
+    pub references: Vec<Reference>,
+    // 超過 MAX_REFERENCES 筆時只回傳前面的結果
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl CrossReferences {
+    // 逐行以純文字比對 name，將符合的行加入結果；已達上限時回傳 false
+    pub fn scan(&mut self, file_path: &str, content: &str, name: &str, case_sensitive: bool) -> bool {
+        for (index, line) in content.lines().enumerate() {
+            let found = if case_sensitive {
+                line.find(name).map(|byte| (line[..byte].chars().count(), name.chars().count()))
+            } else {
+                find_case_insensitive(line, name)
+            };
+            let Some((start, len)) = found else {
+                continue;
+            };
+            if self.references.len() >= MAX_REFERENCES {
+                self.truncated = true;
+                return false;
+            }
+            // 去掉縮排後取符合位置附近的片段
+            let trimmed = line.trim_start();
+            let indent = line.chars().count() - trimmed.chars().count();
+            self.references.push(Reference {
+                file_path: file_path.to_string(),
+                line: index + 1,
+                context: snippet_around(trimmed.trim_end(), start.saturating_sub(indent), len),
+            });
+        }
+        true
+    }
+}
+
+// 不分大小寫尋找關鍵字，回傳以字元計的起點與長度
+fn find_case_insensitive(text: &str, keyword: &str) -> Option<(usize, usize)> {
+    let chars: Vec<char> = text.chars().collect();
+    let keyword: Vec<char> = keyword.chars().collect();
+    if keyword.len() > chars.len() {
+        return None;
+    }
+    (0..=chars.len() - keyword.len())
+        .find(|&start| {
+            chars[start..start + keyword.len()]
+                .iter()
+                .zip(&keyword)
+                .all(|(c, k)| c.to_lowercase().eq(k.to_lowercase()))
+        })
+        .map(|start| (start, keyword.len()))
+}
+
+// 取出符合位置前後共約 80 個字元的片段
+fn snippet_around(text: &str, start: usize, len: usize) -> String {
+    let total = text.chars().count();
+    let context = SNIPPET_CHARS.saturating_sub(len) / 2;
+    let from = start.saturating_sub(context);
+    let to = (from + SNIPPET_CHARS).min(total);
+    let from = to.saturating_sub(SNIPPET_CHARS);
+    text.chars().skip(from).take(to - from).collect()
+}
+
+// 以字元計算的 Levenshtein 編輯距離
+fn levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            current[j + 1] = (previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
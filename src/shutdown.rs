@@ -0,0 +1,48 @@
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+// ===========================
+// 結束訊號（SIGINT / SIGTERM）
+// ===========================
+
+// 所有專案共用：收到結束訊號時取消 token，並等待背景摘要任務結束
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    pub token: CancellationToken,
+    // 摘要與重新掃描的背景任務，結束前等待其完成目前的 API 請求
+    pub tasks: TaskTracker,
+}
+
+impl Shutdown {
+    // 在背景等待 Ctrl+C 或 SIGTERM，收到時取消 token
+    pub fn listen(&self) {
+        let token = self.token.clone();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            token.cancel();
+        });
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let Ok(mut terminate) = signal(SignalKind::terminate()) else {
+        tokio::signal::ctrl_c().await.ok();
+        return;
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    tokio::signal::ctrl_c().await.ok();
+}
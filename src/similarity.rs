@@ -0,0 +1,123 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+// ===========================
+// 摘要相似度（/api/similar）
+// ===========================
+
+// 未指定 top_k 時回傳的檔案數
+pub const DEFAULT_TOP_K: usize = 5;
+
+#[derive(Debug, Serialize)]
+pub struct SimilarFile {
+    pub path: String,
+    pub score: f64,
+    pub summary: String,
+}
+
+// 以所有摘要建立的 TF-IDF 索引；每份摘要為一個正規化後的稀疏向量
+#[derive(Debug, Default)]
+pub struct SimilarityIndex {
+    // 檔案路徑 -> (詞 -> 權重, 摘要)
+    documents: BTreeMap<String, (HashMap<String, f64>, String)>,
+}
+
+impl SimilarityIndex {
+    pub fn build(summaries: &BTreeMap<String, String>) -> Self {
+        let tokenized: Vec<(&String, &String, Vec<String>)> = summaries
+            .iter()
+            .map(|(path, summary)| (path, summary, tokenize(summary)))
+            .collect();
+
+        // 每個詞出現在幾份摘要中
+        let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+        for (_, _, tokens) in &tokenized {
+            let mut seen: Vec<&str> = tokens.iter().map(String::as_str).collect();
+            seen.sort_unstable();
+            seen.dedup();
+            for token in seen {
+                *document_frequency.entry(token).or_default() += 1;
+            }
+        }
+
+        let total = tokenized.len() as f64;
+        let documents = tokenized
+            .iter()
+            .map(|(path, summary, tokens)| {
+                let mut vector: HashMap<String, f64> = HashMap::new();
+                for token in tokens {
+                    *vector.entry(token.clone()).or_default() += 1.0;
+                }
+                // 平滑的 idf，只出現在一份摘要中的詞權重最高
+                for (token, weight) in vector.iter_mut() {
+                    let df = document_frequency[token.as_str()] as f64;
+                    *weight = (*weight / tokens.len() as f64) * (((1.0 + total) / (1.0 + df)).ln() + 1.0);
+                }
+                let norm = vector.values().map(|w| w * w).sum::<f64>().sqrt();
+                if norm > 0.0 {
+                    vector.values_mut().for_each(|w| *w /= norm);
+                }
+                ((*path).clone(), (vector, (*summary).clone()))
+            })
+            .collect();
+        SimilarityIndex { documents }
+    }
+
+    // 與 path 的摘要最相似的 top_k 個檔案（餘弦相似度由高到低，不含自己與相似度為 0 的檔案）；
+    // path 不在索引中時回傳 None
+    pub fn similar(&self, path: &str, top_k: usize) -> Option<Vec<SimilarFile>> {
+        let (query, _) = self.documents.get(path)?;
+        let mut results: Vec<SimilarFile> = self
+            .documents
+            .iter()
+            .filter(|(other, _)| other.as_str() != path)
+            .map(|(other, (vector, summary))| SimilarFile {
+                path: other.clone(),
+                score: cosine(query, vector),
+                summary: summary.clone(),
+            })
+            .filter(|file| file.score > 0.0)
+            .collect();
+        results.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
+        results.truncate(top_k);
+        Some(results)
+    }
+}
+
+// 兩個已正規化向量的內積
+fn cosine(a: &HashMap<String, f64>, b: &HashMap<String, f64>) -> f64 {
+    let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    small
+        .iter()
+        .filter_map(|(token, weight)| large.get(token).map(|other| weight * other))
+        .sum()
+}
+
+// 英數字（含 `_`）以整個單字小寫計算；中文等其他文字沒有空白分隔，以相鄰兩字為一個詞
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut previous: Option<char> = None;
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            word.push(c.to_ascii_lowercase());
+            previous = None;
+            continue;
+        }
+        if !word.is_empty() {
+            tokens.push(std::mem::take(&mut word));
+        }
+        if c.is_alphanumeric() {
+            if let Some(previous) = previous {
+                tokens.push([previous, c].iter().collect());
+            }
+            previous = Some(c);
+        } else {
+            previous = None;
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
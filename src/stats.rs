@@ -0,0 +1,81 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::baseline::ChangeStatus;
+use crate::Directory;
+
+// ===========================
+// 專案統計（/api/stats）
+// ===========================
+
+// 列出的最大檔案數量
+const LARGEST_FILES_LIMIT: usize = 10;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ProjectStats {
+    pub total_files: usize,
+    pub total_lines_of_code: usize,
+    pub total_size_bytes: u64,
+    // 副檔名（小寫，不含 `.`）-> 檔案數，沒有副檔名的檔案計為空字串
+    pub files_per_extension: BTreeMap<String, usize>,
+    // (檔案路徑, bytes)，由大到小
+    pub largest_files: Vec<(String, u64)>,
+    // 摘要的平均字元數
+    pub average_summary_length: f64,
+    // 有摘要的檔案比例（0.0 ~ 1.0）
+    pub summarization_coverage: f64,
+}
+
+impl ProjectStats {
+    // line_counts 為摘要時讀取檔案內容所記錄的行數，不重新讀取磁碟
+    pub fn compute(
+        project: &Directory,
+        summaries: &BTreeMap<String, String>,
+        line_counts: &HashMap<String, usize>,
+    ) -> Self {
+        let mut files = Vec::new();
+        project.with_summaries(summaries).collect_all_files_with_metadata(&mut files);
+        files.retain(|file| file.change_status != Some(ChangeStatus::Deleted));
+
+        let mut files_per_extension = BTreeMap::new();
+        for file in &files {
+            *files_per_extension.entry(file.extension.clone()).or_insert(0) += 1;
+        }
+
+        let mut largest_files: Vec<(String, u64)> =
+            files.iter().map(|file| (file.path.clone(), file.size_bytes)).collect();
+        largest_files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        largest_files.truncate(LARGEST_FILES_LIMIT);
+
+        let summary_lengths: Vec<usize> = files
+            .iter()
+            .filter_map(|file| file.summary.as_ref())
+            .map(|summary| summary.chars().count())
+            .collect();
+        let average_summary_length = if summary_lengths.is_empty() {
+            0.0
+        } else {
+            summary_lengths.iter().sum::<usize>() as f64 / summary_lengths.len() as f64
+        };
+        let summarization_coverage = if files.is_empty() {
+            0.0
+        } else {
+            summary_lengths.len() as f64 / files.len() as f64
+        };
+
+        ProjectStats {
+            total_files: files.len(),
+            total_lines_of_code: files.iter().filter_map(|file| line_counts.get(&file.path)).sum(),
+            total_size_bytes: files.iter().map(|file| file.size_bytes).sum(),
+            files_per_extension,
+            largest_files,
+            average_summary_length,
+            summarization_coverage,
+        }
+    }
+}
+
+// 以 `\n` 的數量計算行數
+pub fn count_lines(content: &str) -> usize {
+    content.bytes().filter(|&b| b == b'\n').count()
+}
@@ -0,0 +1,187 @@
+use serde::{Serialize, Serializer};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, RwLock};
+
+// ===========================
+// 摘要時間軸（/api/timeline）
+// ===========================
+
+// 時間軸上的一個事件，用於找出拖慢分析的檔案與 API 呼叫
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TimelineEventKind {
+    SummarizationStarted { path: String },
+    SummarizationCompleted { path: String, duration_ms: u64 },
+    // status 為 None 表示沒有收到 HTTP 回應（例如連線失敗或逾時）；attempt 從 1 起算
+    ApiError { path: String, status: Option<u16>, attempt: u32 },
+    CacheHit { path: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineEvent {
+    // 輸出為 Unix 毫秒，與 ?since= 使用相同的單位
+    #[serde(serialize_with = "serialize_unix_ms")]
+    pub timestamp: SystemTime,
+    #[serde(flatten)]
+    pub event: TimelineEventKind,
+}
+
+impl TimelineEvent {
+    fn unix_ms(&self) -> u64 {
+        unix_ms(self.timestamp)
+    }
+}
+
+// 依發生順序保存的所有事件
+#[derive(Debug, Default)]
+pub struct AnalysisLog {
+    pub events: Vec<TimelineEvent>,
+}
+
+impl AnalysisLog {
+    // 最近 COMPLETION_WINDOW 個完成的檔案平均每個花費的秒數（含並行，為實際的處理速度而非單一請求的延遲）；
+    // 尚無完成的檔案時回傳 None
+    fn recent_secs_per_file(&self) -> Option<f64> {
+        let started = self.events.first()?.timestamp;
+        let completions: Vec<SystemTime> = self
+            .events
+            .iter()
+            .filter(|event| {
+                matches!(
+                    event.event,
+                    TimelineEventKind::SummarizationCompleted { .. } | TimelineEventKind::CacheHit { .. }
+                )
+            })
+            .map(|event| event.timestamp)
+            .collect();
+        let last = *completions.last()?;
+        let window = completions.len().min(COMPLETION_WINDOW);
+        // 以視窗前一個檔案的完成時間為起點；完成的檔案不足時從第一個事件起算
+        let window_start = match completions.len().checked_sub(window + 1) {
+            Some(index) => completions[index],
+            None => started,
+        };
+        let secs = last.duration_since(window_start).unwrap_or_default().as_secs_f64();
+        Some(secs / window as f64)
+    }
+}
+
+// ===========================
+// 預估完成時間（/api/completion-estimate）
+// ===========================
+
+// 只以最近完成的檔案估計速度，遇到速率限制等變化時能較快反映
+const COMPLETION_WINDOW: usize = 20;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionEstimate {
+    pub completed: usize,
+    pub total: usize,
+    // 從第一個摘要事件起算
+    pub elapsed_secs: u64,
+    // 尚無完成的檔案時以下三個欄位為 null
+    pub avg_latency_secs: Option<f64>,
+    pub estimated_remaining_secs: Option<u64>,
+    #[serde(serialize_with = "crate::serialize_rfc3339")]
+    pub estimated_completion_utc: Option<SystemTime>,
+}
+
+// 摘要任務只送出事件，由背景的寫入任務放進 AnalysisLog，記錄時不需要等待鎖
+#[derive(Clone)]
+pub struct Timeline {
+    tx: mpsc::UnboundedSender<TimelineEvent>,
+    log: Arc<RwLock<AnalysisLog>>,
+}
+
+impl Timeline {
+    // 建立時間軸並啟動寫入任務；需在 tokio runtime 中呼叫
+    pub fn spawn() -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel::<TimelineEvent>();
+        let log = Arc::new(RwLock::new(AnalysisLog::default()));
+        tokio::spawn({
+            let log = log.clone();
+            async move {
+                while let Some(event) = rx.recv().await {
+                    log.write().await.events.push(event);
+                }
+            }
+        });
+        Timeline { tx, log }
+    }
+
+    pub fn record(&self, event: TimelineEventKind) {
+        let _ = self.tx.send(TimelineEvent {
+            timestamp: SystemTime::now(),
+            event,
+        });
+    }
+
+    // 依最近完成的檔案估計剩餘時間；completed 與 total 來自 Progress
+    pub async fn completion_estimate(&self, completed: usize, total: usize) -> CompletionEstimate {
+        let log = self.log.read().await;
+        // 以整秒計算，預估的完成時間不帶小數秒
+        let now = UNIX_EPOCH + Duration::from_secs(unix_ms(SystemTime::now()) / 1000);
+        let elapsed_secs = log
+            .events
+            .first()
+            .and_then(|event| now.duration_since(event.timestamp).ok())
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let avg_latency_secs = log.recent_secs_per_file();
+        let estimated_remaining_secs =
+            avg_latency_secs.map(|secs| (secs * total.saturating_sub(completed) as f64).round() as u64);
+        CompletionEstimate {
+            completed,
+            total,
+            elapsed_secs,
+            avg_latency_secs,
+            estimated_remaining_secs,
+            estimated_completion_utc: estimated_remaining_secs.map(|secs| now + Duration::from_secs(secs)),
+        }
+    }
+
+    // 時間晚於 since（Unix 毫秒）的事件；未指定時回傳全部
+    pub async fn since(&self, since: Option<u64>) -> Vec<TimelineEvent> {
+        let log = self.log.read().await;
+        let start = match since {
+            Some(since) => log.events.partition_point(|event| event.unix_ms() <= since),
+            None => 0,
+        };
+        log.events[start..].to_vec()
+    }
+}
+
+tokio::task_local! {
+    // 目前摘要中的檔案，讓 LLM 後端的重試迴圈不必知道檔案路徑也能記錄 API 錯誤
+    static CURRENT_FILE: (Timeline, String);
+}
+
+// 在 future 執行期間將 API 錯誤歸屬到 path
+pub async fn scope<F: Future>(timeline: Timeline, path: String, future: F) -> F::Output {
+    CURRENT_FILE.scope((timeline, path), future).await
+}
+
+// 目前摘要中的檔案路徑；不在 scope 中時回傳 None
+pub fn current_path() -> Option<String> {
+    CURRENT_FILE.try_with(|(_, path)| path.clone()).ok()
+}
+
+// 記錄一次失敗的 API 請求；不在 scope 中（例如資料夾分析）時忽略
+pub fn record_api_error(status: Option<u16>, attempt: u32) {
+    let _ = CURRENT_FILE.try_with(|(timeline, path)| {
+        timeline.record(TimelineEventKind::ApiError {
+            path: path.clone(),
+            status,
+            attempt,
+        });
+    });
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn serialize_unix_ms<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(unix_ms(*time))
+}
@@ -0,0 +1,77 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use rustls_pemfile::Item;
+
+use crate::error::QprError;
+
+// ===========================
+// HTTPS 憑證
+// ===========================
+
+// 已驗證可讀取的憑證與私鑰路徑
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl TlsFiles {
+    // 兩者皆未指定時回傳 None（使用 HTTP），只指定其中一個時回傳錯誤
+    pub fn from_paths(cert: Option<&str>, key: Option<&str>) -> Result<Option<Self>, QprError> {
+        let (cert, key) = match (cert, key) {
+            (None, None) => return Ok(None),
+            (Some(cert), Some(key)) => (cert, key),
+            (Some(_), None) => {
+                return Err(QprError::ConfigError(
+                    "已指定 --tls-cert，但缺少 --tls-key；啟用 HTTPS 需同時提供憑證與私鑰".to_string(),
+                ))
+            }
+            (None, Some(_)) => {
+                return Err(QprError::ConfigError(
+                    "已指定 --tls-key，但缺少 --tls-cert；啟用 HTTPS 需同時提供憑證與私鑰".to_string(),
+                ))
+            }
+        };
+
+        let files = TlsFiles {
+            cert_path: PathBuf::from(cert),
+            key_path: PathBuf::from(key),
+        };
+        files.validate()?;
+        Ok(Some(files))
+    }
+
+    // 在綁定埠號與開始分析前先檢查檔案可讀取且為有效的 PEM 格式
+    fn validate(&self) -> Result<(), QprError> {
+        let certs = read_pem_items(&self.cert_path)?;
+        if !certs.iter().any(|item| matches!(item, Item::X509Certificate(_))) {
+            return Err(QprError::ConfigError(format!(
+                "憑證檔 {} 中找不到 PEM 格式的憑證（CERTIFICATE）",
+                self.cert_path.display()
+            )));
+        }
+
+        let keys = read_pem_items(&self.key_path)?;
+        if !keys
+            .iter()
+            .any(|item| matches!(item, Item::Pkcs1Key(_) | Item::Pkcs8Key(_) | Item::Sec1Key(_)))
+        {
+            return Err(QprError::ConfigError(format!(
+                "私鑰檔 {} 中找不到 PEM 格式的私鑰（支援 PKCS#1、PKCS#8、SEC1）",
+                self.key_path.display()
+            )));
+        }
+        Ok(())
+    }
+}
+
+// 讀取 PEM 檔中的所有區塊
+fn read_pem_items(path: &Path) -> Result<Vec<Item>, QprError> {
+    let file = File::open(path)
+        .map_err(|e| QprError::ConfigError(format!("無法讀取 {}：{}", path.display(), e)))?;
+    rustls_pemfile::read_all(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| QprError::ConfigError(format!("{} 不是有效的 PEM 檔案：{}", path.display(), e)))
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::error::QprError;
+use crate::ignore::IgnoreSet;
+use crate::{
+    regenerate_summary, run_blocking, AppState, Directory, DeleteEvent, ServerEvent, DEFAULT_IGNORE_PATTERNS,
+    WATCH_DEBOUNCE_MS,
+};
+
+// ===========================
+// 檔案變動監看（--watch）
+// ===========================
+
+// 監看選定的資料夾，檔案新增或修改後重新生成摘要，刪除時移除摘要；直到監看器失效才回傳
+pub async fn watch_project(state: AppState) -> Result<(), QprError> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    // notify 在自己的執行緒呼叫此 callback，因此以 channel 轉交給 async 迴圈
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+        if let Ok(event) = result {
+            let _ = tx.send(event);
+        }
+    })
+    .map_err(|e| QprError::WatchError(e.to_string()))?;
+
+    let root = PathBuf::from(&state.config.project_path);
+    let folders = state.folder_selection.read().await.filtered_folders.clone();
+    let mut watched = 0;
+    for folder in &folders {
+        let dir = if folder.trim() == "." { root.clone() } else { root.join(folder.trim()) };
+        if !dir.is_dir() {
+            continue;
+        }
+        watcher
+            .watch(&dir, RecursiveMode::Recursive)
+            .map_err(|e| QprError::WatchError(format!("{}：{}", dir.display(), e)))?;
+        tracing::info!("監看資料夾變動：{}", dir.display());
+        watched += 1;
+    }
+    if watched == 0 {
+        return Err(QprError::WatchError("選定的資料夾皆不存在，無法監看".to_string()));
+    }
+
+    // 每個檔案各自計時，連續修改多個檔案時不會合併成同一批
+    let mut pending: HashMap<PathBuf, JoinHandle<()>> = HashMap::new();
+    loop {
+        // 收到結束訊號時停止監看
+        let event = tokio::select! {
+            event = rx.recv() => event,
+            _ = state.shutdown.token.cancelled() => None,
+        };
+        let Some(event) = event else {
+            break;
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+            continue;
+        }
+        pending.retain(|_, task| !task.is_finished());
+        for path in event.paths {
+            if Directory::is_code_file(&path, &state.scan_options).is_none() {
+                continue;
+            }
+            if let Some(task) = pending.remove(&path) {
+                task.abort();
+            }
+            let task = tokio::spawn({
+                let state = state.clone();
+                let path = path.clone();
+                async move {
+                    tokio::time::sleep(Duration::from_millis(WATCH_DEBOUNCE_MS)).await;
+                    sync_file(&state, &path).await;
+                }
+            });
+            pending.insert(path, task);
+        }
+    }
+    Ok(())
+}
+
+// 依檔案目前是否存在，重新生成或移除摘要；與掃描相同，符合 .qprignore / .gitignore 規則的檔案不處理
+async fn sync_file(state: &AppState, path: &Path) {
+    let file_path = path.to_string_lossy().to_string();
+    let (metadata, ignored) = run_blocking({
+        let root = PathBuf::from(&state.config.project_path);
+        let path = path.to_path_buf();
+        move || {
+            let ignored = IgnoreSet::from_ancestors(&root, &path, DEFAULT_IGNORE_PATTERNS).is_ignored(&path, false);
+            (fs::metadata(path), ignored)
+        }
+    })
+    .await;
+    let Ok(metadata) = metadata else {
+        remove_file(state, &file_path).await;
+        return;
+    };
+    if !metadata.is_file() {
+        return;
+    }
+    if ignored {
+        tracing::debug!("檔案符合忽略規則，不生成摘要：{}", file_path);
+        return;
+    }
+    if metadata.len() > state.config.max_file_size {
+        tracing::warn!("檔案超過大小上限（{} bytes），略過摘要：{}", metadata.len(), file_path);
+        return;
+    }
+
+    // 只處理目錄樹中已存在的資料夾內的檔案（被忽略的資料夾不在樹中）
+    let is_new = {
+        let mut project = state.project.write().await;
+        if project.parent_dir_mut(path).is_none() {
+            return;
+        }
+        project.add_file(&file_path, metadata.len(), metadata.modified().ok())
+    };
+    if is_new {
+        let mut progress = state.progress.write().await;
+        progress.total_files += 1;
+        progress.completed_files += 1;
+    }
+
+    tracing::info!("偵測到檔案變動，重新生成摘要：{}", file_path);
+    if let Err(e) = regenerate_summary(state.clone(), file_path.clone(), None).await {
+        tracing::error!("摘要生成失敗：{}（{}）", file_path, e);
+        let mut progress = state.progress.write().await;
+        if !progress.failed_files.contains(&file_path) {
+            progress.failed_files.push(file_path);
+        }
+    }
+}
+
+// 檔案已刪除：從目錄樹與進度中移除，並通知 SSE 客戶端
+async fn remove_file(state: &AppState, file_path: &str) {
+    let removed = state.project.write().await.remove_file(file_path);
+    let had_summary = {
+        let mut progress = state.progress.write().await;
+        let had_summary = progress.summaries.remove(file_path).is_some();
+        progress.failed_files.retain(|path| path != file_path);
+        progress.skipped_large_files.retain(|path| path != file_path);
+        progress.budget_exceeded_files.retain(|path| path != file_path);
+        progress.encoding_lossy_files.retain(|path| path != file_path);
+        progress.content_hashes.remove(file_path);
+        progress.line_counts.remove(file_path);
+        progress.latency_ms.remove(file_path);
+        progress.project_stats = None;
+        if removed {
+            progress.total_files = progress.total_files.saturating_sub(1);
+            progress.completed_files = progress.completed_files.saturating_sub(1);
+        }
+        had_summary
+    };
+
+    if removed || had_summary {
+        let _ = state.events.send(ServerEvent::Delete(DeleteEvent {
+            file_path: file_path.to_string(),
+        }));
+        tracing::info!("檔案已刪除，移除摘要：{}", file_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{summarize_all, test_project};
+
+    #[tokio::test]
+    async fn sync_file_skips_ignored_files() {
+        let project = test_project(
+            &[
+                ("src/main.rs", "fn main() {}"),
+                (".qprignore", "*.gen.rs\n"),
+                ("src/.gitignore", "local.rs\n"),
+            ],
+            |_| {},
+            |mock| mock,
+        );
+        summarize_all(&project.state).await;
+        let calls_before = project.mock.calls().len();
+        let new_files = [
+            ("src/schema.gen.rs", "pub struct Schema;"),
+            ("src/local.rs", "pub fn local() {}"),
+            ("src/added.rs", "pub fn added() {}"),
+        ];
+        for (relative, content) in new_files {
+            fs::write(project.path(relative), content).unwrap();
+            sync_file(&project.state, Path::new(&project.path(relative))).await;
+        }
+
+        let calls = project.mock.calls();
+        assert_eq!(calls.len(), calls_before + 1);
+        assert!(calls[calls_before].prompt.contains("pub fn added() {}"));
+        let tree = project.state.project.read().await;
+        assert!(tree.find_file_by_path(&project.path("src/added.rs")).is_some());
+        assert!(tree.find_file_by_path(&project.path("src/schema.gen.rs")).is_none());
+        assert!(tree.find_file_by_path(&project.path("src/local.rs")).is_none());
+        let progress = project.state.progress.read().await;
+        assert_eq!(progress.total_files, 2);
+        assert!(!progress.summaries.contains_key(&project.path("src/local.rs")));
+    }
+}
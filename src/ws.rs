@@ -0,0 +1,182 @@
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use warp::ws::{Message, WebSocket};
+
+use crate::{
+    manual_select_folders, regenerate_summary, AnalysisPhase, AppState, DeleteEvent, ManualSelectRequest,
+    ServerEvent, SummaryEvent,
+};
+
+// ===========================
+// WebSocket（/api/ws）
+// ===========================
+
+// 串流摘要時尚未寫回連線的片段數上限，超過時生成端等待
+const SUMMARY_CHUNK_BUFFER: usize = 64;
+
+// 客戶端送出的訊息，以 type 區分
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    // 回傳目前進度，之後持續推送摘要完成與檔案刪除事件
+    SubscribeProgress,
+    RegenerateFile { file_path: String },
+    // 與 /api/folders/manual-select 相同：add 補充資料夾並重新分析，finalize 時開始生成摘要
+    SelectFolders(ManualSelectRequest),
+    Ping,
+}
+
+// 伺服器送出的訊息
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    ProgressUpdate {
+        phase: AnalysisPhase,
+        completed: usize,
+        total: usize,
+        total_tokens_used: u64,
+    },
+    FileDone(SummaryEvent),
+    // regenerate_file 生成中的摘要片段，依序串接即為目前為止的摘要
+    SummaryChunk { file_path: String, text: String },
+    FileDeleted(DeleteEvent),
+    FoldersSuggested { filtered_folders: Vec<String> },
+    Pong,
+    Error { error: String },
+}
+
+// 處理單一 WebSocket 連線；事件與 SSE 共用同一個 broadcast channel
+pub async fn handle_socket(state: AppState, socket: WebSocket) {
+    let (mut sink, mut stream) = socket.split();
+    // 各請求的處理與事件轉送都經由此 channel 寫回同一個連線
+    let (tx, mut rx) = mpsc::unbounded_channel::<ServerMessage>();
+    let writer = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            let Ok(text) = serde_json::to_string(&message) else {
+                continue;
+            };
+            if sink.send(Message::text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut subscription: Option<JoinHandle<()>> = None;
+    while let Some(Ok(message)) = stream.next().await {
+        if message.is_close() {
+            break;
+        }
+        let Ok(text) = message.to_str() else {
+            continue;
+        };
+        let request = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(request) => request,
+            Err(e) => {
+                let _ = tx.send(ServerMessage::Error {
+                    error: format!("無法解析訊息：{}", e),
+                });
+                continue;
+            }
+        };
+        match request {
+            ClientMessage::Ping => {
+                let _ = tx.send(ServerMessage::Pong);
+            }
+            ClientMessage::SubscribeProgress => {
+                if subscription.is_none() {
+                    // 先訂閱再取快照，避免兩者之間完成的檔案沒有被推送
+                    let events = state.events.subscribe();
+                    let _ = tx.send(progress_update(&state).await);
+                    subscription = Some(tokio::spawn(forward_events(events, tx.clone())));
+                }
+            }
+            ClientMessage::RegenerateFile { file_path } => {
+                // 已訂閱時完成事件會經由 broadcast 送達，不重複回覆
+                let subscribed = subscription.is_some();
+                let state = state.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    // 生成中的片段以 summary_chunk 即時送出；全部轉送後才回覆 file_done
+                    let (chunk_tx, chunk_rx) = mpsc::channel::<String>(SUMMARY_CHUNK_BUFFER);
+                    let (result, ()) = tokio::join!(
+                        regenerate_summary(state.clone(), file_path.clone(), Some(chunk_tx)),
+                        forward_chunks(chunk_rx, tx.clone(), file_path.clone()),
+                    );
+                    match result {
+                        Ok(summary) if !subscribed => {
+                            let progress = state.progress.read().await;
+                            let _ = tx.send(ServerMessage::FileDone(SummaryEvent {
+                                file_path,
+                                summary,
+                                completed: progress.completed_files,
+                                total: progress.total_files,
+                                total_tokens_used: progress.total_tokens_used,
+                            }));
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            let _ = tx.send(ServerMessage::Error { error: e.to_string() });
+                        }
+                    }
+                });
+            }
+            ClientMessage::SelectFolders(request) => {
+                let state = state.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let message = match manual_select_folders(state, request).await {
+                        Ok(filtered_folders) => ServerMessage::FoldersSuggested { filtered_folders },
+                        Err(e) => ServerMessage::Error { error: e.to_string() },
+                    };
+                    let _ = tx.send(message);
+                });
+            }
+        }
+    }
+
+    if let Some(subscription) = subscription {
+        subscription.abort();
+    }
+    drop(tx);
+    let _ = writer.await;
+}
+
+// 目前的分析階段與摘要進度
+async fn progress_update(state: &AppState) -> ServerMessage {
+    let phase = *state.phase.read().await;
+    let progress = state.progress.read().await;
+    ServerMessage::ProgressUpdate {
+        phase,
+        completed: progress.completed_files,
+        total: progress.total_files,
+        total_tokens_used: progress.total_tokens_used,
+    }
+}
+
+// 將串流摘要的片段轉送給客戶端，直到生成結束
+async fn forward_chunks(mut chunks: mpsc::Receiver<String>, tx: mpsc::UnboundedSender<ServerMessage>, file_path: String) {
+    while let Some(text) = chunks.recv().await {
+        let file_path = file_path.clone();
+        if tx.send(ServerMessage::SummaryChunk { file_path, text }).is_err() {
+            return;
+        }
+    }
+}
+
+// 將摘要完成與檔案刪除事件轉送給客戶端，直到連線關閉
+async fn forward_events(mut events: broadcast::Receiver<ServerEvent>, tx: mpsc::UnboundedSender<ServerMessage>) {
+    loop {
+        let message = match events.recv().await {
+            Ok(ServerEvent::Summary(event)) => ServerMessage::FileDone(event),
+            Ok(ServerEvent::Delete(event)) => ServerMessage::FileDeleted(event),
+            // 客戶端處理太慢時略過遺失的事件
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if tx.send(message).is_err() {
+            return;
+        }
+    }
+}